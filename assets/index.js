@@ -1,7 +1,11 @@
 const onButton = document.querySelector(".on")
 const offButton = document.querySelector(".off")
 
+// The device this page controls, other pages may control other devices.
 let connectedDevice = null;
+// Kept in sync with the server by the events from `/api/events`.
+const connected = new Set()
+const states = {}
 
 onButton.addEventListener("click", () => {
 	setLed({ event_type: "on" })
@@ -11,6 +15,10 @@ offButton.addEventListener("click", () => {
 	setLed({ event_type: "off" })
 })
 
+// Errors are answered with `{ error, message }`.
+const reportError = (res) =>
+	res.json().then(({ message }) => alert(message), () => alert(res.statusText))
+
 const setLed = (body) => {
 	if (!connectedDevice) return
 	fetch(`/api/set/${connectedDevice}`, {
@@ -19,6 +27,8 @@ const setLed = (body) => {
 		headers: {
 			"Content-Type": "application/json"
 		}
+	}).then(res => {
+		if (!res.ok) reportError(res)
 	})
 }
 
@@ -32,8 +42,13 @@ color.addEventListener("input", (_) => {
 	currentColor = color.value
 });
 
+const transition = document.querySelector(".transition")
+
+// Colors and brightness fade over the given time, when there is one.
+const transitionMs = () => +transition.value || undefined
+
 setColorButton.addEventListener("click", () => {
-	setLed({ event_type: "color", color: currentColor })
+	setLed({ event_type: "color", color: currentColor, transition_ms: transitionMs() })
 })
 
 const brightness = document.querySelector(".brightness");
@@ -46,26 +61,48 @@ brightness.addEventListener("input", (_) => {
 });
 
 brightnessButton.addEventListener("click", () => {
-	setLed({ event_type: "brightness", brightness: currentBrightness })
+	setLed({ event_type: "brightness", brightness: currentBrightness, transition_ms: transitionMs() })
+})
+
+const scene = document.querySelector(".scene");
+const sceneButton = document.querySelector(".set_scene");
+
+fetch("/api/scenes").then(res => res.json()).then(scenes => {
+	for (const { name } of scenes) {
+		let option = document.createElement("option")
+		option.value = name
+		option.textContent = name
+		scene.append(option)
+	}
+})
+
+sceneButton.addEventListener("click", () => {
+	setLed({ event_type: "scene", scene: scene.value })
 })
 
 const devicesList = document.querySelector(".devices_list")
 
 const createDevicesList = () => {
 	devicesList.innerHTML = ''
-	for (const device of devices) {
+	for (const { address: device, name, room } of devices) {
 		let li = document.createElement("li")
-		li.textContent = device
+		li.textContent = room ? `${name} (${room})` : name
+		if (connectedDevice === device) li.style.fontWeight = "bold"
+
+		if (connected.has(device) && connectedDevice !== device) {
+			let control = document.createElement("button")
+			control.textContent = "Control"
+			control.addEventListener("click", () => select(device))
+			li.appendChild(control)
+		}
 
 		let button = document.createElement("button")
-		button.textContent = connectedDevice === device ? "Disconnect" : "Connect"
+		button.textContent = connected.has(device) ? "Disconnect" : "Connect"
 		button.addEventListener("click", () => {
-			if (connectedDevice === device) {
-
+			if (connected.has(device)) {
 				disconnect(device)
 			} else {
 				connectTo(device)
-
 			}
 		})
 		li.appendChild(button)
@@ -76,12 +113,32 @@ const createDevicesList = () => {
 
 createDevicesList()
 
+// Shows the state of the controlled device on the inputs.
+const showState = () => {
+	const state = states[connectedDevice]
+	if (!state) return
+	if (state.brightness !== null) {
+		currentBrightness = state.brightness
+		brightness.value = state.brightness
+	}
+	if (state.mode && state.mode.mode === "color") {
+		currentColor = state.mode.value
+		color.value = state.mode.value
+	}
+}
+
+const select = (addr) => {
+	connectedDevice = addr
+	showState()
+	createDevicesList()
+}
+
 const connectTo = (addr) => {
 	fetch(`/api/connect/${addr}`, {
 		method: "POST",
 	}).then(res => {
-		connectedDevice = addr
-		createDevicesList()
+		if (!res.ok) return reportError(res)
+		select(addr)
 	})
 }
 
@@ -89,7 +146,28 @@ const disconnect = (addr) => {
 	fetch(`/api/disconnect/${addr}`, {
 		method: "POST",
 	}).then(res => {
-		connectedDevice = null
-		createDevicesList()
+		if (!res.ok) reportError(res)
 	})
-}
\ No newline at end of file
+}
+
+const events = new EventSource("/api/events")
+
+events.addEventListener("message", ({ data }) => {
+	const event = JSON.parse(data)
+	switch (event.type) {
+		case "connection":
+			if (event.status === "connected") {
+				connected.add(event.address)
+			} else if (event.status === "disconnected") {
+				connected.delete(event.address)
+				if (connectedDevice === event.address) connectedDevice = null
+			}
+			createDevicesList()
+			break
+		case "command":
+		case "state":
+			states[event.address] = event.state
+			if (event.address === connectedDevice) showState()
+			break
+	}
+})
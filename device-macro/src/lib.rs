@@ -3,18 +3,17 @@ use quote::quote;
 use syn::{
     braced, parenthesized,
     parse::{Parse, ParseStream, Result},
-    parse_macro_input, Ident, ImplItem, Token, Visibility,
+    parse_macro_input, Ident, Token, Visibility,
 };
 
 struct DevicesEnum {
     ident: Ident,
     devices: Vec<Ident>,
-    vis: Visibility,
 }
 
 impl Parse for DevicesEnum {
     fn parse(input: ParseStream) -> Result<Self> {
-        let vis: Visibility = input.parse()?;
+        let _vis: Visibility = input.parse()?;
         <Token![enum]>::parse(input)?;
         let ident: Ident = input.parse()?;
 
@@ -26,11 +25,7 @@ impl Parse for DevicesEnum {
             devices.push(<Device>::parse(&content)?.0);
         }
 
-        Ok(Self {
-            ident,
-            devices,
-            vis,
-        })
+        Ok(Self { ident, devices })
     }
 }
 
@@ -57,23 +52,41 @@ pub fn devices(input: TokenStream) -> TokenStream {
     quote! {
         #[async_trait::async_trait]
         impl crate::LedDevice for #ident {
-            async fn connect(&mut self) -> io::Result<()> {
+            async fn connect(&mut self) -> crate::Result<()> {
                 match self {
                     #(#ident::#devices(d) => d.connect().await,)*
                 }
             }
 
-            async fn disconnect(&mut self) -> io::Result<()> {
+            async fn disconnect(&mut self) -> crate::Result<()> {
                 match self {
                     #(#ident::#devices(d) => d.disconnect().await,)*
                 }
             }
 
-            async fn on_event(&mut self, event: crate::Event) -> io::Result<()> {
+            async fn on_event(&mut self, event: crate::Event) -> crate::Result<()> {
                 match self {
                     #(#ident::#devices(d) => d.on_event(event).await,)*
                 }
             }
+
+            async fn state(&mut self) -> crate::Result<crate::DeviceState> {
+                match self {
+                    #(#ident::#devices(d) => d.state().await,)*
+                }
+            }
+
+            fn bluetooth_device(&self) -> Option<bluer::Device> {
+                match self {
+                    #(#ident::#devices(d) => d.bluetooth_device(),)*
+                }
+            }
+
+            fn mark_disconnected(&mut self) {
+                match self {
+                    #(#ident::#devices(d) => d.mark_disconnected(),)*
+                }
+            }
         }
     }
     .into()
@@ -0,0 +1,58 @@
+use rand::Rng;
+use tokio::time::Duration;
+
+/// Exponentially growing delays between reconnect attempts, with random jitter so
+/// several strips that dropped at once don't all retry at the same moment.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    multiplier: f64,
+    jitter: f64,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            multiplier: 2.0,
+            jitter: 0.2,
+            attempt: 0,
+        }
+    }
+
+    /// Fraction of the delay that is randomly added or subtracted, `0.0..=1.0`.
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let exp = self.multiplier.powi(self.attempt.min(32) as i32);
+        let delay = (self.initial.as_secs_f64() * exp).min(self.max.as_secs_f64());
+        self.attempt = self.attempt.saturating_add(1);
+
+        let jitter = if self.jitter > 0.0 {
+            rand::thread_rng().gen_range(-self.jitter..=self.jitter)
+        } else {
+            0.0
+        };
+        Duration::from_secs_f64((delay * (1.0 + jitter)).max(0.0))
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1), Duration::from_secs(60))
+    }
+}
@@ -0,0 +1,743 @@
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+/// 8-bit RGB color, as it is sent to the led strips.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+/// Hue in degrees `[0, 360)`, saturation and value in `[0, 1]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hsv {
+    pub h: f32,
+    pub s: f32,
+    pub v: f32,
+}
+
+/// Hue in degrees `[0, 360)`, saturation and lightness in `[0, 1]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hsl {
+    pub h: f32,
+    pub s: f32,
+    pub l: f32,
+}
+
+/// [OKLab](https://bottosson.github.io/posts/oklab/), where equal distances look about equally
+/// different, so fades through it look even.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Oklab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+pub const MIN_KELVIN: u32 = 1000;
+pub const MAX_KELVIN: u32 = 40000;
+
+impl Color {
+    pub const BLACK: Color = Color::new(0, 0, 0);
+    pub const WHITE: Color = Color::new(255, 255, 255);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    pub fn from_hsv(hsv: Hsv) -> Self {
+        let c = hsv.v * hsv.s;
+        Self::from_chroma(hsv.h, c, hsv.v - c)
+    }
+
+    pub fn to_hsv(self) -> Hsv {
+        let (r, g, b) = self.unit();
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let s = if max == 0.0 { 0.0 } else { (max - min) / max };
+
+        Hsv {
+            h: self.hue(),
+            s,
+            v: max,
+        }
+    }
+
+    pub fn from_hsl(hsl: Hsl) -> Self {
+        let c = (1.0 - (2.0 * hsl.l - 1.0).abs()) * hsl.s;
+        Self::from_chroma(hsl.h, c, hsl.l - c / 2.0)
+    }
+
+    pub fn to_hsl(self) -> Hsl {
+        let (r, g, b) = self.unit();
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let l = (max + min) / 2.0;
+        let delta = max - min;
+        let s = if delta == 0.0 {
+            0.0
+        } else {
+            (delta / (1.0 - (2.0 * l - 1.0).abs())).min(1.0)
+        };
+
+        Hsl {
+            h: self.hue(),
+            s,
+            l,
+        }
+    }
+
+    /// Approximate color of a black body at `kelvin`, clamped to `MIN_KELVIN..=MAX_KELVIN`.
+    // https://tannerhelland.com/2012/09/18/convert-temperature-rgb-algorithm-code.html
+    pub fn from_kelvin(kelvin: u32) -> Self {
+        let temp = kelvin.clamp(MIN_KELVIN, MAX_KELVIN) as f32 / 100.0;
+
+        let r = if temp <= 66.0 {
+            255.0
+        } else {
+            329.69873 * (temp - 60.0).powf(-0.13320476)
+        };
+        let g = if temp <= 66.0 {
+            99.4708 * temp.ln() - 161.11957
+        } else {
+            288.12216 * (temp - 60.0).powf(-0.075514846)
+        };
+        let b = if temp >= 66.0 {
+            255.0
+        } else if temp <= 19.0 {
+            0.0
+        } else {
+            138.51773 * (temp - 10.0).ln() - 305.0448
+        };
+
+        Self::new(to_u8(r), to_u8(g), to_u8(b))
+    }
+
+    /// Dims the color, `factor` is clamped to `[0, 1]`.
+    pub fn scale(self, factor: f32) -> Self {
+        let factor = factor.clamp(0.0, 1.0);
+        let (r, g, b) = self.unit();
+        Self::new(
+            to_u8(r * factor * 255.0),
+            to_u8(g * factor * 255.0),
+            to_u8(b * factor * 255.0),
+        )
+    }
+
+    pub fn to_oklab(self) -> Oklab {
+        let (r, g, b) = self.unit();
+        let (r, g, b) = (to_linear(r), to_linear(g), to_linear(b));
+
+        let l = (0.41222147 * r + 0.53633254 * g + 0.05144599 * b).cbrt();
+        let m = (0.2119035 * r + 0.6806995 * g + 0.10739696 * b).cbrt();
+        let s = (0.08830246 * r + 0.28171884 * g + 0.6299787 * b).cbrt();
+
+        Oklab {
+            l: 0.21045426 * l + 0.7936178 * m - 0.00407205 * s,
+            a: 1.9779985 * l - 2.4285922 * m + 0.4505937 * s,
+            b: 0.02590404 * l + 0.78277177 * m - 0.80867577 * s,
+        }
+    }
+
+    /// The closest color, out of gamut colors are clamped.
+    pub fn from_oklab(lab: Oklab) -> Self {
+        let l = (lab.l + 0.39633778 * lab.a + 0.21580376 * lab.b).powi(3);
+        let m = (lab.l - 0.105561346 * lab.a - 0.06385417 * lab.b).powi(3);
+        let s = (lab.l - 0.08948418 * lab.a - 1.2914855 * lab.b).powi(3);
+
+        let r = 4.0767417 * l - 3.3077116 * m + 0.23096994 * s;
+        let g = -1.268438 * l + 2.6097574 * m - 0.34131938 * s;
+        let b = -0.0041960863 * l - 0.7034186 * m + 1.7076147 * s;
+
+        Self::new(
+            to_u8(from_linear(r) * 255.0),
+            to_u8(from_linear(g) * 255.0),
+            to_u8(from_linear(b) * 255.0),
+        )
+    }
+
+    /// Interpolates from `self` to `other` through OKLab, `t = 0` is `self` and `t = 1` is `other`.
+    pub fn mix_oklab(self, other: Color, t: f32) -> Self {
+        let t = t.clamp(0.0, 1.0);
+        let (from, to) = (self.to_oklab(), other.to_oklab());
+        Self::from_oklab(Oklab {
+            l: from.l + (to.l - from.l) * t,
+            a: from.a + (to.a - from.a) * t,
+            b: from.b + (to.b - from.b) * t,
+        })
+    }
+
+    /// Mixes the channels of `self` and `other`, `t = 0` is `self` and `t = 1` is `other`.
+    pub fn mix(self, other: Color, t: f32) -> Self {
+        let t = t.clamp(0.0, 1.0);
+        let channel = |a: u8, b: u8| to_u8(a as f32 + (b as f32 - a as f32) * t);
+        Self::new(
+            channel(self.r, other.r),
+            channel(self.g, other.g),
+            channel(self.b, other.b),
+        )
+    }
+
+    pub fn to_hex(self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+
+    fn unit(self) -> (f32, f32, f32) {
+        (
+            self.r as f32 / 255.0,
+            self.g as f32 / 255.0,
+            self.b as f32 / 255.0,
+        )
+    }
+
+    fn hue(self) -> f32 {
+        let (r, g, b) = self.unit();
+        let max = r.max(g).max(b);
+        let delta = max - r.min(g).min(b);
+
+        let h = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        h.rem_euclid(360.0)
+    }
+
+    fn from_chroma(h: f32, c: f32, m: f32) -> Self {
+        let h = h.rem_euclid(360.0) / 60.0;
+        let x = c * (1.0 - (h.rem_euclid(2.0) - 1.0).abs());
+
+        let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+
+        Self::new(
+            to_u8((r + m) * 255.0),
+            to_u8((g + m) * 255.0),
+            to_u8((b + m) * 255.0),
+        )
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseColorError {
+    Empty,
+    InvalidHex(String),
+    InvalidComponent(String),
+    KelvinOutOfRange(u32),
+    Unknown(String),
+}
+
+impl fmt::Display for ParseColorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseColorError::Empty => write!(f, "empty color"),
+            ParseColorError::InvalidHex(s) => {
+                write!(f, "invalid hex color {s:?}, expected #rgb or #rrggbb")
+            }
+            ParseColorError::InvalidComponent(s) => write!(f, "invalid color component {s:?}"),
+            ParseColorError::KelvinOutOfRange(k) => write!(
+                f,
+                "color temperature {k}K out of range {MIN_KELVIN}K..={MAX_KELVIN}K"
+            ),
+            ParseColorError::Unknown(s) => write!(f, "unknown color {s:?}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseColorError {}
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Color::from_str(&s).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(feature = "openapi")]
+impl<'s> utoipa::ToSchema<'s> for Color {
+    fn schema() -> (
+        &'s str,
+        utoipa::openapi::RefOr<utoipa::openapi::schema::Schema>,
+    ) {
+        let schema = utoipa::openapi::ObjectBuilder::new()
+            .schema_type(utoipa::openapi::SchemaType::String)
+            .description(Some(
+                "Written as `#rrggbb`, read as anything `FromStr` accepts: `#rgb`, `rgb(r, g, b)`, \
+                 `hsl(h, s%, l%)`, CSS color names or color temperatures like `2700K`.",
+            ));
+        ("Color", schema.into())
+    }
+}
+
+/// Parses `#rrggbb`, `#rgb`, `rgb(r, g, b)`, `hsl(h, s%, l%)`, CSS color names and
+/// color temperatures like `2700K`.
+impl FromStr for Color {
+    type Err = ParseColorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        if s.is_empty() {
+            return Err(ParseColorError::Empty);
+        }
+
+        if let Some(hex) = s.strip_prefix('#') {
+            return parse_hex(hex).ok_or(ParseColorError::InvalidHex(s.clone()));
+        }
+
+        if let Some(args) = function_args(&s, "rgb") {
+            let [r, g, b] = args?;
+            return Ok(Self::new(
+                parse_rgb_component(r)?,
+                parse_rgb_component(g)?,
+                parse_rgb_component(b)?,
+            ));
+        }
+
+        if let Some(args) = function_args(&s, "hsl") {
+            let [h, sat, l] = args?;
+            return Ok(Self::from_hsl(Hsl {
+                h: parse_hue(h)?,
+                s: parse_fraction(sat)?,
+                l: parse_fraction(l)?,
+            }));
+        }
+
+        if let Some(kelvin) = s.strip_suffix('k') {
+            if let Ok(kelvin) = kelvin.trim().parse::<u32>() {
+                if !(MIN_KELVIN..=MAX_KELVIN).contains(&kelvin) {
+                    return Err(ParseColorError::KelvinOutOfRange(kelvin));
+                }
+                return Ok(Self::from_kelvin(kelvin));
+            }
+        }
+
+        NAMED_COLORS
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, color)| *color)
+            .ok_or(ParseColorError::Unknown(s))
+    }
+}
+
+/// sRGB transfer function, from encoded to linear light.
+fn to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn from_linear(c: f32) -> f32 {
+    let c = c.clamp(0.0, 1.0);
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn to_u8(v: f32) -> u8 {
+    v.round().clamp(0.0, 255.0) as u8
+}
+
+fn parse_hex(hex: &str) -> Option<Color> {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let channel = |s: &str| u8::from_str_radix(s, 16).ok();
+    match hex.len() {
+        3 => {
+            let short = |i: usize| channel(&hex[i..i + 1]).map(|v| v * 17);
+            Some(Color::new(short(0)?, short(1)?, short(2)?))
+        }
+        6 => Some(Color::new(
+            channel(&hex[0..2])?,
+            channel(&hex[2..4])?,
+            channel(&hex[4..6])?,
+        )),
+        _ => None,
+    }
+}
+
+/// Splits `name(a, b, c)` (or `name(a b c)`) into its three arguments.
+fn function_args<'a>(s: &'a str, name: &str) -> Option<Result<[&'a str; 3], ParseColorError>> {
+    let inner = s.strip_prefix(name)?.trim_start().strip_prefix('(')?;
+    let Some(inner) = inner.strip_suffix(')') else {
+        return Some(Err(ParseColorError::InvalidComponent(s.to_string())));
+    };
+
+    let args = inner
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|a| !a.is_empty())
+        .collect::<Vec<_>>();
+
+    Some(
+        args.try_into()
+            .map_err(|_| ParseColorError::InvalidComponent(s.to_string())),
+    )
+}
+
+fn parse_number(s: &str) -> Result<f32, ParseColorError> {
+    s.parse::<f32>()
+        .ok()
+        .filter(|v| v.is_finite())
+        .ok_or_else(|| ParseColorError::InvalidComponent(s.to_string()))
+}
+
+fn parse_rgb_component(s: &str) -> Result<u8, ParseColorError> {
+    let v = match s.strip_suffix('%') {
+        Some(pct) => parse_number(pct)? * 2.55,
+        None => parse_number(s)?,
+    };
+
+    if !(0.0..=255.0).contains(&v) {
+        return Err(ParseColorError::InvalidComponent(s.to_string()));
+    }
+    Ok(to_u8(v))
+}
+
+fn parse_hue(s: &str) -> Result<f32, ParseColorError> {
+    Ok(parse_number(s.strip_suffix("deg").unwrap_or(s))?.rem_euclid(360.0))
+}
+
+fn parse_fraction(s: &str) -> Result<f32, ParseColorError> {
+    let v = parse_number(s.strip_suffix('%').unwrap_or(s))?;
+    if !(0.0..=100.0).contains(&v) {
+        return Err(ParseColorError::InvalidComponent(s.to_string()));
+    }
+    Ok(v / 100.0)
+}
+
+// https://www.w3.org/TR/css-color-4/#named-colors
+const NAMED_COLORS: &[(&str, Color)] = &[
+    ("aliceblue", Color::new(0xf0, 0xf8, 0xff)),
+    ("antiquewhite", Color::new(0xfa, 0xeb, 0xd7)),
+    ("aqua", Color::new(0x00, 0xff, 0xff)),
+    ("aquamarine", Color::new(0x7f, 0xff, 0xd4)),
+    ("azure", Color::new(0xf0, 0xff, 0xff)),
+    ("beige", Color::new(0xf5, 0xf5, 0xdc)),
+    ("bisque", Color::new(0xff, 0xe4, 0xc4)),
+    ("black", Color::new(0x00, 0x00, 0x00)),
+    ("blanchedalmond", Color::new(0xff, 0xeb, 0xcd)),
+    ("blue", Color::new(0x00, 0x00, 0xff)),
+    ("blueviolet", Color::new(0x8a, 0x2b, 0xe2)),
+    ("brown", Color::new(0xa5, 0x2a, 0x2a)),
+    ("burlywood", Color::new(0xde, 0xb8, 0x87)),
+    ("cadetblue", Color::new(0x5f, 0x9e, 0xa0)),
+    ("chartreuse", Color::new(0x7f, 0xff, 0x00)),
+    ("chocolate", Color::new(0xd2, 0x69, 0x1e)),
+    ("coral", Color::new(0xff, 0x7f, 0x50)),
+    ("cornflowerblue", Color::new(0x64, 0x95, 0xed)),
+    ("cornsilk", Color::new(0xff, 0xf8, 0xdc)),
+    ("crimson", Color::new(0xdc, 0x14, 0x3c)),
+    ("cyan", Color::new(0x00, 0xff, 0xff)),
+    ("darkblue", Color::new(0x00, 0x00, 0x8b)),
+    ("darkcyan", Color::new(0x00, 0x8b, 0x8b)),
+    ("darkgoldenrod", Color::new(0xb8, 0x86, 0x0b)),
+    ("darkgray", Color::new(0xa9, 0xa9, 0xa9)),
+    ("darkgreen", Color::new(0x00, 0x64, 0x00)),
+    ("darkgrey", Color::new(0xa9, 0xa9, 0xa9)),
+    ("darkkhaki", Color::new(0xbd, 0xb7, 0x6b)),
+    ("darkmagenta", Color::new(0x8b, 0x00, 0x8b)),
+    ("darkolivegreen", Color::new(0x55, 0x6b, 0x2f)),
+    ("darkorange", Color::new(0xff, 0x8c, 0x00)),
+    ("darkorchid", Color::new(0x99, 0x32, 0xcc)),
+    ("darkred", Color::new(0x8b, 0x00, 0x00)),
+    ("darksalmon", Color::new(0xe9, 0x96, 0x7a)),
+    ("darkseagreen", Color::new(0x8f, 0xbc, 0x8f)),
+    ("darkslateblue", Color::new(0x48, 0x3d, 0x8b)),
+    ("darkslategray", Color::new(0x2f, 0x4f, 0x4f)),
+    ("darkslategrey", Color::new(0x2f, 0x4f, 0x4f)),
+    ("darkturquoise", Color::new(0x00, 0xce, 0xd1)),
+    ("darkviolet", Color::new(0x94, 0x00, 0xd3)),
+    ("deeppink", Color::new(0xff, 0x14, 0x93)),
+    ("deepskyblue", Color::new(0x00, 0xbf, 0xff)),
+    ("dimgray", Color::new(0x69, 0x69, 0x69)),
+    ("dimgrey", Color::new(0x69, 0x69, 0x69)),
+    ("dodgerblue", Color::new(0x1e, 0x90, 0xff)),
+    ("firebrick", Color::new(0xb2, 0x22, 0x22)),
+    ("floralwhite", Color::new(0xff, 0xfa, 0xf0)),
+    ("forestgreen", Color::new(0x22, 0x8b, 0x22)),
+    ("fuchsia", Color::new(0xff, 0x00, 0xff)),
+    ("gainsboro", Color::new(0xdc, 0xdc, 0xdc)),
+    ("ghostwhite", Color::new(0xf8, 0xf8, 0xff)),
+    ("gold", Color::new(0xff, 0xd7, 0x00)),
+    ("goldenrod", Color::new(0xda, 0xa5, 0x20)),
+    ("gray", Color::new(0x80, 0x80, 0x80)),
+    ("green", Color::new(0x00, 0x80, 0x00)),
+    ("greenyellow", Color::new(0xad, 0xff, 0x2f)),
+    ("grey", Color::new(0x80, 0x80, 0x80)),
+    ("honeydew", Color::new(0xf0, 0xff, 0xf0)),
+    ("hotpink", Color::new(0xff, 0x69, 0xb4)),
+    ("indianred", Color::new(0xcd, 0x5c, 0x5c)),
+    ("indigo", Color::new(0x4b, 0x00, 0x82)),
+    ("ivory", Color::new(0xff, 0xff, 0xf0)),
+    ("khaki", Color::new(0xf0, 0xe6, 0x8c)),
+    ("lavender", Color::new(0xe6, 0xe6, 0xfa)),
+    ("lavenderblush", Color::new(0xff, 0xf0, 0xf5)),
+    ("lawngreen", Color::new(0x7c, 0xfc, 0x00)),
+    ("lemonchiffon", Color::new(0xff, 0xfa, 0xcd)),
+    ("lightblue", Color::new(0xad, 0xd8, 0xe6)),
+    ("lightcoral", Color::new(0xf0, 0x80, 0x80)),
+    ("lightcyan", Color::new(0xe0, 0xff, 0xff)),
+    ("lightgoldenrodyellow", Color::new(0xfa, 0xfa, 0xd2)),
+    ("lightgray", Color::new(0xd3, 0xd3, 0xd3)),
+    ("lightgreen", Color::new(0x90, 0xee, 0x90)),
+    ("lightgrey", Color::new(0xd3, 0xd3, 0xd3)),
+    ("lightpink", Color::new(0xff, 0xb6, 0xc1)),
+    ("lightsalmon", Color::new(0xff, 0xa0, 0x7a)),
+    ("lightseagreen", Color::new(0x20, 0xb2, 0xaa)),
+    ("lightskyblue", Color::new(0x87, 0xce, 0xfa)),
+    ("lightslategray", Color::new(0x77, 0x88, 0x99)),
+    ("lightslategrey", Color::new(0x77, 0x88, 0x99)),
+    ("lightsteelblue", Color::new(0xb0, 0xc4, 0xde)),
+    ("lightyellow", Color::new(0xff, 0xff, 0xe0)),
+    ("lime", Color::new(0x00, 0xff, 0x00)),
+    ("limegreen", Color::new(0x32, 0xcd, 0x32)),
+    ("linen", Color::new(0xfa, 0xf0, 0xe6)),
+    ("magenta", Color::new(0xff, 0x00, 0xff)),
+    ("maroon", Color::new(0x80, 0x00, 0x00)),
+    ("mediumaquamarine", Color::new(0x66, 0xcd, 0xaa)),
+    ("mediumblue", Color::new(0x00, 0x00, 0xcd)),
+    ("mediumorchid", Color::new(0xba, 0x55, 0xd3)),
+    ("mediumpurple", Color::new(0x93, 0x70, 0xdb)),
+    ("mediumseagreen", Color::new(0x3c, 0xb3, 0x71)),
+    ("mediumslateblue", Color::new(0x7b, 0x68, 0xee)),
+    ("mediumspringgreen", Color::new(0x00, 0xfa, 0x9a)),
+    ("mediumturquoise", Color::new(0x48, 0xd1, 0xcc)),
+    ("mediumvioletred", Color::new(0xc7, 0x15, 0x85)),
+    ("midnightblue", Color::new(0x19, 0x19, 0x70)),
+    ("mintcream", Color::new(0xf5, 0xff, 0xfa)),
+    ("mistyrose", Color::new(0xff, 0xe4, 0xe1)),
+    ("moccasin", Color::new(0xff, 0xe4, 0xb5)),
+    ("navajowhite", Color::new(0xff, 0xde, 0xad)),
+    ("navy", Color::new(0x00, 0x00, 0x80)),
+    ("oldlace", Color::new(0xfd, 0xf5, 0xe6)),
+    ("olive", Color::new(0x80, 0x80, 0x00)),
+    ("olivedrab", Color::new(0x6b, 0x8e, 0x23)),
+    ("orange", Color::new(0xff, 0xa5, 0x00)),
+    ("orangered", Color::new(0xff, 0x45, 0x00)),
+    ("orchid", Color::new(0xda, 0x70, 0xd6)),
+    ("palegoldenrod", Color::new(0xee, 0xe8, 0xaa)),
+    ("palegreen", Color::new(0x98, 0xfb, 0x98)),
+    ("paleturquoise", Color::new(0xaf, 0xee, 0xee)),
+    ("palevioletred", Color::new(0xdb, 0x70, 0x93)),
+    ("papayawhip", Color::new(0xff, 0xef, 0xd5)),
+    ("peachpuff", Color::new(0xff, 0xda, 0xb9)),
+    ("peru", Color::new(0xcd, 0x85, 0x3f)),
+    ("pink", Color::new(0xff, 0xc0, 0xcb)),
+    ("plum", Color::new(0xdd, 0xa0, 0xdd)),
+    ("powderblue", Color::new(0xb0, 0xe0, 0xe6)),
+    ("purple", Color::new(0x80, 0x00, 0x80)),
+    ("rebeccapurple", Color::new(0x66, 0x33, 0x99)),
+    ("red", Color::new(0xff, 0x00, 0x00)),
+    ("rosybrown", Color::new(0xbc, 0x8f, 0x8f)),
+    ("royalblue", Color::new(0x41, 0x69, 0xe1)),
+    ("saddlebrown", Color::new(0x8b, 0x45, 0x13)),
+    ("salmon", Color::new(0xfa, 0x80, 0x72)),
+    ("sandybrown", Color::new(0xf4, 0xa4, 0x60)),
+    ("seagreen", Color::new(0x2e, 0x8b, 0x57)),
+    ("seashell", Color::new(0xff, 0xf5, 0xee)),
+    ("sienna", Color::new(0xa0, 0x52, 0x2d)),
+    ("silver", Color::new(0xc0, 0xc0, 0xc0)),
+    ("skyblue", Color::new(0x87, 0xce, 0xeb)),
+    ("slateblue", Color::new(0x6a, 0x5a, 0xcd)),
+    ("slategray", Color::new(0x70, 0x80, 0x90)),
+    ("slategrey", Color::new(0x70, 0x80, 0x90)),
+    ("snow", Color::new(0xff, 0xfa, 0xfa)),
+    ("springgreen", Color::new(0x00, 0xff, 0x7f)),
+    ("steelblue", Color::new(0x46, 0x82, 0xb4)),
+    ("tan", Color::new(0xd2, 0xb4, 0x8c)),
+    ("teal", Color::new(0x00, 0x80, 0x80)),
+    ("thistle", Color::new(0xd8, 0xbf, 0xd8)),
+    ("tomato", Color::new(0xff, 0x63, 0x47)),
+    ("turquoise", Color::new(0x40, 0xe0, 0xd0)),
+    ("violet", Color::new(0xee, 0x82, 0xee)),
+    ("wheat", Color::new(0xf5, 0xde, 0xb3)),
+    ("white", Color::new(0xff, 0xff, 0xff)),
+    ("whitesmoke", Color::new(0xf5, 0xf5, 0xf5)),
+    ("yellow", Color::new(0xff, 0xff, 0x00)),
+    ("yellowgreen", Color::new(0x9a, 0xcd, 0x32)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<Color, ParseColorError> {
+        s.parse()
+    }
+
+    #[test]
+    fn parses_hex() {
+        assert_eq!(parse("#ff8800"), Ok(Color::new(0xff, 0x88, 0x00)));
+        assert_eq!(parse("#FF8800"), Ok(Color::new(0xff, 0x88, 0x00)));
+        assert_eq!(parse("#f80"), Ok(Color::new(0xff, 0x88, 0x00)));
+        assert_eq!(parse("#fff"), Ok(Color::WHITE));
+        assert_eq!(parse("  #000000 "), Ok(Color::BLACK));
+    }
+
+    #[test]
+    fn parses_rgb() {
+        assert_eq!(parse("rgb(255, 136, 0)"), Ok(Color::new(255, 136, 0)));
+        assert_eq!(parse("rgb(255 136 0)"), Ok(Color::new(255, 136, 0)));
+        assert_eq!(parse("RGB(100%, 0%, 50%)"), Ok(Color::new(255, 0, 128)));
+    }
+
+    #[test]
+    fn parses_hsl() {
+        assert_eq!(parse("hsl(0, 100%, 50%)"), Ok(Color::new(255, 0, 0)));
+        assert_eq!(parse("hsl(120deg 100% 25%)"), Ok(Color::new(0, 128, 0)));
+        assert_eq!(parse("hsl(-120, 100%, 50%)"), Ok(Color::new(0, 0, 255)));
+        assert_eq!(parse("hsl(0, 0%, 100%)"), Ok(Color::WHITE));
+    }
+
+    #[test]
+    fn parses_names() {
+        assert_eq!(parse("red"), Ok(Color::new(255, 0, 0)));
+        assert_eq!(parse("RebeccaPurple"), Ok(Color::new(0x66, 0x33, 0x99)));
+        assert_eq!(parse("grey"), parse("gray"));
+    }
+
+    #[test]
+    fn parses_kelvin() {
+        assert_eq!(parse("2700K"), Ok(Color::new(255, 167, 87)));
+        assert_eq!(parse("2700 k"), Ok(Color::new(255, 167, 87)));
+        assert_eq!(parse("6600k"), Ok(Color::WHITE));
+        assert_eq!(parse("1000k"), Ok(Color::new(255, 68, 0)));
+    }
+
+    #[test]
+    fn rejects_invalid_colors() {
+        assert_eq!(parse(""), Err(ParseColorError::Empty));
+        assert_eq!(parse("   "), Err(ParseColorError::Empty));
+        for hex in ["#", "#f", "#ff", "#ffff", "#fffffff", "#ggg", "#ééé"] {
+            assert_eq!(
+                parse(hex),
+                Err(ParseColorError::InvalidHex(hex.to_lowercase())),
+                "{hex}"
+            );
+        }
+        for s in [
+            "rgb(1, 2)",
+            "rgb(1, 2, 3, 4)",
+            "rgb(1, 2, 3",
+            "rgb(256, 0, 0)",
+            "rgb(-1, 0, 0)",
+            "rgb(a, b, c)",
+            "hsl(0, 101%, 50%)",
+            "hsl(nan, 0%, 0%)",
+        ] {
+            assert!(
+                matches!(parse(s), Err(ParseColorError::InvalidComponent(_))),
+                "{s}"
+            );
+        }
+        assert_eq!(parse("999k"), Err(ParseColorError::KelvinOutOfRange(999)));
+        assert_eq!(
+            parse("40001K"),
+            Err(ParseColorError::KelvinOutOfRange(40001))
+        );
+        for s in ["r", "k", "redd", "rgb", "2700", "0xff0000"] {
+            assert_eq!(
+                parse(s),
+                Err(ParseColorError::Unknown(s.to_string())),
+                "{s}"
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips_hsv() {
+        for color in [
+            Color::new(255, 136, 0),
+            Color::new(18, 52, 86),
+            Color::new(200, 200, 200),
+            Color::BLACK,
+            Color::WHITE,
+        ] {
+            assert_eq!(Color::from_hsv(color.to_hsv()), color);
+        }
+
+        let hsv = Color::new(255, 0, 0).to_hsv();
+        assert_eq!((hsv.h, hsv.s, hsv.v), (0.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn round_trips_hsl() {
+        for color in [
+            Color::new(255, 136, 0),
+            Color::new(18, 52, 86),
+            Color::new(200, 200, 200),
+            Color::BLACK,
+            Color::WHITE,
+        ] {
+            assert_eq!(Color::from_hsl(color.to_hsl()), color);
+        }
+
+        let hsl = Color::new(0, 0, 255).to_hsl();
+        assert_eq!((hsl.h, hsl.s, hsl.l), (240.0, 1.0, 0.5));
+    }
+
+    #[test]
+    fn round_trips_oklab() {
+        for r in (0..=255).step_by(15) {
+            for g in (0..=255).step_by(15) {
+                for b in (0..=255).step_by(15) {
+                    let color = Color::new(r, g, b);
+                    assert_eq!(Color::from_oklab(color.to_oklab()), color);
+                }
+            }
+        }
+
+        let white = Color::WHITE.to_oklab();
+        assert!((white.l - 1.0).abs() < 1e-3, "{:?}", white);
+        assert!(white.a.abs() < 1e-3 && white.b.abs() < 1e-3, "{:?}", white);
+        assert_eq!(Color::BLACK.to_oklab().l, 0.0);
+    }
+
+    #[test]
+    fn mixes_oklab_between_the_inputs() {
+        let (orange, teal) = (Color::new(255, 136, 0), Color::new(0, 128, 128));
+        assert_eq!(orange.mix_oklab(teal, 0.0), orange);
+        assert_eq!(orange.mix_oklab(teal, 1.0), teal);
+        assert_eq!(orange.mix_oklab(teal, -1.0), orange);
+        assert_eq!(orange.mix_oklab(teal, 2.0), teal);
+        assert_eq!(Color::BLACK.mix_oklab(Color::WHITE, 1.0), Color::WHITE);
+
+        // Halfway in lightness, not in the channels.
+        let middle = Color::BLACK.mix_oklab(Color::WHITE, 0.5).to_oklab();
+        assert!((middle.l - 0.5).abs() < 0.01, "{:?}", middle);
+    }
+
+    #[test]
+    fn displays_as_hex() {
+        let color = Color::new(0xff, 0x50, 0x00);
+        assert_eq!(color.to_string(), "#ff5000");
+        assert_eq!(parse(&color.to_string()), Ok(color));
+    }
+}
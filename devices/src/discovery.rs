@@ -0,0 +1,228 @@
+use bluer::{AdapterEvent, Address, AddressType, Device, Uuid};
+use futures::{pin_mut, StreamExt};
+use log::{debug, info};
+use serde::Serialize;
+use std::collections::HashSet;
+use tokio::time::{self, Duration};
+
+use crate::{adapter, esp, DeviceKind};
+
+/// Names Govee strips speaking [`crate::govee::protocol`] advertise, followed by part of their address.
+pub const GOVEE_NAME_PREFIXES: &[&str] = &["ihoment_H6127_"];
+
+/// Which kind of supported strip advertised `name` and `uuids`, if any.
+pub fn classify(name: Option<&str>, uuids: &[Uuid]) -> Option<DeviceKind> {
+    if uuids.contains(&esp::SERVICE_UUID) {
+        return Some(DeviceKind::Esp);
+    }
+    match name {
+        Some(name) if GOVEE_NAME_PREFIXES.iter().any(|p| name.starts_with(p)) => {
+            Some(DeviceKind::Govee)
+        }
+        _ => None,
+    }
+}
+
+/// Bounds a discovery, and which devices it reports.
+///
+/// bluer sets its own BlueZ discovery filter when it starts discovering, so `service_uuids` and
+/// `rssi` are applied to the discovered devices here, with the same semantics as BlueZ.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveryOptions {
+    pub timeout: Duration,
+    /// Only report devices advertising any of these, empty to report all.
+    pub service_uuids: HashSet<Uuid>,
+    /// Only report devices received stronger than this threshold, in dBm.
+    pub rssi: Option<i16>,
+}
+
+impl Default for DiscoveryOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+            service_uuids: HashSet::new(),
+            rssi: None,
+        }
+    }
+}
+
+impl DiscoveryOptions {
+    pub fn matches(&self, device: &ScannedDevice) -> bool {
+        let rssi_matches = match (self.rssi, device.rssi) {
+            (None, _) => true,
+            (Some(threshold), Some(rssi)) => rssi >= threshold,
+            (Some(_), None) => false,
+        };
+        let uuids_match = self.service_uuids.is_empty()
+            || device
+                .uuids
+                .iter()
+                .any(|uuid| self.service_uuids.contains(uuid));
+
+        rssi_matches && uuids_match
+    }
+}
+
+/// A device seen while scanning, with what it advertised.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ScannedDevice {
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    pub address: Address,
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    pub address_type: AddressType,
+    pub name: Option<String>,
+    pub rssi: Option<i16>,
+    pub uuids: Vec<Uuid>,
+    /// The kind of strip, when it is one we support.
+    pub kind: Option<DeviceKind>,
+}
+
+impl ScannedDevice {
+    pub async fn read(device: &Device) -> bluer::Result<Self> {
+        let mut uuids = device
+            .uuids()
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .collect::<Vec<_>>();
+        uuids.sort();
+        let name = device.name().await?;
+
+        Ok(Self {
+            address: device.address(),
+            address_type: device.address_type().await?,
+            kind: classify(name.as_deref(), &uuids),
+            name,
+            rssi: device.rssi().await?,
+            uuids,
+        })
+    }
+}
+
+/// Discovers on adapter `adapter_name` for `options.timeout` and returns the devices that
+/// match `options`, strongest signal first.
+pub async fn scan(
+    adapter_name: Option<&str>,
+    options: &DiscoveryOptions,
+) -> bluer::Result<Vec<ScannedDevice>> {
+    let adapter = adapter(adapter_name).await?;
+    adapter.set_powered(true).await?;
+    info!(
+        "Scanning on Bluetooth adapter {} for {:?}",
+        adapter.name(),
+        options.timeout
+    );
+
+    let discover = adapter.discover_devices().await?;
+    pin_mut!(discover);
+
+    let mut addresses = Vec::new();
+    let _ = time::timeout(options.timeout, async {
+        while let Some(evt) = discover.next().await {
+            if let AdapterEvent::DeviceAdded(addr) = evt {
+                if !addresses.contains(&addr) {
+                    addresses.push(addr);
+                }
+            }
+        }
+    })
+    .await;
+
+    let mut devices = Vec::new();
+    for addr in addresses {
+        // Devices can disappear between being discovered and being read.
+        match ScannedDevice::read(&adapter.device(addr)?).await {
+            Ok(device) if options.matches(&device) => devices.push(device),
+            Ok(_) => {}
+            Err(e) => debug!("Skipping {addr}: {e}"),
+        }
+    }
+
+    devices.sort_by_key(|device| std::cmp::Reverse(device.rssi));
+    Ok(devices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEART_RATE: Uuid = Uuid::from_u128(0x0000180d_0000_1000_8000_00805f9b34fb);
+
+    fn scanned(rssi: Option<i16>, uuids: &[Uuid]) -> ScannedDevice {
+        ScannedDevice {
+            address: Address::new([0xA4, 0xC1, 0x38, 0xEC, 0x91, 0x32]),
+            address_type: AddressType::LePublic,
+            name: None,
+            rssi,
+            uuids: uuids.to_vec(),
+            kind: None,
+        }
+    }
+
+    fn options(service_uuids: &[Uuid], rssi: Option<i16>) -> DiscoveryOptions {
+        DiscoveryOptions {
+            service_uuids: service_uuids.iter().copied().collect(),
+            rssi,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn matches_everything_without_filters() {
+        let options = DiscoveryOptions::default();
+        assert!(options.matches(&scanned(None, &[])));
+        assert!(options.matches(&scanned(Some(-100), &[HEART_RATE])));
+    }
+
+    #[test]
+    fn matches_any_of_the_service_uuids() {
+        let options = options(&[esp::SERVICE_UUID, HEART_RATE], None);
+        assert!(options.matches(&scanned(None, &[HEART_RATE])));
+        assert!(options.matches(&scanned(
+            None,
+            &[crate::govee::SERVICE_UUID, esp::SERVICE_UUID]
+        )));
+        assert!(!options.matches(&scanned(None, &[crate::govee::SERVICE_UUID])));
+        assert!(!options.matches(&scanned(None, &[])));
+    }
+
+    #[test]
+    fn matches_signals_at_least_as_strong_as_the_threshold() {
+        let options = options(&[], Some(-70));
+        assert!(options.matches(&scanned(Some(-40), &[])));
+        assert!(options.matches(&scanned(Some(-70), &[])));
+        assert!(!options.matches(&scanned(Some(-71), &[])));
+        // Like BlueZ, devices without a received signal strength do not pass a threshold.
+        assert!(!options.matches(&scanned(None, &[])));
+    }
+
+    #[test]
+    fn matches_both_filters() {
+        let options = options(&[HEART_RATE], Some(-70));
+        assert!(options.matches(&scanned(Some(-60), &[HEART_RATE])));
+        assert!(!options.matches(&scanned(Some(-80), &[HEART_RATE])));
+        assert!(!options.matches(&scanned(Some(-60), &[esp::SERVICE_UUID])));
+    }
+
+    #[test]
+    fn classifies_govee_strips_by_name_and_esp_strips_by_service() {
+        let govee = Some("ihoment_H6127_91A2");
+        assert_eq!(classify(govee, &[]), Some(DeviceKind::Govee));
+        assert_eq!(classify(None, &[esp::SERVICE_UUID]), Some(DeviceKind::Esp));
+        assert_eq!(
+            classify(Some("esp32-strip"), &[HEART_RATE, esp::SERVICE_UUID]),
+            Some(DeviceKind::Esp)
+        );
+        // The service decides when a device has both.
+        assert_eq!(classify(govee, &[esp::SERVICE_UUID]), Some(DeviceKind::Esp));
+    }
+
+    #[test]
+    fn does_not_classify_other_devices() {
+        assert_eq!(classify(Some("ihoment_H6199_91A2"), &[]), None);
+        assert_eq!(classify(Some("Pixel 7"), &[HEART_RATE]), None);
+        assert_eq!(classify(None, &[]), None);
+        assert_eq!(classify(None, &[crate::govee::SERVICE_UUID]), None);
+    }
+}
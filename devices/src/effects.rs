@@ -0,0 +1,311 @@
+//! Animations computed on the host and written to a strip frame by frame, for strips whose
+//! firmware does not offer them.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{f32::consts::PI, time::Duration};
+use tokio::time::Instant;
+
+use crate::{
+    color::{Color, Hsv},
+    Error, Result,
+};
+
+fn default_rainbow_period() -> u64 {
+    10_000
+}
+
+fn default_breathing_period() -> u64 {
+    4_000
+}
+
+fn default_strobe_period() -> u64 {
+    200
+}
+
+fn default_color_loop_period() -> u64 {
+    5_000
+}
+
+fn default_random_period() -> u64 {
+    1_000
+}
+
+fn default_candle_color() -> Color {
+    Color::from_kelvin(1900)
+}
+
+/// An animation and its parameters, periods are in milliseconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "effect", rename_all = "snake_case", deny_unknown_fields)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum Effect {
+    /// Cycles through all hues once per period.
+    Rainbow {
+        #[serde(default = "default_rainbow_period")]
+        period_ms: u64,
+    },
+    /// Fades `color` out and back in once per period.
+    Breathing {
+        color: Color,
+        #[serde(default = "default_breathing_period")]
+        period_ms: u64,
+    },
+    /// Flashes `color` once per period.
+    Strobe {
+        color: Color,
+        #[serde(default = "default_strobe_period")]
+        period_ms: u64,
+    },
+    /// Fades from each color to the next, spending one period on each.
+    ColorLoop {
+        colors: Vec<Color>,
+        #[serde(default = "default_color_loop_period")]
+        period_ms: u64,
+    },
+    /// Flickers like a flame of `color`.
+    Candle {
+        #[serde(default = "default_candle_color")]
+        color: Color,
+    },
+    /// Jumps to a random hue once per period.
+    Random {
+        #[serde(default = "default_random_period")]
+        period_ms: u64,
+    },
+}
+
+impl Effect {
+    pub const NAMES: [&'static str; 6] = [
+        "rainbow",
+        "breathing",
+        "strobe",
+        "color_loop",
+        "candle",
+        "random",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Effect::Rainbow { .. } => "rainbow",
+            Effect::Breathing { .. } => "breathing",
+            Effect::Strobe { .. } => "strobe",
+            Effect::ColorLoop { .. } => "color_loop",
+            Effect::Candle { .. } => "candle",
+            Effect::Random { .. } => "random",
+        }
+    }
+
+    fn period(&self) -> Option<Duration> {
+        match self {
+            Effect::Rainbow { period_ms }
+            | Effect::Breathing { period_ms, .. }
+            | Effect::Strobe { period_ms, .. }
+            | Effect::ColorLoop { period_ms, .. }
+            | Effect::Random { period_ms } => Some(Duration::from_millis(*period_ms)),
+            Effect::Candle { .. } => None,
+        }
+    }
+
+    /// Rejects parameters the effect can not be animated with.
+    pub fn validate(&self) -> Result<()> {
+        if self.period() == Some(Duration::ZERO) {
+            return Err(Error::InvalidCommand("period_ms must not be 0".to_string()));
+        }
+        if let Effect::ColorLoop { colors, .. } = self {
+            if colors.is_empty() {
+                return Err(Error::InvalidCommand(
+                    "color_loop needs at least one color".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A running effect, producing the color to show at any point in time.
+#[derive(Debug)]
+pub struct Animation {
+    effect: Effect,
+    started: Instant,
+    /// Current brightness of the candle flame.
+    flicker: f32,
+    /// The period the random color was picked in, and the color.
+    random: Option<(u128, Color)>,
+}
+
+impl Animation {
+    pub fn new(effect: Effect) -> Self {
+        Self {
+            effect,
+            started: Instant::now(),
+            flicker: 1.0,
+            random: None,
+        }
+    }
+
+    pub fn effect(&self) -> &Effect {
+        &self.effect
+    }
+
+    /// Changes the parameters without starting over.
+    pub fn set_effect(&mut self, effect: Effect) {
+        self.effect = effect;
+    }
+
+    /// Time until the next frame differs. `smooth` is used for effects that fade.
+    pub fn frame_interval(&self, smooth: Duration) -> Duration {
+        match self.effect {
+            Effect::Strobe { period_ms, .. } => Duration::from_millis(period_ms) / 2,
+            Effect::Random { period_ms } => Duration::from_millis(period_ms),
+            _ => smooth,
+        }
+    }
+
+    pub fn frame(&mut self, now: Instant) -> Color {
+        let elapsed = now.saturating_duration_since(self.started);
+        let periods = match self.effect.period() {
+            Some(period) => elapsed.as_secs_f64() / period.as_secs_f64(),
+            None => 0.0,
+        };
+        let phase = periods.fract() as f32;
+
+        match &self.effect {
+            Effect::Rainbow { .. } => Color::from_hsv(Hsv {
+                h: phase * 360.0,
+                s: 1.0,
+                v: 1.0,
+            }),
+            Effect::Breathing { color, .. } => color.scale((1.0 + (2.0 * PI * phase).cos()) / 2.0),
+            Effect::Strobe { color, .. } if phase < 0.5 => *color,
+            Effect::Strobe { .. } => Color::BLACK,
+            Effect::ColorLoop { colors, .. } => {
+                let i = periods as usize % colors.len();
+                colors[i].mix(colors[(i + 1) % colors.len()], phase)
+            }
+            Effect::Candle { color } => {
+                let target = rand::thread_rng().gen_range(0.55..=1.0);
+                self.flicker += (target - self.flicker) * 0.5;
+                color.scale(self.flicker)
+            }
+            Effect::Random { .. } => {
+                let period = periods as u128;
+                match self.random {
+                    Some((picked, color)) if picked == period => color,
+                    _ => {
+                        let color = Color::from_hsv(Hsv {
+                            h: rand::thread_rng().gen_range(0.0..360.0),
+                            s: 1.0,
+                            v: 1.0,
+                        });
+                        self.random = Some((period, color));
+                        color
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Color = Color::new(255, 0, 0);
+    const GREEN: Color = Color::new(0, 255, 0);
+    const BLUE: Color = Color::new(0, 0, 255);
+
+    /// The frame `ms` milliseconds after the animation started.
+    fn frame_at(animation: &mut Animation, ms: u64) -> Color {
+        let now = animation.started + Duration::from_millis(ms);
+        animation.frame(now)
+    }
+
+    #[test]
+    fn rainbow_follows_the_hue_circle() {
+        let mut rainbow = Animation::new(Effect::Rainbow { period_ms: 1200 });
+        assert_eq!(frame_at(&mut rainbow, 0), RED);
+        assert_eq!(frame_at(&mut rainbow, 400), GREEN);
+        assert_eq!(frame_at(&mut rainbow, 800), BLUE);
+        assert_eq!(frame_at(&mut rainbow, 1200), RED);
+        assert_eq!(frame_at(&mut rainbow, 1300), Color::new(255, 128, 0));
+    }
+
+    #[test]
+    fn breathing_fades_out_and_back_in() {
+        let mut breathing = Animation::new(Effect::Breathing {
+            color: Color::WHITE,
+            period_ms: 1000,
+        });
+        assert_eq!(frame_at(&mut breathing, 0), Color::WHITE);
+        assert_eq!(frame_at(&mut breathing, 500), Color::BLACK);
+        assert_eq!(frame_at(&mut breathing, 1000), Color::WHITE);
+    }
+
+    #[test]
+    fn strobe_is_on_for_the_first_half() {
+        let mut strobe = Animation::new(Effect::Strobe {
+            color: BLUE,
+            period_ms: 200,
+        });
+        assert_eq!(frame_at(&mut strobe, 0), BLUE);
+        assert_eq!(frame_at(&mut strobe, 99), BLUE);
+        assert_eq!(frame_at(&mut strobe, 100), Color::BLACK);
+        assert_eq!(frame_at(&mut strobe, 199), Color::BLACK);
+        assert_eq!(frame_at(&mut strobe, 200), BLUE);
+        assert_eq!(
+            strobe.frame_interval(Duration::from_millis(50)),
+            Duration::from_millis(100)
+        );
+    }
+
+    #[test]
+    fn color_loop_wraps_around() {
+        let mut color_loop = Animation::new(Effect::ColorLoop {
+            colors: vec![RED, GREEN, BLUE],
+            period_ms: 1000,
+        });
+        assert_eq!(frame_at(&mut color_loop, 0), RED);
+        assert_eq!(frame_at(&mut color_loop, 1000), GREEN);
+        assert_eq!(frame_at(&mut color_loop, 2000), BLUE);
+        assert_eq!(frame_at(&mut color_loop, 2500), Color::new(128, 0, 128));
+        assert_eq!(frame_at(&mut color_loop, 3000), RED);
+        assert_eq!(frame_at(&mut color_loop, 4000), GREEN);
+    }
+
+    #[test]
+    fn random_keeps_its_color_for_a_period() {
+        let mut random = Animation::new(Effect::Random { period_ms: 1000 });
+        let color = frame_at(&mut random, 100);
+        assert_eq!(frame_at(&mut random, 900), color);
+        assert_eq!(color.to_hsv().s, 1.0);
+    }
+
+    #[test]
+    fn validate_rejects_unusable_parameters() {
+        for effect in [
+            Effect::Rainbow { period_ms: 0 },
+            Effect::Strobe {
+                color: RED,
+                period_ms: 0,
+            },
+            Effect::ColorLoop {
+                colors: vec![RED],
+                period_ms: 0,
+            },
+            Effect::ColorLoop {
+                colors: Vec::new(),
+                period_ms: 1000,
+            },
+        ] {
+            assert!(
+                matches!(effect.validate(), Err(Error::InvalidCommand(_))),
+                "{:?}",
+                effect
+            );
+        }
+
+        assert!(Effect::Candle { color: RED }.validate().is_ok());
+        assert!(Effect::Rainbow { period_ms: 1 }.validate().is_ok());
+    }
+}
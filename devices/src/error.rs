@@ -0,0 +1,81 @@
+use bluer::{Address, Uuid};
+use std::{fmt, io};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Why an operation on a led strip failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The strip was not discovered before the discovery timeout.
+    NotFound(Address),
+    /// The operation needs a connection to the strip.
+    NotConnected(Address),
+    /// The strip does not offer the characteristic it is driven through.
+    CharacteristicMissing(Uuid),
+    /// BlueZ, or the link to the strip, failed.
+    Ble(bluer::Error),
+    /// The strip can not carry out the command.
+    InvalidCommand(String),
+    /// The strip did not respond in time to the described operation.
+    Timeout(String),
+    /// A bug on our side, like a task that stopped before answering.
+    Internal(String),
+    /// A newer command stopped a transition before it reached its target.
+    Superseded,
+}
+
+impl Error {
+    /// A short, stable name of the variant, for API clients to match on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::NotFound(_) => "not_found",
+            Error::NotConnected(_) => "not_connected",
+            Error::CharacteristicMissing(_) => "characteristic_missing",
+            Error::Ble(_) => "ble",
+            Error::InvalidCommand(_) => "invalid_command",
+            Error::Timeout(_) => "timeout",
+            Error::Internal(_) => "internal",
+            Error::Superseded => "superseded",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotFound(addr) => write!(f, "Device {} not found", addr),
+            Error::NotConnected(addr) => write!(f, "Device {} not connected", addr),
+            Error::CharacteristicMissing(uuid) => write!(f, "Characteristic {} not found", uuid),
+            Error::Ble(e) => write!(f, "Bluetooth error: {}", e),
+            Error::InvalidCommand(message) => write!(f, "{}", message),
+            Error::Timeout(action) => write!(f, "Timed out {}", action),
+            Error::Internal(message) => write!(f, "Internal error: {}", message),
+            Error::Superseded => write!(
+                f,
+                "Stopped by a newer command before the transition finished"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Ble(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<bluer::Error> for Error {
+    fn from(e: bluer::Error) -> Self {
+        Error::Ble(e)
+    }
+}
+
+/// Transports report failures as `io::Error`.
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Ble(e.into())
+    }
+}
@@ -1,35 +1,90 @@
 use async_trait::async_trait;
-use bluer::{gatt::remote::Characteristic, Address, Device, Uuid};
-use log::{debug, error, info};
-use std::io::{self, Error, ErrorKind};
+use bluer::{Address, Device, Uuid};
+use log::{debug, info};
+use std::sync::Arc;
+use tokio::time::Duration;
 
-use super::{connect_device, discover_device, find_characteristic, Event, LedDevice};
+use super::{
+    connect_device, discover_device, find_characteristic, Color, DeviceState, Error, Event,
+    LedDevice, Result, DEFAULT_DISCOVERY_TIMEOUT,
+};
+use crate::transport::{BluerTransport, GattTransport};
+
+pub const SERVICE_UUID: Uuid = Uuid::from_u128(0x1afc47f3_4a31_4c4e_9f54_ca1ede6e2e1f);
+pub const CHARACTERISTIC_UUID: Uuid = Uuid::from_u128(0x21b3e7c8_bc41_47c7_af6c_1fe47aad759f);
 
 #[derive(Debug, Clone)]
 pub struct EspLed {
     addr: Address,
+    adapter: Option<String>,
+    discovery_timeout: Duration,
     service_uuid: Uuid,
     characteristic_uuid: Uuid,
     device: Option<Device>,
-    characteristic: Option<Characteristic>,
+    transport: Option<Arc<dyn GattTransport>>,
+    // The firmware does not report its state, so this is the last state written to it.
+    state: DeviceState,
 }
 
 impl EspLed {
     pub fn new(addr: Address, service_uuid: Uuid, characteristic_uuid: Uuid) -> Self {
         Self {
             addr,
+            adapter: None,
+            discovery_timeout: DEFAULT_DISCOVERY_TIMEOUT,
             service_uuid,
             characteristic_uuid,
             device: None,
-            characteristic: None,
+            transport: None,
+            state: DeviceState::default(),
         }
     }
+
+    /// Give up connecting when the strip was not discovered within `timeout`.
+    pub fn with_discovery_timeout(mut self, timeout: Duration) -> Self {
+        self.discovery_timeout = timeout;
+        self
+    }
+
+    /// Connect through adapter `name` instead of the default adapter.
+    pub fn with_adapter(mut self, name: impl Into<String>) -> Self {
+        self.adapter = Some(name.into());
+        self
+    }
+
+    /// Drive the led strip through `transport`.
+    pub fn attach_transport(&mut self, transport: Arc<dyn GattTransport>) {
+        self.transport = Some(transport);
+        self.state.connected = true;
+    }
+
+    async fn write(&self, frame: &[u8]) -> Result<()> {
+        let transport = self
+            .transport
+            .as_ref()
+            .ok_or(Error::NotConnected(self.addr))?;
+        Ok(transport.write(frame).await?)
+    }
+
+    // 0x01, STATE
+    async fn set_power(&mut self, on: bool) -> Result<()> {
+        self.write(&[0x01, on as u8]).await
+    }
+
+    // 0x02, BRIGHTNESS
+    async fn set_brightness(&mut self, brightness: u8) -> Result<()> {
+        self.write(&[0x02, brightness]).await
+    }
+
+    // 0x03, RED, GREEN, BLUE
+    async fn set_color(&mut self, color: Color) -> Result<()> {
+        self.write(&[0x03, color.r, color.g, color.b]).await
+    }
 }
 
-// TOOD: use anyhow error handling
 #[async_trait]
 impl LedDevice for EspLed {
-    async fn connect(&mut self) -> io::Result<()> {
+    async fn connect(&mut self) -> Result<()> {
         info!("Start connect to esp");
 
         if let Some(device) = &self.device {
@@ -39,76 +94,66 @@ impl LedDevice for EspLed {
             }
         }
 
-        match discover_device(self.addr).await {
-            Ok(Some(device)) => {
-                self.device = Some(device);
-            }
-            Ok(None) => {
-                let err = Error::new(
-                    ErrorKind::NotFound,
-                    format!("Device {} not found", self.addr),
-                );
-                return Err(err);
-            }
-            Err(e) => {
-                let err = Error::new(
-                    ErrorKind::NotFound,
-                    format!("Error searching for {}: {e}", self.addr),
-                );
-                return Err(err);
-            }
-        }
+        let device =
+            discover_device(self.adapter.as_deref(), self.addr, self.discovery_timeout).await?;
+        connect_device(&device).await?;
+        info!("Successfully connected to {:?}", device);
+
+        let characteristic =
+            find_characteristic(&device, self.service_uuid, self.characteristic_uuid)
+                .await?
+                .ok_or(Error::CharacteristicMissing(self.characteristic_uuid))?;
+
+        self.device = Some(device);
+        self.attach_transport(Arc::new(BluerTransport::new(characteristic)));
+        info!("successfully found char");
+
+        Ok(())
+    }
 
+    async fn disconnect(&mut self) -> Result<()> {
         if let Some(device) = &self.device {
-            match connect_device(device).await {
-                Ok(()) => {}
-                Err(e) => {
-                    let err = Error::new(
-                        ErrorKind::NotFound,
-                        format!("Error connecting to {}: {e}", self.addr),
-                    );
-                    return Err(err);
-                }
-            }
+            device.disconnect().await?;
+            self.device = None;
+        }
+        self.transport = None;
+        self.state = DeviceState::default();
+        info!("try disconnect");
+        Ok(())
+    }
 
-            info!("Successfully connected to {:?}", self.device);
-
-            match find_characteristic(device, self.service_uuid, self.characteristic_uuid).await {
-                Ok(Some(characteristic)) => {
-                    self.characteristic = Some(characteristic.clone());
-                }
-                Ok(None) => {
-                    let err = Error::new(
-                        ErrorKind::NotFound,
-                        format!("Characteristic {} not found", self.characteristic_uuid),
-                    );
-                    return Err(err);
-                }
-                Err(e) => {
-                    error!("{e}");
-                    let err = Error::new(
-                        ErrorKind::NotFound,
-                        format!(
-                            "Error searching for characteristic {}: {e}",
-                            self.characteristic_uuid
-                        ),
-                    );
-                    return Err(err);
-                }
+    async fn on_event(&mut self, event: Event) -> Result<()> {
+        debug!("Set led on {:?}, {:?}", self.addr, event);
+
+        match &event {
+            Event::On => self.set_power(true).await?,
+            Event::Off => self.set_power(false).await?,
+            Event::Color(color) => self.set_color(*color).await?,
+            Event::Brightness(brightness) => self.set_brightness(*brightness).await?,
+            Event::Scene(_) | Event::Music(_) | Event::Diy(_) => {
+                return Err(Error::InvalidCommand(format!(
+                    "{event:?} is not supported by {}",
+                    self.addr
+                )))
             }
-            info!("successfully found char");
+            Event::Other(_) => {}
         }
 
+        self.state.apply_event(&event);
         Ok(())
     }
 
-    async fn disconnect(&mut self) -> io::Result<()> {
-        Ok(())
+    fn bluetooth_device(&self) -> Option<Device> {
+        self.device.clone()
     }
 
-    async fn on_event(&mut self, event: Event) -> io::Result<()> {
-        info!("Set led on {:?}, {:?}", self.addr, event);
+    fn mark_disconnected(&mut self) {
+        self.device = None;
+        self.transport = None;
+        self.state = DeviceState::default();
+    }
 
-        Ok(())
+    async fn state(&mut self) -> Result<DeviceState> {
+        Ok(self.state.clone())
     }
 }
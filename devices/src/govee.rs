@@ -1,23 +1,66 @@
+pub mod protocol;
+
 use async_trait::async_trait;
-use bluer::{gatt::remote::Characteristic, gatt::CharacteristicWriter, Address, Device, Uuid};
-use log::{debug, error, info};
-use std::io::{self, Error, ErrorKind};
+use bluer::{Address, Device, Uuid};
+use futures::StreamExt;
+use log::{debug, info, warn};
 use std::sync::Arc;
-use tokio::io::AsyncWriteExt;
-use tokio::sync::Mutex;
-use tokio::time::{self, Duration};
+use tokio::{
+    sync::{broadcast, watch},
+    task::JoinHandle,
+    time::{self, Duration},
+};
 
-use super::{connect_device, discover_device, find_characteristic, Event, LedDevice};
+use super::{
+    connect_device, discover_device, find_characteristic, DeviceState, Error, Event, LedDevice,
+    Result, DEFAULT_DISCOVERY_TIMEOUT,
+};
 use crate::keep_alive_job::KeepAlive;
+use crate::transport::{BluerTransport, GattTransport};
+use protocol::{Command, Mode, MusicMode, Packet, Query, Scene, Status, MAX_DIY_COLORS};
+
+pub const SERVICE_UUID: Uuid = Uuid::from_u128(0x000102030405060708090a0b0c0d1910);
+pub const CHARACTERISTIC_UUID: Uuid = Uuid::from_u128(0x000102030405060708090a0b0c0d2b11);
+pub const NOTIFY_CHARACTERISTIC_UUID: Uuid = Uuid::from_u128(0x000102030405060708090a0b0c0d2b10);
+
+/// How long `state` waits for the replies to its status queries.
+const STATE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Built-in scenes and music modes, with the name they can be picked by.
+pub fn scene_catalog() -> Vec<(String, Event)> {
+    let scenes = Scene::ALL
+        .into_iter()
+        .map(|scene| (scene.name().to_string(), Event::Scene(scene)));
+    let music = MusicMode::ALL
+        .into_iter()
+        .map(|mode| (format!("Music: {}", mode.name()), Event::Music(mode)));
+
+    scenes.chain(music).collect()
+}
+
+/// Looks up a `scene_catalog` entry, ignoring case.
+pub fn find_scene(name: &str) -> Option<Event> {
+    scene_catalog()
+        .into_iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name.trim()))
+        .map(|(_, event)| event)
+}
 
 #[derive(Debug)]
 pub struct GoveeLed {
     addr: Address,
+    adapter: Option<String>,
+    discovery_timeout: Duration,
     service_uuid: Uuid,
     characteristic_uuid: Uuid,
+    notify_characteristic_uuid: Uuid,
     device: Option<Device>,
-    characteristic: Option<Characteristic>,
+    transport: Option<Arc<dyn GattTransport>>,
     keep_alive: KeepAlive,
+    state: Arc<watch::Sender<DeviceState>>,
+    /// Every status reply, for `state` to tell which of its queries were answered.
+    statuses: broadcast::Sender<Status>,
+    notifications: Option<JoinHandle<()>>,
 }
 
 impl GoveeLed {
@@ -26,95 +69,107 @@ impl GoveeLed {
 
         Self {
             addr,
+            adapter: None,
+            discovery_timeout: DEFAULT_DISCOVERY_TIMEOUT,
             service_uuid,
             characteristic_uuid,
+            notify_characteristic_uuid: NOTIFY_CHARACTERISTIC_UUID,
             device: None,
-            characteristic: None,
+            transport: None,
             keep_alive,
+            state: Arc::new(watch::channel(DeviceState::default()).0),
+            statuses: broadcast::channel(16).0,
+            notifications: None,
         }
     }
 
-    // 0x33, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x33
-    async fn turn_on(&mut self) {
-        let on_ev = vec![
-            0x33, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x33,
-        ];
-        if let Some(characteristic) = &self.characteristic {
-            characteristic.write(&on_ev).await.unwrap();
-        }
+    /// Give up connecting when the strip was not discovered within `timeout`.
+    pub fn with_discovery_timeout(mut self, timeout: Duration) -> Self {
+        self.discovery_timeout = timeout;
+        self
     }
 
-    // 0x33, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x32
-    async fn turn_off(&mut self) {
-        let off_ev = vec![
-            0x33, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x32,
-        ];
-        if let Some(characteristic) = &self.characteristic {
-            characteristic.write(&off_ev).await.unwrap();
-        }
+    /// Connect through adapter `name` instead of the default adapter.
+    pub fn with_adapter(mut self, name: impl Into<String>) -> Self {
+        self.adapter = Some(name.into());
+        self
     }
 
-    // https://github.com/egold555/Govee-Reverse-Engineering/blob/master/Products/H6127.md#set-color
-    // 0x33, 0x05, 0x02, RED, GREEN, BLUE, 0x00, 0xFF, 0xAE, 0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, XOR
-    // 0x33, 0x05, 0x02, RED, GREEN, BLUE, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, XOR
-    async fn set_color(&mut self, color: String) {
-        let mut color_ev = vec![0x33, 0x05, 0x02];
-
-        let mut color_vals = color.chars().collect::<Vec<char>>();
-        color_vals.remove(0);
-        let rgb_colors = color_vals
-            .chunks(2)
-            .map(|c| c.iter().collect::<String>())
-            .collect::<Vec<String>>();
-
-        color_ev.push(u8::from_str_radix(&rgb_colors[0], 16).unwrap());
-        color_ev.push(u8::from_str_radix(&rgb_colors[1], 16).unwrap());
-        color_ev.push(u8::from_str_radix(&rgb_colors[2], 16).unwrap());
-
-        // color_ev.extend([
-        //     0x00, 0xFF, 0xAE, 0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-        // ]);
-        color_ev.extend([
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-        ]);
-
-        let mut xor = color_ev[0];
-        for a in color_ev.iter().skip(1) {
-            xor ^= a;
-        }
+    pub fn with_notify_characteristic(mut self, notify_characteristic_uuid: Uuid) -> Self {
+        self.notify_characteristic_uuid = notify_characteristic_uuid;
+        self
+    }
 
-        color_ev.push(xor);
+    /// Drive the led strip through `transport`, start sending keep alive packets on it and
+    /// track the status replies it notifies.
+    pub fn attach_transport(&mut self, transport: Arc<dyn GattTransport>) {
+        // Send keep alive packet every 2 seconds to ensure the connection remains established, until disconnected manually.
+        let keep_alive_ev = protocol::encode_query(Query::Power).to_vec();
+        self.keep_alive.stop();
+        self.keep_alive.run(transport.clone(), keep_alive_ev);
 
-        if let Some(characteristic) = &self.characteristic {
-            characteristic.write(color_ev.as_slice()).await.unwrap();
-        }
+        self.listen(transport.clone());
+        self.state.send_modify(|state| state.connected = true);
+        self.transport = Some(transport);
     }
 
-    // 0x33, 0x04, BRIGHTNESS, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, (0x33 ^ 0x04 ^ BRIGHTNESS)
-    async fn set_brightness(&mut self, brightness: u8) {
-        let mut brightness_ev = vec![
-            0x33, 0x04, brightness, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-        ];
+    /// The state the strip reported, updated whenever it notifies a change.
+    pub fn watch_state(&self) -> watch::Receiver<DeviceState> {
+        self.state.subscribe()
+    }
 
-        brightness_ev.push(0x33 ^ 0x04 ^ brightness);
+    fn detach_transport(&mut self) {
+        self.keep_alive.stop();
+        if let Some(notifications) = self.notifications.take() {
+            notifications.abort();
+        }
+        self.state.send_replace(DeviceState::default());
+        self.transport = None;
+    }
 
-        if let Some(characteristic) = &self.characteristic {
-            characteristic
-                .write(brightness_ev.as_slice())
-                .await
-                .unwrap();
+    fn listen(&mut self, transport: Arc<dyn GattTransport>) {
+        if let Some(notifications) = self.notifications.take() {
+            notifications.abort();
         }
+
+        let state = self.state.clone();
+        let statuses = self.statuses.clone();
+        let addr = self.addr;
+        self.notifications = Some(tokio::spawn(async move {
+            let mut notifications = match transport.notify().await {
+                Ok(notifications) => notifications,
+                Err(e) => {
+                    warn!("Failed to subscribe to notifications of {addr}: {e}");
+                    return;
+                }
+            };
+
+            while let Some(value) = notifications.next().await {
+                match protocol::decode(&value) {
+                    Ok(Packet::Status(status)) => {
+                        debug!("Status of {addr}: {status:?}");
+                        state.send_if_modified(|state| apply_status(state, status.clone()));
+                        let _ = statuses.send(status);
+                    }
+                    Ok(Packet::Command(_)) => {}
+                    Err(e) => debug!("Ignoring notification {value:02x?} from {addr}: {e}"),
+                }
+            }
+        }));
+    }
+
+    async fn send(&self, command: Command) -> Result<()> {
+        let transport = self
+            .transport
+            .as_ref()
+            .ok_or(Error::NotConnected(self.addr))?;
+        Ok(transport.write(&protocol::encode(&command)).await?)
     }
 }
 
-// TOOD: use anyhow error handling
 #[async_trait]
 impl LedDevice for GoveeLed {
-    // TOOD: start job for sending `keep_alive` messages
-    async fn connect(&mut self) -> io::Result<()> {
+    async fn connect(&mut self) -> Result<()> {
         if let Some(device) = &self.device {
             if device.is_connected().await? {
                 info!("Device already connected");
@@ -122,98 +177,119 @@ impl LedDevice for GoveeLed {
             }
         }
 
-        match discover_device(self.addr).await {
-            Ok(Some(device)) => {
-                self.device = Some(device);
-            }
-            Ok(None) => {
-                let err = Error::new(
-                    ErrorKind::NotFound,
-                    format!("Device {} not found", self.addr),
-                );
-                return Err(err);
-            }
-            Err(e) => {
-                let err = Error::new(
-                    ErrorKind::NotFound,
-                    format!("Error searching for {}: {e}", self.addr),
-                );
-                return Err(err);
-            }
-        }
-
-        if let Some(device) = &self.device {
-            match connect_device(device).await {
-                Ok(()) => {}
-                Err(e) => {
-                    let err = Error::new(
-                        ErrorKind::NotFound,
-                        format!("Error connecting to {}: {e}", self.addr),
-                    );
-                    return Err(err);
-                }
-            }
-
-            info!("Successfully connected to {:?}", self.device);
+        let device =
+            discover_device(self.adapter.as_deref(), self.addr, self.discovery_timeout).await?;
+        connect_device(&device).await?;
+        info!("Successfully connected to {:?}", device);
 
-            match find_characteristic(device, self.service_uuid, self.characteristic_uuid).await {
-                Ok(Some(characteristic)) => {
-                    self.characteristic = Some(characteristic.clone());
+        let characteristic =
+            find_characteristic(&device, self.service_uuid, self.characteristic_uuid)
+                .await?
+                .ok_or(Error::CharacteristicMissing(self.characteristic_uuid))?;
+        let mut transport = BluerTransport::new(characteristic);
 
-                    // Send keep alive packet every 2 seconds to ensure the connection remains established, until disconnected manually.
-                    // 0xAA, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xAB
-                    let keep_alive_ev = vec![
-                        0xAA, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xAB,
-                    ];
-                    self.keep_alive.run(characteristic, keep_alive_ev);
-                }
-                Ok(None) => {
-                    let err = Error::new(
-                        ErrorKind::NotFound,
-                        format!("Characteristic {} not found", self.characteristic_uuid),
-                    );
-                    return Err(err);
-                }
-                Err(e) => {
-                    let err = Error::new(
-                        ErrorKind::NotFound,
-                        format!(
-                            "Error searching for characteristic {}: {e}",
-                            self.characteristic_uuid
-                        ),
-                    );
-                    return Err(err);
-                }
+        match find_characteristic(&device, self.service_uuid, self.notify_characteristic_uuid).await
+        {
+            Ok(Some(notify_characteristic)) => {
+                transport = transport.with_notify_characteristic(notify_characteristic);
             }
-            info!("successfully found char");
+            Ok(None) => warn!(
+                "Notify characteristic {} not found, state will not be read back",
+                self.notify_characteristic_uuid
+            ),
+            Err(e) => warn!(
+                "Error searching for notify characteristic {}: {e}",
+                self.notify_characteristic_uuid
+            ),
         }
 
+        self.device = Some(device);
+        self.attach_transport(Arc::new(transport));
+        info!("successfully found char");
+
         Ok(())
     }
 
-    async fn disconnect(&mut self) -> io::Result<()> {
+    async fn disconnect(&mut self) -> Result<()> {
         if let Some(device) = &self.device {
             device.disconnect().await?;
-            self.keep_alive.stop();
             self.device = None;
-            self.characteristic = None;
         }
+        self.detach_transport();
         info!("try disconnect");
         Ok(())
     }
 
-    async fn on_event(&mut self, event: Event) -> io::Result<()> {
-        info!("Set led on {:?}, {:?}", self.addr, event);
+    async fn on_event(&mut self, event: Event) -> Result<()> {
+        debug!("Set led on {:?}, {:?}", self.addr, event);
+
+        let command = match event {
+            Event::On => Command::Power(true),
+            Event::Off => Command::Power(false),
+            Event::Color(color) => Command::Mode(Mode::Color(color)),
+            Event::Brightness(brightness) => Command::Brightness(brightness),
+            Event::Scene(scene) => Command::Mode(Mode::Scene(scene)),
+            Event::Music(mode) => Command::Mode(Mode::Music(mode)),
+            Event::Diy(diy) if diy.colors.is_empty() || diy.colors.len() > MAX_DIY_COLORS => {
+                return Err(Error::InvalidCommand(format!(
+                    "DIY animations take 1 to {} colors, got {}",
+                    MAX_DIY_COLORS,
+                    diy.colors.len()
+                )))
+            }
+            Event::Diy(diy) => Command::Mode(Mode::Diy(diy)),
+            Event::Other(_) => return Ok(()),
+        };
+        self.send(command).await
+    }
+
+    fn bluetooth_device(&self) -> Option<Device> {
+        self.device.clone()
+    }
+
+    fn mark_disconnected(&mut self) {
+        self.device = None;
+        self.detach_transport();
+    }
+
+    async fn state(&mut self) -> Result<DeviceState> {
+        let Some(transport) = &self.transport else {
+            return Ok(self.state.borrow().clone());
+        };
 
-        match event {
-            Event::On => self.turn_on().await,
-            Event::Off => self.turn_off().await,
-            Event::Color(color) => self.set_color(color).await,
-            Event::Brightness(brightness) => self.set_brightness(brightness).await,
-            Event::Other(_) => {}
+        let mut replies = self.statuses.subscribe();
+        let mut unanswered = vec![Query::Power, Query::Brightness, Query::Mode];
+        for query in &unanswered {
+            transport.write(&protocol::encode_query(*query)).await?;
         }
 
-        Ok(())
+        // Whatever answered in time is in `self.state`, the rest keeps its last known value.
+        let _ = time::timeout(STATE_TIMEOUT, async {
+            while !unanswered.is_empty() {
+                let answered = match replies.recv().await {
+                    Ok(Status::Power(_)) => Query::Power,
+                    Ok(Status::Brightness(_)) => Query::Brightness,
+                    Ok(Status::Mode(_)) => Query::Mode,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                unanswered.retain(|query| *query != answered);
+            }
+        })
+        .await;
+
+        let state = self.state.borrow().clone();
+        Ok(state)
+    }
+}
+
+/// Returns whether `status` changed `state`.
+fn apply_status(state: &mut DeviceState, status: Status) -> bool {
+    let before = state.clone();
+    match status {
+        Status::Power(on) => state.power = Some(on),
+        Status::Brightness(brightness) => state.brightness = Some(brightness),
+        Status::Mode(mode) => state.mode = Some(mode),
     }
+    *state != before
 }
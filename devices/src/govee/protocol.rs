@@ -0,0 +1,558 @@
+//! Encoding and decoding of the 20-byte frames Govee H6127 strips understand.
+//!
+//! Every frame starts with a header byte, `0x33` for commands and `0xAA` for status queries and
+//! their replies, and ends with the XOR of the 19 bytes before it.
+//!
+//! https://github.com/egold555/Govee-Reverse-Engineering/blob/master/Products/H6127.md
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::Color;
+
+pub const FRAME_LEN: usize = 20;
+
+pub type Frame = [u8; FRAME_LEN];
+
+const COMMAND_HEADER: u8 = 0x33;
+const STATUS_HEADER: u8 = 0xAA;
+
+const POWER: u8 = 0x01;
+const BRIGHTNESS: u8 = 0x04;
+const MODE: u8 = 0x05;
+
+const MODE_MUSIC: u8 = 0x01;
+const MODE_MANUAL: u8 = 0x02;
+const MODE_SCENE: u8 = 0x04;
+const MODE_DIY: u8 = 0x0a;
+
+pub const MAX_DIY_COLORS: usize = 4;
+
+/// Built-in scenes, `0x33, 0x05, 0x04, SCENE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum Scene {
+    Sunrise,
+    Sunset,
+    Movie,
+    Dating,
+    Romantic,
+    Blinking,
+    Candlelight,
+    Snowflake,
+}
+
+impl Scene {
+    pub const ALL: [Scene; 8] = [
+        Scene::Sunrise,
+        Scene::Sunset,
+        Scene::Movie,
+        Scene::Dating,
+        Scene::Romantic,
+        Scene::Blinking,
+        Scene::Candlelight,
+        Scene::Snowflake,
+    ];
+
+    pub fn id(self) -> u8 {
+        match self {
+            Scene::Sunrise => 0x00,
+            Scene::Sunset => 0x01,
+            Scene::Movie => 0x04,
+            Scene::Dating => 0x05,
+            Scene::Romantic => 0x07,
+            Scene::Blinking => 0x08,
+            Scene::Candlelight => 0x09,
+            Scene::Snowflake => 0x0f,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|scene| scene.id() == id)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scene::Sunrise => "Sunrise",
+            Scene::Sunset => "Sunset",
+            Scene::Movie => "Movie",
+            Scene::Dating => "Dating",
+            Scene::Romantic => "Romantic",
+            Scene::Blinking => "Blinking",
+            Scene::Candlelight => "Candlelight",
+            Scene::Snowflake => "Snowflake",
+        }
+    }
+}
+
+/// Music reactive modes, `0x33, 0x05, 0x01, MODE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum MusicMode {
+    Energic,
+    Spectrum,
+    Rolling,
+    Rhythm,
+}
+
+impl MusicMode {
+    pub const ALL: [MusicMode; 4] = [
+        MusicMode::Energic,
+        MusicMode::Spectrum,
+        MusicMode::Rolling,
+        MusicMode::Rhythm,
+    ];
+
+    pub fn id(self) -> u8 {
+        match self {
+            MusicMode::Energic => 0x00,
+            MusicMode::Spectrum => 0x01,
+            MusicMode::Rolling => 0x02,
+            MusicMode::Rhythm => 0x03,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.id() == id)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MusicMode::Energic => "energic",
+            MusicMode::Spectrum => "spectrum",
+            MusicMode::Rolling => "rolling",
+            MusicMode::Rhythm => "rhythm",
+        }
+    }
+}
+
+/// Animation of a DIY multicolor mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum DiyStyle {
+    Fade,
+    Jumping,
+    Flicker,
+    Marquee,
+}
+
+impl DiyStyle {
+    pub const ALL: [DiyStyle; 4] = [
+        DiyStyle::Fade,
+        DiyStyle::Jumping,
+        DiyStyle::Flicker,
+        DiyStyle::Marquee,
+    ];
+
+    pub fn id(self) -> u8 {
+        match self {
+            DiyStyle::Fade => 0x00,
+            DiyStyle::Jumping => 0x01,
+            DiyStyle::Flicker => 0x02,
+            DiyStyle::Marquee => 0x03,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|style| style.id() == id)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DiyStyle::Fade => "fade",
+            DiyStyle::Jumping => "jumping",
+            DiyStyle::Flicker => "flicker",
+            DiyStyle::Marquee => "marquee",
+        }
+    }
+}
+
+/// DIY multicolor animation, `0x33, 0x05, 0x0A, STYLE, SPEED, COUNT, (RED, GREEN, BLUE) * COUNT`.
+///
+/// Only the first `MAX_DIY_COLORS` colors fit in a frame.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Diy {
+    pub style: DiyStyle,
+    pub speed: u8,
+    pub colors: Vec<Color>,
+}
+
+/// What the strip is showing, the payload of `0x05` frames.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", content = "value", rename_all = "snake_case")]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum Mode {
+    /// 0x02, RED, GREEN, BLUE
+    Color(Color),
+    /// 0x04, SCENE
+    Scene(Scene),
+    /// 0x01, MODE
+    Music(MusicMode),
+    /// 0x0A, STYLE, SPEED, COUNT, COLORS
+    Diy(Diy),
+}
+
+/// Commands written to the strip, header `0x33`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// 0x33, 0x01, ON/OFF, 0x00, ..., XOR
+    Power(bool),
+    /// 0x33, 0x04, BRIGHTNESS, 0x00, ..., XOR
+    Brightness(u8),
+    /// 0x33, 0x05, MODE..., 0x00, ..., XOR
+    Mode(Mode),
+}
+
+/// Status queries written to the strip, header `0xAA`. The device answers with a frame
+/// that decodes to a [`Status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Query {
+    /// 0xAA, 0x01, 0x00, ..., 0xAB
+    ///
+    /// Also used as keep alive packet.
+    Power,
+    /// 0xAA, 0x04, 0x00, ..., 0xAE
+    Brightness,
+    /// 0xAA, 0x05, 0x00, ..., 0xAF
+    Mode,
+}
+
+/// Replies to a [`Query`], header `0xAA`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    /// 0xAA, 0x01, ON/OFF, 0x00, ..., XOR
+    Power(bool),
+    /// 0xAA, 0x04, BRIGHTNESS, 0x00, ..., XOR
+    Brightness(u8),
+    /// 0xAA, 0x05, MODE..., 0x00, ..., XOR
+    Mode(Mode),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Packet {
+    Command(Command),
+    Status(Status),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    InvalidLength(usize),
+    InvalidHeader(u8),
+    InvalidChecksum { expected: u8, actual: u8 },
+    UnknownCommand { header: u8, command: u8 },
+    UnknownMode(u8),
+    UnknownScene(u8),
+    UnknownMusicMode(u8),
+    UnknownDiyStyle(u8),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::InvalidLength(len) => {
+                write!(f, "invalid frame length {len}, expected {FRAME_LEN}")
+            }
+            DecodeError::InvalidHeader(header) => write!(f, "invalid frame header {header:#04x}"),
+            DecodeError::InvalidChecksum { expected, actual } => write!(
+                f,
+                "invalid checksum {actual:#04x}, expected {expected:#04x}"
+            ),
+            DecodeError::UnknownCommand { header, command } => {
+                write!(f, "unknown command {header:#04x} {command:#04x}")
+            }
+            DecodeError::UnknownMode(mode) => write!(f, "unknown mode {mode:#04x}"),
+            DecodeError::UnknownScene(scene) => write!(f, "unknown scene {scene:#04x}"),
+            DecodeError::UnknownMusicMode(mode) => write!(f, "unknown music mode {mode:#04x}"),
+            DecodeError::UnknownDiyStyle(style) => write!(f, "unknown diy style {style:#04x}"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// XOR of all `bytes`.
+pub fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |xor, b| xor ^ b)
+}
+
+pub fn encode(command: &Command) -> Frame {
+    match command {
+        Command::Power(on) => frame(COMMAND_HEADER, &[POWER, *on as u8]),
+        Command::Brightness(brightness) => frame(COMMAND_HEADER, &[BRIGHTNESS, *brightness]),
+        Command::Mode(mode) => {
+            let mut payload = vec![MODE];
+            payload.extend(encode_mode(mode));
+            frame(COMMAND_HEADER, &payload)
+        }
+    }
+}
+
+fn encode_mode(mode: &Mode) -> Vec<u8> {
+    match mode {
+        Mode::Color(Color { r, g, b }) => vec![MODE_MANUAL, *r, *g, *b],
+        Mode::Scene(scene) => vec![MODE_SCENE, scene.id()],
+        Mode::Music(mode) => vec![MODE_MUSIC, mode.id()],
+        Mode::Diy(diy) => {
+            let colors = &diy.colors[..diy.colors.len().min(MAX_DIY_COLORS)];
+
+            let mut payload = vec![MODE_DIY, diy.style.id(), diy.speed, colors.len() as u8];
+            for color in colors {
+                payload.extend([color.r, color.g, color.b]);
+            }
+            payload
+        }
+    }
+}
+
+pub fn encode_query(query: Query) -> Frame {
+    let command = match query {
+        Query::Power => POWER,
+        Query::Brightness => BRIGHTNESS,
+        Query::Mode => MODE,
+    };
+    frame(STATUS_HEADER, &[command])
+}
+
+/// Checks length and checksum of `bytes`.
+pub fn validate(bytes: &[u8]) -> Result<&Frame, DecodeError> {
+    let frame: &Frame = bytes
+        .try_into()
+        .map_err(|_| DecodeError::InvalidLength(bytes.len()))?;
+
+    let expected = checksum(&frame[..FRAME_LEN - 1]);
+    let actual = frame[FRAME_LEN - 1];
+    if expected != actual {
+        return Err(DecodeError::InvalidChecksum { expected, actual });
+    }
+
+    Ok(frame)
+}
+
+/// Decodes a frame, `0x33` frames as commands and `0xAA` frames as status replies.
+pub fn decode(bytes: &[u8]) -> Result<Packet, DecodeError> {
+    let frame = validate(bytes)?;
+    let [header, command, payload @ ..] = frame;
+
+    match *header {
+        COMMAND_HEADER => decode_command(*command, payload).map(Packet::Command),
+        STATUS_HEADER => decode_status(*command, payload).map(Packet::Status),
+        header => Err(DecodeError::InvalidHeader(header)),
+    }
+}
+
+fn decode_command(command: u8, payload: &[u8]) -> Result<Command, DecodeError> {
+    match command {
+        POWER => Ok(Command::Power(payload[0] == 0x01)),
+        BRIGHTNESS => Ok(Command::Brightness(payload[0])),
+        MODE => decode_mode(payload).map(Command::Mode),
+        command => Err(DecodeError::UnknownCommand {
+            header: COMMAND_HEADER,
+            command,
+        }),
+    }
+}
+
+fn decode_status(command: u8, payload: &[u8]) -> Result<Status, DecodeError> {
+    match command {
+        POWER => Ok(Status::Power(payload[0] == 0x01)),
+        BRIGHTNESS => Ok(Status::Brightness(payload[0])),
+        MODE => decode_mode(payload).map(Status::Mode),
+        command => Err(DecodeError::UnknownCommand {
+            header: STATUS_HEADER,
+            command,
+        }),
+    }
+}
+
+fn decode_mode(payload: &[u8]) -> Result<Mode, DecodeError> {
+    match payload[0] {
+        MODE_MANUAL => Ok(Mode::Color(color(&payload[1..]))),
+        MODE_SCENE => Scene::from_id(payload[1])
+            .map(Mode::Scene)
+            .ok_or(DecodeError::UnknownScene(payload[1])),
+        MODE_MUSIC => MusicMode::from_id(payload[1])
+            .map(Mode::Music)
+            .ok_or(DecodeError::UnknownMusicMode(payload[1])),
+        MODE_DIY => {
+            let style =
+                DiyStyle::from_id(payload[1]).ok_or(DecodeError::UnknownDiyStyle(payload[1]))?;
+            let count = (payload[3] as usize).min(MAX_DIY_COLORS);
+            let colors = payload[4..4 + count * 3].chunks(3).map(color).collect();
+
+            Ok(Mode::Diy(Diy {
+                style,
+                speed: payload[2],
+                colors,
+            }))
+        }
+        mode => Err(DecodeError::UnknownMode(mode)),
+    }
+}
+
+fn color(bytes: &[u8]) -> Color {
+    Color::new(bytes[0], bytes[1], bytes[2])
+}
+
+/// Builds a zero padded frame from `header` and `payload`, ending with the checksum.
+fn frame(header: u8, payload: &[u8]) -> Frame {
+    let mut frame = [0; FRAME_LEN];
+    frame[0] = header;
+    frame[1..=payload.len()].copy_from_slice(payload);
+    frame[FRAME_LEN - 1] = checksum(&frame[..FRAME_LEN - 1]);
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `prefix` padded with zeros, ending with the documented `xor`.
+    fn padded(prefix: &[u8], xor: u8) -> Frame {
+        let mut frame = [0; FRAME_LEN];
+        frame[..prefix.len()].copy_from_slice(prefix);
+        frame[FRAME_LEN - 1] = xor;
+        frame
+    }
+
+    #[test]
+    fn encodes_power() {
+        assert_eq!(
+            encode(&Command::Power(true)),
+            padded(&[0x33, 0x01, 0x01], 0x33)
+        );
+        assert_eq!(
+            encode(&Command::Power(false)),
+            padded(&[0x33, 0x01, 0x00], 0x32)
+        );
+    }
+
+    #[test]
+    fn encodes_queries() {
+        // The power query doubles as keep alive packet.
+        assert_eq!(encode_query(Query::Power), padded(&[0xAA, 0x01], 0xAB));
+        assert_eq!(encode_query(Query::Brightness), padded(&[0xAA, 0x04], 0xAE));
+        assert_eq!(encode_query(Query::Mode), padded(&[0xAA, 0x05], 0xAF));
+    }
+
+    #[test]
+    fn encodes_color_and_brightness() {
+        let color = Command::Mode(Mode::Color(Color::new(0xFF, 0x88, 0x00)));
+        assert_eq!(
+            encode(&color),
+            padded(&[0x33, 0x05, 0x02, 0xFF, 0x88, 0x00], 0x43)
+        );
+        assert_eq!(
+            encode(&Command::Brightness(0x80)),
+            padded(&[0x33, 0x04, 0x80], 0xB7)
+        );
+    }
+
+    #[test]
+    fn encodes_scene_music_and_diy() {
+        assert_eq!(
+            encode(&Command::Mode(Mode::Scene(Scene::Movie))),
+            padded(&[0x33, 0x05, 0x04, 0x04], 0x36)
+        );
+        assert_eq!(
+            encode(&Command::Mode(Mode::Music(MusicMode::Spectrum))),
+            padded(&[0x33, 0x05, 0x01, 0x01], 0x36)
+        );
+
+        let diy = Diy {
+            style: DiyStyle::Jumping,
+            speed: 0x32,
+            colors: vec![Color::new(0xFF, 0, 0), Color::new(0, 0, 0xFF)],
+        };
+        assert_eq!(
+            encode(&Command::Mode(Mode::Diy(diy))),
+            padded(
+                &[0x33, 0x05, 0x0A, 0x01, 0x32, 0x02, 0xFF, 0x00, 0x00, 0x00, 0x00, 0xFF],
+                0x0D
+            )
+        );
+    }
+
+    #[test]
+    fn diy_keeps_only_the_colors_that_fit() {
+        let diy = Diy {
+            style: DiyStyle::Fade,
+            speed: 0,
+            colors: vec![Color::WHITE; MAX_DIY_COLORS + 2],
+        };
+        let frame = encode(&Command::Mode(Mode::Diy(diy)));
+        assert_eq!(frame[5], MAX_DIY_COLORS as u8);
+        assert!(validate(&frame).is_ok());
+    }
+
+    #[test]
+    fn decodes_status_replies() {
+        assert_eq!(
+            decode(&padded(&[0xAA, 0x01, 0x01], 0xAA)),
+            Ok(Packet::Status(Status::Power(true)))
+        );
+        assert_eq!(
+            decode(&padded(&[0xAA, 0x04, 0x40], 0xEE)),
+            Ok(Packet::Status(Status::Brightness(0x40)))
+        );
+        assert_eq!(
+            decode(&padded(&[0xAA, 0x05, 0x02, 0x12, 0x34, 0x56], 0xDD)),
+            Ok(Packet::Status(Status::Mode(Mode::Color(Color::new(
+                0x12, 0x34, 0x56
+            )))))
+        );
+    }
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let commands = [
+            Command::Power(true),
+            Command::Brightness(200),
+            Command::Mode(Mode::Scene(Scene::Snowflake)),
+            Command::Mode(Mode::Music(MusicMode::Rhythm)),
+            Command::Mode(Mode::Diy(Diy {
+                style: DiyStyle::Marquee,
+                speed: 7,
+                colors: vec![Color::new(1, 2, 3)],
+            })),
+        ];
+        for command in commands {
+            assert_eq!(decode(&encode(&command)), Ok(Packet::Command(command)));
+        }
+    }
+
+    #[test]
+    fn rejects_bad_checksum() {
+        let mut frame = encode(&Command::Power(true));
+        frame[FRAME_LEN - 1] ^= 0xFF;
+        assert_eq!(
+            validate(&frame),
+            Err(DecodeError::InvalidChecksum {
+                expected: 0x33,
+                actual: 0xCC
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_length() {
+        let frame = encode(&Command::Power(true));
+        assert_eq!(validate(&frame[..19]), Err(DecodeError::InvalidLength(19)));
+        assert_eq!(validate(&[]), Err(DecodeError::InvalidLength(0)));
+    }
+
+    #[test]
+    fn rejects_unknown_header_and_scene() {
+        assert_eq!(
+            decode(&padded(&[0x11, 0x01], 0x10)),
+            Err(DecodeError::InvalidHeader(0x11))
+        );
+        assert_eq!(
+            decode(&padded(&[0x33, 0x05, 0x04, 0x02], 0x30)),
+            Err(DecodeError::UnknownScene(0x02))
+        );
+    }
+}
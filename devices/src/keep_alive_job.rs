@@ -1,12 +1,9 @@
-use bluer::gatt::remote::Characteristic;
 use futures::channel::oneshot;
-use log::info;
-use std::{future::Future, io, pin::Pin, sync::Arc};
-use tokio::{
-    io::{AsyncWrite, AsyncWriteExt},
-    sync::Mutex,
-    time::{self, Duration},
-};
+use log::{info, warn};
+use std::sync::Arc;
+use tokio::time::{self, Duration};
+
+use crate::transport::GattTransport;
 
 #[derive(Debug)]
 pub(crate) struct KeepAlive {
@@ -22,7 +19,7 @@ impl KeepAlive {
         }
     }
 
-    pub(crate) fn run(&mut self, characteristic: Characteristic, ev: Vec<u8>) {
+    pub(crate) fn run(&mut self, transport: Arc<dyn GattTransport>, ev: Vec<u8>) {
         let mut interval = time::interval(self.interval);
 
         let (abort_tx, mut abort_rx) = oneshot::channel();
@@ -33,7 +30,10 @@ impl KeepAlive {
                 tokio::select! {
                     _ = interval.tick() => {
                         info!("Send keep alive");
-                        characteristic.write(&ev).await.unwrap()
+                        if let Err(e) = transport.write(&ev).await {
+                            warn!("Failed to send keep alive, stopping: {e}");
+                            return;
+                        }
                     }
                     _ = &mut abort_rx => {
                         info!("Kill keep alive cycle");
@@ -1,32 +1,117 @@
+pub mod backoff;
+pub mod color;
+pub mod discovery;
+pub mod effects;
+mod error;
 pub mod esp;
 pub mod govee;
 mod keep_alive_job;
+pub mod transport;
 
+pub use color::{Color, ParseColorError};
+pub use error::{Error, Result};
 use esp::EspLed;
-use govee::GoveeLed;
+use govee::{
+    protocol::{Diy, Mode, MusicMode, Scene},
+    GoveeLed,
+};
 
 use async_trait::async_trait;
-use bluer::{gatt::remote::Characteristic, AdapterEvent, Address, Device, Uuid};
+use bluer::{
+    gatt::remote::Characteristic, Adapter, AdapterEvent, Address, Device, DeviceEvent,
+    DeviceProperty, Session, Uuid,
+};
 use futures::{pin_mut, StreamExt};
-use log::{debug, error, info};
-use std::io::{self, Error, ErrorKind};
+use log::info;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{watch, OnceCell},
+    time::{self, Duration},
+};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Event {
     On,
     Off,
-    Color(String),
+    Color(Color),
     Brightness(u8),
+    Scene(Scene),
+    Music(MusicMode),
+    Diy(Diy),
     Other(Option<String>),
 }
 
+/// What a led strip is showing, `None` where it is unknown.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DeviceState {
+    pub connected: bool,
+    pub power: Option<bool>,
+    pub brightness: Option<u8>,
+    pub mode: Option<Mode>,
+}
+
+impl DeviceState {
+    /// Updates the state as if `event` was applied successfully.
+    pub fn apply_event(&mut self, event: &Event) {
+        match event {
+            Event::On => self.power = Some(true),
+            Event::Off => self.power = Some(false),
+            Event::Color(color) => self.mode = Some(Mode::Color(*color)),
+            Event::Brightness(brightness) => self.brightness = Some(*brightness),
+            Event::Scene(scene) => self.mode = Some(Mode::Scene(*scene)),
+            Event::Music(mode) => self.mode = Some(Mode::Music(*mode)),
+            Event::Diy(diy) => self.mode = Some(Mode::Diy(diy.clone())),
+            Event::Other(_) => {}
+        }
+    }
+
+    /// Events that bring a strip into this state.
+    pub fn events(&self) -> Vec<Event> {
+        if self.power == Some(false) {
+            return vec![Event::Off];
+        }
+
+        let mut events = Vec::new();
+        if self.power == Some(true) {
+            events.push(Event::On);
+        }
+        if let Some(brightness) = self.brightness {
+            events.push(Event::Brightness(brightness));
+        }
+        if let Some(mode) = &self.mode {
+            events.push(mode.clone().into());
+        }
+        events
+    }
+}
+
+impl From<Mode> for Event {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Color(color) => Event::Color(color),
+            Mode::Scene(scene) => Event::Scene(scene),
+            Mode::Music(mode) => Event::Music(mode),
+            Mode::Diy(diy) => Event::Diy(diy),
+        }
+    }
+}
+
 #[async_trait]
 pub trait LedDevice {
-    async fn connect(&mut self) -> io::Result<()>;
+    async fn connect(&mut self) -> Result<()>;
+
+    async fn disconnect(&mut self) -> Result<()>;
+
+    async fn on_event(&mut self, event: Event) -> Result<()>;
+
+    async fn state(&mut self) -> Result<DeviceState>;
 
-    async fn disconnect(&mut self) -> io::Result<()>;
+    /// The bluer device while connected, to watch for connection changes.
+    fn bluetooth_device(&self) -> Option<Device>;
 
-    async fn on_event(&mut self, event: Event) -> io::Result<()>;
+    /// Forgets the connection after the strip dropped it, without talking to the strip.
+    fn mark_disconnected(&mut self);
 }
 
 #[derive(Debug, device_macro::Devices)]
@@ -35,9 +120,59 @@ pub enum Devices {
     Esp(EspLed),
 }
 
-async fn discover_device(device_addr: Address) -> bluer::Result<Option<Device>> {
-    let session = bluer::Session::new().await?;
-    let adapter = session.default_adapter().await?;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum DeviceKind {
+    Govee,
+    Esp,
+}
+
+impl Devices {
+    pub fn kind(&self) -> DeviceKind {
+        match self {
+            Devices::Govee(_) => DeviceKind::Govee,
+            Devices::Esp(_) => DeviceKind::Esp,
+        }
+    }
+
+    /// Changes the strip reports by itself, for strips that notify their state.
+    pub fn watch_state(&self) -> Option<watch::Receiver<DeviceState>> {
+        match self {
+            Devices::Govee(led) => Some(led.watch_state()),
+            Devices::Esp(_) => None,
+        }
+    }
+}
+
+static SESSION: OnceCell<Session> = OnceCell::const_new();
+
+/// The bluer session shared by all devices of the process.
+pub async fn session() -> bluer::Result<&'static Session> {
+    SESSION.get_or_try_init(Session::new).await
+}
+
+/// Adapter `name` (like `hci1`), or the default adapter.
+pub async fn adapter(name: Option<&str>) -> bluer::Result<Adapter> {
+    let session = session().await?;
+    match name {
+        Some(name) => session.adapter(name),
+        None => session.default_adapter().await,
+    }
+}
+
+pub const DEFAULT_DISCOVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long BlueZ gets to establish a connection to a discovered device.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Discovers `device_addr`, giving up with [`Error::NotFound`] after `timeout`.
+async fn discover_device(
+    adapter_name: Option<&str>,
+    device_addr: Address,
+    timeout: Duration,
+) -> Result<Device> {
+    let adapter = adapter(adapter_name).await?;
     adapter.set_powered(true).await?;
 
     info!(
@@ -49,49 +184,67 @@ async fn discover_device(device_addr: Address) -> bluer::Result<Option<Device>>
     let discover = adapter.discover_devices().await?;
     pin_mut!(discover);
 
-    while let Some(evt) = discover.next().await {
-        match evt {
-            AdapterEvent::DeviceAdded(addr) => {
-                let device = adapter.device(addr)?;
-                let addr = device.address();
+    let found = time::timeout(timeout, async {
+        while let Some(evt) = discover.next().await {
+            match evt {
+                AdapterEvent::DeviceAdded(addr) => {
+                    let device = adapter.device(addr)?;
+                    let addr = device.address();
 
-                info!("{}", addr);
+                    info!("{}", addr);
 
-                if addr == device_addr {
-                    info!("Found led device on {device_addr}");
+                    if addr == device_addr {
+                        info!("Found led device on {device_addr}");
 
-                    return Ok(Some(device));
+                        return Ok(device);
+                    }
                 }
+                AdapterEvent::DeviceRemoved(_addr) => {
+                    // info!("Device removed {addr}");
+                }
+                _ => (),
             }
-            AdapterEvent::DeviceRemoved(_addr) => {
-                // info!("Device removed {addr}");
-            }
-            _ => (),
+        }
+
+        Err(Error::NotFound(device_addr))
+    })
+    .await;
+
+    match found {
+        Ok(found) => found,
+        Err(_) => {
+            info!("Gave up discovering {device_addr} after {timeout:?}");
+            Err(Error::NotFound(device_addr))
         }
     }
+}
 
-    Ok(None)
+async fn connect_device(device: &Device) -> Result<()> {
+    if device.is_connected().await? {
+        return Ok(());
+    }
+
+    time::timeout(CONNECT_TIMEOUT, device.connect())
+        .await
+        .map_err(|_| Error::Timeout(format!("connecting to {}", device.address())))??;
+    info!("Successfully connected to {}", device.address());
+
+    Ok(())
 }
 
-const MAX_CONNECT_RETRIES: i32 = 2;
+/// Resolves once `device` is disconnected, or removed by BlueZ.
+pub async fn wait_for_disconnect(device: &Device) -> bluer::Result<()> {
+    let events = device.events().await?;
+    pin_mut!(events);
 
-async fn connect_device(device: &Device) -> bluer::Result<()> {
-    if device.is_connected().await? {
+    // Don't wait for an event that happened before subscribing.
+    if !device.is_connected().await? {
         return Ok(());
     }
 
-    let mut retries = 0;
-    loop {
-        match device.connect().await {
-            Ok(()) => {
-                info!("Successfully connected to {}", device.address());
-                break
-            },
-            Err(err) if retries <= MAX_CONNECT_RETRIES => {
-                info!("Error while connecting to {}: {}", device.address(), &err);
-                retries += 1;
-            }
-            Err(err) => return Err(err),
+    while let Some(DeviceEvent::PropertyChanged(property)) = events.next().await {
+        if let DeviceProperty::Connected(false) = property {
+            return Ok(());
         }
     }
 
@@ -0,0 +1,173 @@
+use async_trait::async_trait;
+use bluer::gatt::{
+    remote::{Characteristic, CharacteristicWriteRequest},
+    WriteOp,
+};
+use futures::{channel::mpsc, Stream, StreamExt};
+use std::{
+    fmt::Debug,
+    io::{self, Error, ErrorKind},
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+pub type Notifications = Pin<Box<dyn Stream<Item = Vec<u8>> + Send>>;
+
+/// Link to the GATT characteristic(s) a led device is driven through.
+#[async_trait]
+pub trait GattTransport: Debug + Send + Sync {
+    async fn write(&self, value: &[u8]) -> io::Result<()>;
+
+    async fn write_without_response(&self, value: &[u8]) -> io::Result<()>;
+
+    async fn read(&self) -> io::Result<Vec<u8>>;
+
+    async fn notify(&self) -> io::Result<Notifications>;
+}
+
+/// Default transport, backed by a characteristic of a connected bluer `Device`.
+#[derive(Debug, Clone)]
+pub struct BluerTransport {
+    characteristic: Characteristic,
+    notify_characteristic: Option<Characteristic>,
+}
+
+impl BluerTransport {
+    pub fn new(characteristic: Characteristic) -> Self {
+        Self {
+            characteristic,
+            notify_characteristic: None,
+        }
+    }
+
+    /// Subscribe to a different characteristic than the one that is written to.
+    pub fn with_notify_characteristic(mut self, characteristic: Characteristic) -> Self {
+        self.notify_characteristic = Some(characteristic);
+        self
+    }
+}
+
+#[async_trait]
+impl GattTransport for BluerTransport {
+    async fn write(&self, value: &[u8]) -> io::Result<()> {
+        Ok(self.characteristic.write(value).await?)
+    }
+
+    async fn write_without_response(&self, value: &[u8]) -> io::Result<()> {
+        let req = CharacteristicWriteRequest {
+            op_type: WriteOp::Command,
+            ..Default::default()
+        };
+        Ok(self.characteristic.write_ext(value, &req).await?)
+    }
+
+    async fn read(&self) -> io::Result<Vec<u8>> {
+        Ok(self.characteristic.read().await?)
+    }
+
+    async fn notify(&self) -> io::Result<Notifications> {
+        let characteristic = self
+            .notify_characteristic
+            .as_ref()
+            .unwrap_or(&self.characteristic);
+        Ok(characteristic.notify().await?.boxed())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrittenFrame {
+    pub value: Vec<u8>,
+    pub with_response: bool,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    written: Vec<WrittenFrame>,
+    read_value: Vec<u8>,
+    subscribers: Vec<mpsc::UnboundedSender<Vec<u8>>>,
+    fail_writes: bool,
+}
+
+/// In-memory transport that records every written frame and lets notifications be injected,
+/// so devices can be driven without a Bluetooth adapter.
+///
+/// Clones share the same state, keep one to inspect what a device wrote.
+#[derive(Debug, Clone, Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn written(&self) -> Vec<WrittenFrame> {
+        self.state.lock().unwrap().written.clone()
+    }
+
+    /// Values of all written frames, regardless of write type.
+    pub fn frames(&self) -> Vec<Vec<u8>> {
+        self.written().into_iter().map(|w| w.value).collect()
+    }
+
+    pub fn take_frames(&self) -> Vec<Vec<u8>> {
+        let written = std::mem::take(&mut self.state.lock().unwrap().written);
+        written.into_iter().map(|w| w.value).collect()
+    }
+
+    pub fn clear(&self) {
+        self.state.lock().unwrap().written.clear();
+    }
+
+    pub fn set_read_value(&self, value: Vec<u8>) {
+        self.state.lock().unwrap().read_value = value;
+    }
+
+    /// Make every following write fail, like a device that went out of range.
+    pub fn set_fail_writes(&self, fail: bool) {
+        self.state.lock().unwrap().fail_writes = fail;
+    }
+
+    /// Deliver `value` to every open notification stream.
+    pub fn inject_notification(&self, value: Vec<u8>) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .subscribers
+            .retain(|tx| tx.unbounded_send(value.clone()).is_ok());
+    }
+
+    fn record(&self, value: &[u8], with_response: bool) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.fail_writes {
+            return Err(Error::new(ErrorKind::NotConnected, "Mock write failed"));
+        }
+
+        state.written.push(WrittenFrame {
+            value: value.to_vec(),
+            with_response,
+        });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl GattTransport for MockTransport {
+    async fn write(&self, value: &[u8]) -> io::Result<()> {
+        self.record(value, true)
+    }
+
+    async fn write_without_response(&self, value: &[u8]) -> io::Result<()> {
+        self.record(value, false)
+    }
+
+    async fn read(&self) -> io::Result<Vec<u8>> {
+        Ok(self.state.lock().unwrap().read_value.clone())
+    }
+
+    async fn notify(&self) -> io::Result<Notifications> {
+        let (tx, rx) = mpsc::unbounded();
+        self.state.lock().unwrap().subscribers.push(tx);
+        Ok(rx.boxed())
+    }
+}
@@ -0,0 +1,262 @@
+//! Drives the devices through `MockTransport` and checks the frames they write.
+
+use bluer::Address;
+use devices::{
+    esp::{self, EspLed},
+    govee::{
+        self,
+        protocol::{Diy, DiyStyle, Mode, MusicMode, Scene},
+        GoveeLed,
+    },
+    transport::MockTransport,
+    Color, DeviceState, Error, Event, LedDevice,
+};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::time;
+
+const ADDR: Address = Address::new([0xA4, 0xC1, 0x38, 0xEC, 0x91, 0x32]);
+
+fn govee() -> (GoveeLed, MockTransport) {
+    let mock = MockTransport::new();
+    let mut led = GoveeLed::new(ADDR, govee::SERVICE_UUID, govee::CHARACTERISTIC_UUID);
+    led.attach_transport(Arc::new(mock.clone()));
+    (led, mock)
+}
+
+fn esp() -> (EspLed, MockTransport) {
+    let mock = MockTransport::new();
+    let mut led = EspLed::new(ADDR, esp::SERVICE_UUID, esp::CHARACTERISTIC_UUID);
+    led.attach_transport(Arc::new(mock.clone()));
+    (led, mock)
+}
+
+/// Written commands, leaving out the keep alive queries running alongside.
+fn commands(mock: &MockTransport) -> Vec<Vec<u8>> {
+    mock.frames()
+        .into_iter()
+        .filter(|frame| frame[0] != 0xAA)
+        .collect()
+}
+
+/// The 20-byte frame starting with `prefix`, ending with `xor`.
+fn frame(prefix: &[u8], xor: u8) -> Vec<u8> {
+    let mut frame = vec![0; 20];
+    frame[..prefix.len()].copy_from_slice(prefix);
+    frame[19] = xor;
+    frame
+}
+
+#[tokio::test]
+async fn govee_writes_a_frame_for_every_event() {
+    let diy = Diy {
+        style: DiyStyle::Fade,
+        speed: 0,
+        colors: vec![Color::new(0xFF, 0, 0)],
+    };
+    let cases = [
+        (Event::On, frame(&[0x33, 0x01, 0x01], 0x33)),
+        (Event::Off, frame(&[0x33, 0x01, 0x00], 0x32)),
+        (
+            Event::Color(Color::new(0xFF, 0x88, 0x00)),
+            frame(&[0x33, 0x05, 0x02, 0xFF, 0x88, 0x00], 0x43),
+        ),
+        (Event::Brightness(0x80), frame(&[0x33, 0x04, 0x80], 0xB7)),
+        (
+            Event::Scene(Scene::Movie),
+            frame(&[0x33, 0x05, 0x04, 0x04], 0x36),
+        ),
+        (
+            Event::Music(MusicMode::Spectrum),
+            frame(&[0x33, 0x05, 0x01, 0x01], 0x36),
+        ),
+        (
+            Event::Diy(diy),
+            frame(
+                &[0x33, 0x05, 0x0A, 0x00, 0x00, 0x01, 0xFF, 0x00, 0x00],
+                0xC2,
+            ),
+        ),
+    ];
+
+    let (mut led, mock) = govee();
+    for (event, expected) in cases {
+        mock.clear();
+        led.on_event(event.clone()).await.unwrap();
+        assert_eq!(commands(&mock), vec![expected], "{:?}", event);
+    }
+
+    mock.clear();
+    led.on_event(Event::Other(None)).await.unwrap();
+    assert!(commands(&mock).is_empty());
+}
+
+#[tokio::test]
+async fn govee_writes_with_response() {
+    let (mut led, mock) = govee();
+    led.on_event(Event::On).await.unwrap();
+    assert!(mock.written().iter().all(|written| written.with_response));
+}
+
+#[tokio::test]
+async fn govee_keeps_the_connection_alive() {
+    let (_led, mock) = govee();
+    let keep_alive = frame(&[0xAA, 0x01], 0xAB);
+    // The first query goes out as soon as the keep alive task runs.
+    let sent = time::timeout(Duration::from_secs(1), async {
+        while !mock.frames().contains(&keep_alive) {
+            tokio::task::yield_now().await;
+        }
+    });
+    assert!(sent.await.is_ok());
+}
+
+/// Answers the status queries of `led.state()` with `replies`, once they are all written.
+async fn state_answered_with(
+    led: &mut GoveeLed,
+    mock: &MockTransport,
+    replies: Vec<Vec<u8>>,
+) -> (DeviceState, Duration) {
+    let strip = mock.clone();
+    let mode_query = frame(&[0xAA, 0x05], 0xAF);
+    tokio::spawn(async move {
+        while !strip.frames().contains(&mode_query) {
+            tokio::task::yield_now().await;
+        }
+        for reply in replies {
+            strip.inject_notification(reply);
+        }
+    });
+
+    let started = Instant::now();
+    let state = led.state().await.unwrap();
+    (state, started.elapsed())
+}
+
+#[tokio::test]
+async fn govee_state_returns_once_every_query_is_answered() {
+    let (mut led, mock) = govee();
+    let replies = vec![
+        frame(&[0xAA, 0x05, 0x02, 0x12, 0x34, 0x56], 0xDD),
+        frame(&[0xAA, 0x01, 0x01], 0xAA),
+        frame(&[0xAA, 0x04, 0x40], 0xEE),
+    ];
+    let (state, elapsed) = state_answered_with(&mut led, &mock, replies).await;
+
+    assert!(elapsed < Duration::from_millis(500), "{:?}", elapsed);
+    assert!(state.connected);
+    assert_eq!(state.power, Some(true));
+    assert_eq!(state.brightness, Some(0x40));
+    assert_eq!(state.mode, Some(Mode::Color(Color::new(0x12, 0x34, 0x56))));
+}
+
+#[tokio::test]
+async fn govee_state_does_not_count_repeated_power_replies() {
+    // Keep alive queries are answered with power replies as well.
+    let (mut led, mock) = govee();
+    let power = frame(&[0xAA, 0x01, 0x01], 0xAA);
+    let replies = vec![power.clone(), power.clone(), power];
+    let (state, elapsed) = state_answered_with(&mut led, &mock, replies).await;
+
+    assert!(elapsed >= Duration::from_millis(900), "{:?}", elapsed);
+    assert_eq!(state.power, Some(true));
+    assert_eq!(state.brightness, None);
+    assert_eq!(state.mode, None);
+}
+
+#[tokio::test]
+async fn govee_reports_notified_changes() {
+    let (led, mock) = govee();
+    let mut updates = led.watch_state();
+    let brightness = frame(&[0xAA, 0x04, 0x40], 0xEE);
+
+    // Notifications sent before the device subscribed are lost, like on a real strip.
+    let notified = time::timeout(Duration::from_secs(1), async {
+        loop {
+            mock.inject_notification(brightness.clone());
+            if time::timeout(Duration::from_millis(10), updates.changed())
+                .await
+                .is_ok()
+            {
+                break;
+            }
+        }
+    });
+    assert!(notified.await.is_ok());
+    assert_eq!(updates.borrow_and_update().brightness, Some(0x40));
+
+    mock.inject_notification(brightness);
+    let repeated = time::timeout(Duration::from_millis(50), updates.changed()).await;
+    assert!(repeated.is_err(), "an unchanged state was reported");
+}
+
+#[tokio::test]
+async fn govee_rejects_diy_without_colors() {
+    let (mut led, mock) = govee();
+    let diy = Diy {
+        style: DiyStyle::Fade,
+        speed: 0,
+        colors: Vec::new(),
+    };
+    let result = led.on_event(Event::Diy(diy)).await;
+    assert!(matches!(result, Err(Error::InvalidCommand(_))));
+    assert!(commands(&mock).is_empty());
+}
+
+#[tokio::test]
+async fn esp_writes_a_frame_for_every_event() {
+    let cases = [
+        (Event::On, vec![0x01, 0x01]),
+        (Event::Off, vec![0x01, 0x00]),
+        (Event::Brightness(0x80), vec![0x02, 0x80]),
+        (Event::Color(Color::new(1, 2, 3)), vec![0x03, 1, 2, 3]),
+    ];
+
+    let (mut led, mock) = esp();
+    for (event, expected) in cases {
+        mock.clear();
+        led.on_event(event.clone()).await.unwrap();
+        assert_eq!(mock.frames(), vec![expected], "{:?}", event);
+    }
+
+    let state = led.state().await.unwrap();
+    assert_eq!(state.power, Some(false));
+    assert_eq!(state.brightness, Some(0x80));
+}
+
+#[tokio::test]
+async fn esp_rejects_govee_modes() {
+    let (mut led, mock) = esp();
+    for event in [Event::Scene(Scene::Sunset), Event::Music(MusicMode::Rhythm)] {
+        let result = led.on_event(event).await;
+        assert!(matches!(result, Err(Error::InvalidCommand(_))));
+    }
+    assert!(mock.frames().is_empty());
+}
+
+#[tokio::test]
+async fn failed_writes_are_errors() {
+    let (mut led, mock) = govee();
+    mock.set_fail_writes(true);
+    assert!(matches!(led.on_event(Event::On).await, Err(Error::Ble(_))));
+
+    let (mut led, mock) = esp();
+    mock.set_fail_writes(true);
+    assert!(matches!(led.on_event(Event::On).await, Err(Error::Ble(_))));
+    assert_eq!(led.state().await.unwrap().power, None);
+
+    mock.set_fail_writes(false);
+    led.on_event(Event::On).await.unwrap();
+    assert_eq!(mock.frames(), vec![vec![0x01, 0x01]]);
+}
+
+#[tokio::test]
+async fn devices_without_transport_are_not_connected() {
+    let mut led = GoveeLed::new(ADDR, govee::SERVICE_UUID, govee::CHARACTERISTIC_UUID);
+    assert!(matches!(
+        led.on_event(Event::On).await,
+        Err(Error::NotConnected(_))
+    ));
+}
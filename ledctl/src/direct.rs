@@ -0,0 +1,81 @@
+//! Drives a strip over Bluetooth from this process, for when no server is running.
+
+use devices::{
+    discovery::{self, DiscoveryOptions},
+    esp::{self, EspLed},
+    govee::{self, GoveeLed},
+    DeviceKind, Devices, LedDevice,
+};
+use std::time::Duration;
+
+use crate::{print_scanned, Cli, Command, Scanned, SetArgs};
+
+pub async fn run(cli: Cli) -> Result<(), String> {
+    let adapter = cli.adapter.as_deref();
+    match cli.command {
+        Command::Set(args) => set(adapter, args).await,
+        Command::Scan { timeout_secs } => {
+            let options = DiscoveryOptions {
+                timeout: Duration::from_secs(timeout_secs),
+                ..Default::default()
+            };
+            let devices = discovery::scan(adapter, &options)
+                .await
+                .map_err(|e| format!("Scan failed: {}", e))?
+                .into_iter()
+                .map(|device| Scanned {
+                    address: device.address.to_string(),
+                    name: device.name,
+                    rssi: device.rssi,
+                    kind: device.kind,
+                })
+                .collect::<Vec<_>>();
+            print_scanned(&devices);
+            Ok(())
+        }
+        _ => Err("Only set and scan work with --direct, the others need a server".to_string()),
+    }
+}
+
+async fn set(adapter: Option<&str>, args: SetArgs) -> Result<(), String> {
+    let events = args.events()?;
+    let kind = args
+        .kind
+        .ok_or("--direct needs the --kind of the strip, govee or esp")?;
+    if args.transition_ms.is_some() {
+        return Err("Transitions are run by the server, leave out --direct".to_string());
+    }
+
+    let mut device = match kind {
+        DeviceKind::Govee => {
+            let mut led = GoveeLed::new(
+                args.address,
+                govee::SERVICE_UUID,
+                govee::CHARACTERISTIC_UUID,
+            );
+            if let Some(adapter) = adapter {
+                led = led.with_adapter(adapter);
+            }
+            Devices::Govee(led)
+        }
+        DeviceKind::Esp => {
+            let mut led = EspLed::new(args.address, esp::SERVICE_UUID, esp::CHARACTERISTIC_UUID);
+            if let Some(adapter) = adapter {
+                led = led.with_adapter(adapter);
+            }
+            Devices::Esp(led)
+        }
+    };
+
+    device.connect().await.map_err(|e| e.to_string())?;
+    let mut result = Ok(());
+    for event in events {
+        result = device.on_event(event).await;
+        if result.is_err() {
+            break;
+        }
+    }
+    // Disconnects even after a failed event, so the strip is free for the next client.
+    let disconnected = device.disconnect().await;
+    result.and(disconnected).map_err(|e| e.to_string())
+}
@@ -0,0 +1,169 @@
+mod direct;
+mod server;
+
+use bluer::Address;
+use clap::{Args, Parser, Subcommand};
+use devices::{Color, DeviceKind, Event};
+use serde::Deserialize;
+
+#[derive(Debug, Parser)]
+#[command(about = "Control led strips through a gatt server, or directly over Bluetooth")]
+struct Cli {
+    /// URL of the gatt server.
+    #[arg(
+        long,
+        global = true,
+        env = "LEDCTL_SERVER",
+        default_value = "http://localhost:3000"
+    )]
+    server: String,
+    /// Talk to the strip over Bluetooth instead of through a server, for `set` and `scan`.
+    #[arg(long, global = true)]
+    direct: bool,
+    /// Bluetooth adapter to use with `--direct`, like `hci1`.
+    #[arg(long, global = true)]
+    adapter: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// List the registered devices and whether they are connected.
+    List,
+    /// Connect the server to a device.
+    Connect { address: Address },
+    /// Disconnect the server from a device.
+    Disconnect { address: Address },
+    /// Set power, color and brightness of a device, in that order.
+    Set(SetArgs),
+    /// Snapshots of several devices saved on the server.
+    #[command(subcommand)]
+    Scene(SceneCommand),
+    /// List the supported strips nearby.
+    Scan {
+        /// Seconds to scan for.
+        #[arg(long, default_value_t = 5)]
+        timeout_secs: u64,
+    },
+}
+
+#[derive(Debug, Args)]
+struct SetArgs {
+    address: Address,
+    #[arg(long, conflicts_with = "off")]
+    on: bool,
+    #[arg(long)]
+    off: bool,
+    /// A hex color like `#ff8800`, a color name or a temperature like `2700k`.
+    #[arg(long)]
+    color: Option<Color>,
+    #[arg(long)]
+    brightness: Option<u8>,
+    /// Fades color and brightness over this many milliseconds, only through a server.
+    #[arg(long)]
+    transition_ms: Option<u64>,
+    /// The kind of the strip, `govee` or `esp`, needed with `--direct`.
+    #[arg(long, value_parser = parse_kind)]
+    kind: Option<DeviceKind>,
+}
+
+impl SetArgs {
+    /// The events that carry out the command, in order.
+    fn events(&self) -> Result<Vec<Event>, String> {
+        let mut events = Vec::new();
+        if self.on {
+            events.push(Event::On);
+        }
+        if self.off {
+            events.push(Event::Off);
+        }
+        if let Some(color) = self.color {
+            events.push(Event::Color(color));
+        }
+        if let Some(brightness) = self.brightness {
+            events.push(Event::Brightness(brightness));
+        }
+
+        match events.is_empty() {
+            true => Err("Nothing to set, pass --on, --off, --color or --brightness".to_string()),
+            false => Ok(events),
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum SceneCommand {
+    /// List the saved snapshots.
+    List,
+    /// Bring every device of a snapshot back to its saved state.
+    Recall {
+        name: String,
+        /// Fades color and brightness over this many milliseconds.
+        #[arg(long)]
+        transition_ms: Option<u64>,
+    },
+}
+
+fn parse_kind(kind: &str) -> Result<DeviceKind, String> {
+    match kind.to_ascii_lowercase().as_str() {
+        "govee" => Ok(DeviceKind::Govee),
+        "esp" => Ok(DeviceKind::Esp),
+        _ => Err(format!("Unknown kind {:?}, expected govee or esp", kind)),
+    }
+}
+
+/// A strip found by a scan, as the server reports it.
+#[derive(Debug, Deserialize)]
+struct Scanned {
+    address: String,
+    name: Option<String>,
+    rssi: Option<i16>,
+    kind: Option<DeviceKind>,
+}
+
+fn print_scanned(devices: &[Scanned]) {
+    for device in devices {
+        let kind = match device.kind {
+            Some(DeviceKind::Govee) => "govee",
+            Some(DeviceKind::Esp) => "esp",
+            None => "-",
+        };
+        let rssi = device
+            .rssi
+            .map_or("-".to_string(), |rssi| format!("{} dBm", rssi));
+        println!(
+            "{}  {:<6} {:>8}  {}",
+            device.address,
+            kind,
+            rssi,
+            device.name.as_deref().unwrap_or("")
+        );
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+
+    let cli = Cli::parse();
+    let result = match cli.direct {
+        true => direct::run(cli).await,
+        false => server::run(cli).await,
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn cli_is_valid() {
+        Cli::command().debug_assert();
+    }
+}
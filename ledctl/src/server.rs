@@ -0,0 +1,208 @@
+//! Carries out commands through the JSON API of a gatt server.
+
+use bluer::Address;
+use reqwest::{Client, Method, RequestBuilder, Response, Url};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+
+use crate::{print_scanned, Cli, Command, Scanned, SceneCommand, SetArgs};
+
+/// The body of a failed request.
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviceStatus {
+    address: String,
+    name: String,
+    room: Option<String>,
+    status: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Snapshot {
+    name: String,
+    devices: serde_json::Map<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MemberResult {
+    address: String,
+    error: Option<ErrorBody>,
+}
+
+struct Server {
+    client: Client,
+    url: Url,
+}
+
+impl Server {
+    fn new(url: &str) -> Result<Self, String> {
+        let url = Url::parse(url).map_err(|e| format!("Invalid server URL {:?}: {}", url, e))?;
+        if url.cannot_be_a_base() {
+            return Err(format!("Invalid server URL {:?}", url.as_str()));
+        }
+        Ok(Self {
+            client: Client::new(),
+            url,
+        })
+    }
+
+    /// A request to the API path made of `segments`, each percent-encoded as needed.
+    fn request(&self, method: Method, segments: &[&str]) -> RequestBuilder {
+        let mut url = self.url.clone();
+        url.path_segments_mut()
+            .expect("checked in Server::new")
+            .pop_if_empty()
+            .push("api")
+            .extend(segments);
+        self.client.request(method, url)
+    }
+
+    /// Sends `request`, turning error responses into their message.
+    async fn send(&self, request: RequestBuilder) -> Result<Response, String> {
+        let response = request.send().await.map_err(|e| {
+            format!(
+                "Failed to reach the server at {}, is it running? Use --direct without one: {}",
+                self.url, e
+            )
+        })?;
+        if response.status().is_success() {
+            return Ok(response);
+        }
+
+        let status = response.status();
+        match response.json::<ErrorBody>().await {
+            Ok(body) => Err(body.message),
+            Err(_) => Err(format!("The server answered {}", status)),
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, String> {
+        self.send(request)
+            .await?
+            .json()
+            .await
+            .map_err(|e| format!("Invalid response from the server: {}", e))
+    }
+}
+
+pub async fn run(cli: Cli) -> Result<(), String> {
+    let server = Server::new(&cli.server)?;
+
+    match cli.command {
+        Command::List => {
+            let devices: Vec<DeviceStatus> = server
+                .get(server.request(Method::GET, &["v2", "devices"]))
+                .await?;
+            for device in devices {
+                let room = device
+                    .room
+                    .map_or(String::new(), |room| format!(" ({})", room));
+                println!(
+                    "{}  {:<12} {}{}",
+                    device.address, device.status, device.name, room
+                );
+            }
+            Ok(())
+        }
+        Command::Connect { address } => connection(&server, address, "connect").await,
+        Command::Disconnect { address } => connection(&server, address, "disconnect").await,
+        Command::Set(args) => set(&server, args).await,
+        Command::Scene(SceneCommand::List) => {
+            let snapshots: Vec<Snapshot> = server
+                .get(server.request(Method::GET, &["v2", "snapshots"]))
+                .await?;
+            for snapshot in snapshots {
+                println!("{}  ({} devices)", snapshot.name, snapshot.devices.len());
+            }
+            Ok(())
+        }
+        Command::Scene(SceneCommand::Recall {
+            name,
+            transition_ms,
+        }) => {
+            let mut request = server.request(Method::POST, &["v2", "snapshots", &name, "recall"]);
+            if let Some(transition_ms) = transition_ms {
+                request = request.query(&[("transition_ms", transition_ms)]);
+            }
+            let results: Vec<MemberResult> = server.get(request).await?;
+
+            let mut failed = 0;
+            for result in results {
+                match result.error {
+                    Some(error) => {
+                        failed += 1;
+                        println!("{}  failed: {}", result.address, error.message);
+                    }
+                    None => println!("{}  ok", result.address),
+                }
+            }
+            match failed {
+                0 => Ok(()),
+                _ => Err(format!("{} devices failed", failed)),
+            }
+        }
+        Command::Scan { timeout_secs } => {
+            let request = server
+                .request(Method::GET, &["scan"])
+                .query(&[("timeout_secs", timeout_secs)]);
+            let devices: Vec<Scanned> = server.get(request).await?;
+            print_scanned(&devices);
+            Ok(())
+        }
+    }
+}
+
+async fn connection(server: &Server, address: Address, action: &str) -> Result<(), String> {
+    let address = address.to_string();
+    let request = server.request(Method::POST, &["v2", "devices", &address, action]);
+    server.send(request).await?;
+    Ok(())
+}
+
+async fn set(server: &Server, args: SetArgs) -> Result<(), String> {
+    if args.kind.is_some() {
+        return Err("--kind is only used with --direct, the server knows its devices".to_string());
+    }
+
+    // Sent as one command so color and brightness fade together.
+    args.events()?;
+    let command = json!({
+        "type": "set",
+        "on": (args.on || args.off).then_some(args.on),
+        "color": args.color,
+        "brightness": args.brightness,
+        "transition_ms": args.transition_ms,
+    });
+    let address = args.address.to_string();
+    let path = ["v2", "devices", &address, "commands"];
+    server
+        .send(server.request(Method::POST, &path).json(&command))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_path_segments() {
+        let server = Server::new("http://localhost:3000/gatt/").unwrap();
+        let request = server
+            .request(
+                Method::POST,
+                &["v2", "snapshots", "movie night/2?", "recall"],
+            )
+            .build()
+            .unwrap();
+        assert_eq!(
+            request.url().as_str(),
+            "http://localhost:3000/gatt/api/v2/snapshots/movie%20night%2F2%3F/recall"
+        );
+        assert!(Server::new("localhost:3000").is_err());
+    }
+}
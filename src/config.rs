@@ -0,0 +1,1010 @@
+use bluer::{Address, Uuid};
+use devices::{
+    discovery::DiscoveryOptions, esp, esp::EspLed, govee, govee::protocol::Mode, govee::GoveeLed,
+    Color, DeviceKind, DeviceState, Devices, DEFAULT_DISCOVERY_TIMEOUT,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fmt, fs, io,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub storage: StorageConfig,
+    pub bluetooth: BluetoothConfig,
+    pub discovery: DiscoveryConfig,
+    pub queue: QueueConfig,
+    pub effects: EffectsConfig,
+    /// Where the strips are, for schedules relative to sunrise and sunset.
+    pub location: Option<Location>,
+    /// `None` when the `[mqtt]` section is missing.
+    pub mqtt: Option<MqttConfig>,
+    pub devices: Vec<DeviceConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerConfig {
+    pub addr: SocketAddr,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageConfig {
+    pub data_dir: PathBuf,
+}
+
+impl StorageConfig {
+    pub fn state_file(&self) -> PathBuf {
+        self.data_dir.join("state.json")
+    }
+
+    pub fn registry_file(&self) -> PathBuf {
+        self.data_dir.join("registry.json")
+    }
+
+    pub fn schedules_file(&self) -> PathBuf {
+        self.data_dir.join("schedules.json")
+    }
+
+    pub fn snapshots_file(&self) -> PathBuf {
+        self.data_dir.join("snapshots.json")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BluetoothConfig {
+    /// Adapters to use, by name. Empty to use the default adapter.
+    pub adapters: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveryConfig {
+    /// How long connecting searches for a strip before giving up.
+    pub timeout: Duration,
+    /// Defaults for `GET /api/scan`.
+    pub scan: DiscoveryOptions,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueConfig {
+    /// Least time between two writes to the same strip.
+    pub min_write_interval: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EffectsConfig {
+    /// Time between two frames of effects and transitions that fade, at least the queue's
+    /// write interval.
+    pub frame_interval: Duration,
+}
+
+/// Degrees, north and east are positive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    /// Username and password.
+    pub credentials: Option<(String, String)>,
+    /// Where Home Assistant looks for discovery configs.
+    pub discovery_prefix: String,
+    /// Prefix of the command, state and availability topics of the devices.
+    pub base_topic: String,
+}
+
+/// What to do with a strip after connecting to it.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum PowerOnPolicy {
+    /// Apply the last state that was set through the server.
+    Restore,
+    /// Apply a fixed state.
+    Default(DeviceState),
+    /// Leave the strip in whatever state it is in.
+    #[default]
+    Untouched,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceConfig {
+    pub kind: DeviceKind,
+    pub address: Address,
+    pub name: String,
+    pub room: Option<String>,
+    pub adapter: Option<String>,
+    /// How long connecting searches for the strip before giving up.
+    pub discovery_timeout: Duration,
+    pub service_uuid: Uuid,
+    pub characteristic_uuid: Uuid,
+    pub notify_characteristic_uuid: Option<Uuid>,
+    pub power_on: PowerOnPolicy,
+}
+
+impl DeviceConfig {
+    pub fn build(&self) -> Devices {
+        match self.kind {
+            DeviceKind::Govee => {
+                let mut led =
+                    GoveeLed::new(self.address, self.service_uuid, self.characteristic_uuid)
+                        .with_discovery_timeout(self.discovery_timeout);
+                if let Some(uuid) = self.notify_characteristic_uuid {
+                    led = led.with_notify_characteristic(uuid);
+                }
+                if let Some(adapter) = &self.adapter {
+                    led = led.with_adapter(adapter);
+                }
+                Devices::Govee(led)
+            }
+            DeviceKind::Esp => {
+                let mut led =
+                    EspLed::new(self.address, self.service_uuid, self.characteristic_uuid)
+                        .with_discovery_timeout(self.discovery_timeout);
+                if let Some(adapter) = &self.adapter {
+                    led = led.with_adapter(adapter);
+                }
+                Devices::Esp(led)
+            }
+        }
+    }
+
+    /// The entry that validates back into `self`.
+    pub fn to_raw(&self) -> RawDeviceConfig {
+        let (power_on, default_state) = match &self.power_on {
+            PowerOnPolicy::Restore => (RawPowerOnPolicy::Restore, None),
+            PowerOnPolicy::Default(state) => (
+                RawPowerOnPolicy::Default,
+                Some(RawDefaultState {
+                    power: state.power,
+                    brightness: state.brightness,
+                    color: match &state.mode {
+                        Some(Mode::Color(color)) => Some(color.to_hex()),
+                        _ => None,
+                    },
+                }),
+            ),
+            PowerOnPolicy::Untouched => (RawPowerOnPolicy::Untouched, None),
+        };
+
+        RawDeviceConfig {
+            kind: self.kind,
+            address: self.address.to_string(),
+            name: Some(self.name.clone()),
+            room: self.room.clone(),
+            adapter: self.adapter.clone(),
+            service_uuid: Some(self.service_uuid.to_string()),
+            characteristic_uuid: Some(self.characteristic_uuid.to_string()),
+            notify_characteristic_uuid: self.notify_characteristic_uuid.map(|u| u.to_string()),
+            power_on,
+            default_state,
+        }
+    }
+
+    /// Whether a device built from `self` talks to the strip the same way as one built from `other`,
+    /// so it can be kept (and stay connected) when switching between them.
+    pub fn same_device(&self, other: &DeviceConfig) -> bool {
+        self.kind == other.kind
+            && self.address == other.address
+            && self.adapter == other.adapter
+            && self.discovery_timeout == other.discovery_timeout
+            && self.service_uuid == other.service_uuid
+            && self.characteristic_uuid == other.characteristic_uuid
+            && self.notify_characteristic_uuid == other.notify_characteristic_uuid
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(PathBuf, Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => {
+                write!(f, "Failed to read config {}: {}", path.display(), e)
+            }
+            ConfigError::Parse(path, e) => {
+                write!(f, "Failed to parse config {}: {}", path.display(), e)
+            }
+            ConfigError::Invalid(path, errors) => {
+                write!(f, "Invalid config {}:", path.display())?;
+                for error in errors {
+                    write!(f, "\n  - {}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let content =
+            fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_path_buf(), e))?;
+        let raw: RawConfig =
+            toml::from_str(&content).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+
+        raw.validate()
+            .map_err(|errors| ConfigError::Invalid(path.to_path_buf(), errors))
+    }
+
+    /// Validates a device that was added at runtime, as if it was listed in this config.
+    pub fn device(&self, raw: RawDeviceConfig) -> Result<DeviceConfig, Vec<String>> {
+        let mut errors = Vec::new();
+        match raw.validate(
+            "device",
+            &self.bluetooth.adapters,
+            self.discovery.timeout,
+            &mut errors,
+        ) {
+            Some(device) if errors.is_empty() => Ok(device),
+            _ => Err(errors),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Config {
+    /// Parses and validates `content`, panicking on errors.
+    pub fn from_toml(content: &str) -> Config {
+        let raw: RawConfig = toml::from_str(content).unwrap();
+        raw.validate().unwrap()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    #[serde(default)]
+    server: RawServerConfig,
+    #[serde(default)]
+    storage: RawStorageConfig,
+    #[serde(default)]
+    bluetooth: RawBluetoothConfig,
+    #[serde(default)]
+    discovery: RawDiscoveryConfig,
+    #[serde(default)]
+    queue: RawQueueConfig,
+    #[serde(default)]
+    effects: RawEffectsConfig,
+    location: Option<RawLocation>,
+    mqtt: Option<RawMqttConfig>,
+    #[serde(default)]
+    devices: Vec<RawDeviceConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct RawServerConfig {
+    bind: String,
+    port: u16,
+}
+
+impl Default for RawServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0".to_string(),
+            port: 3000,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct RawStorageConfig {
+    data_dir: PathBuf,
+}
+
+impl Default for RawStorageConfig {
+    fn default() -> Self {
+        Self {
+            data_dir: PathBuf::from("data"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct RawBluetoothConfig {
+    adapters: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct RawDiscoveryConfig {
+    timeout_secs: u64,
+    scan_timeout_secs: u64,
+    rssi: Option<i16>,
+    service_uuids: Vec<String>,
+}
+
+impl Default for RawDiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: DEFAULT_DISCOVERY_TIMEOUT.as_secs(),
+            scan_timeout_secs: DiscoveryOptions::default().timeout.as_secs(),
+            rssi: None,
+            service_uuids: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct RawQueueConfig {
+    min_write_interval_ms: u64,
+}
+
+impl Default for RawQueueConfig {
+    fn default() -> Self {
+        Self {
+            min_write_interval_ms: 50,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct RawEffectsConfig {
+    frame_interval_ms: u64,
+}
+
+impl Default for RawEffectsConfig {
+    fn default() -> Self {
+        Self {
+            frame_interval_ms: 100,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawLocation {
+    latitude: f64,
+    longitude: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct RawMqttConfig {
+    host: String,
+    port: u16,
+    client_id: String,
+    username: Option<String>,
+    password: Option<String>,
+    discovery_prefix: String,
+    base_topic: String,
+}
+
+impl Default for RawMqttConfig {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 1883,
+            client_id: "gatt".to_string(),
+            username: None,
+            password: None,
+            discovery_prefix: "homeassistant".to_string(),
+            base_topic: "gatt".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RawPowerOnPolicy {
+    Restore,
+    Default,
+    #[default]
+    Untouched,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RawDefaultState {
+    pub power: Option<bool>,
+    pub brightness: Option<u8>,
+    pub color: Option<String>,
+}
+
+/// A `[[devices]]` entry as written, before it is validated.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RawDeviceConfig {
+    pub kind: DeviceKind,
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub room: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adapter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_uuid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub characteristic_uuid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notify_characteristic_uuid: Option<String>,
+    #[serde(default)]
+    pub power_on: RawPowerOnPolicy,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_state: Option<RawDefaultState>,
+}
+
+impl RawDeviceConfig {
+    /// A device of `kind` using the default UUIDs of its kind.
+    pub fn new(kind: DeviceKind, address: Address) -> Self {
+        Self {
+            kind,
+            address: address.to_string(),
+            name: None,
+            room: None,
+            adapter: None,
+            service_uuid: None,
+            characteristic_uuid: None,
+            notify_characteristic_uuid: None,
+            power_on: Default::default(),
+            default_state: None,
+        }
+    }
+}
+
+/// Accepts `null` to clear an optional field, which plain `Option` treats like a missing field.
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Changes to some fields of a device entry, where `null` clears an optional field.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DevicePatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub room: Option<Option<String>>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub adapter: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power_on: Option<RawPowerOnPolicy>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub default_state: Option<Option<RawDefaultState>>,
+}
+
+impl DevicePatch {
+    /// Sets the fields of `raw` that are set in `self`.
+    pub fn apply(&self, raw: &mut RawDeviceConfig) {
+        if let Some(name) = &self.name {
+            raw.name = Some(name.clone());
+        }
+        if let Some(room) = &self.room {
+            raw.room = room.clone();
+        }
+        if let Some(adapter) = &self.adapter {
+            raw.adapter = adapter.clone();
+        }
+        if let Some(power_on) = &self.power_on {
+            raw.power_on = power_on.clone();
+        }
+        if let Some(default_state) = &self.default_state {
+            raw.default_state = default_state.clone();
+        }
+    }
+
+    /// Adds the fields set in `later`, which win over the ones set in `self`.
+    pub fn merge(&mut self, later: DevicePatch) {
+        self.name = later.name.or(self.name.take());
+        self.room = later.room.or(self.room.take());
+        self.adapter = later.adapter.or(self.adapter.take());
+        self.power_on = later.power_on.or(self.power_on.take());
+        self.default_state = later.default_state.or(self.default_state.take());
+    }
+}
+
+impl RawConfig {
+    fn validate(self) -> Result<Config, Vec<String>> {
+        let mut errors = Vec::new();
+
+        let bind = match IpAddr::from_str(&self.server.bind) {
+            Ok(bind) => bind,
+            Err(_) => {
+                errors.push(format!(
+                    "server.bind: invalid IP address {:?}",
+                    self.server.bind
+                ));
+                IpAddr::from([0, 0, 0, 0])
+            }
+        };
+        if self.server.port == 0 {
+            errors.push("server.port: must not be 0".to_string());
+        }
+
+        let mut adapters = HashSet::new();
+        for (i, adapter) in self.bluetooth.adapters.iter().enumerate() {
+            if adapter.trim().is_empty() {
+                errors.push(format!("bluetooth.adapters[{}]: must not be empty", i));
+            } else if !adapters.insert(adapter.as_str()) {
+                errors.push(format!(
+                    "bluetooth.adapters[{}]: duplicate adapter {:?}",
+                    i, adapter
+                ));
+            }
+        }
+
+        if self.discovery.timeout_secs == 0 {
+            errors.push("discovery.timeout_secs: must not be 0".to_string());
+        }
+        if self.discovery.scan_timeout_secs == 0 {
+            errors.push("discovery.scan_timeout_secs: must not be 0".to_string());
+        }
+        let mut service_uuids = HashSet::new();
+        for (i, uuid) in self.discovery.service_uuids.iter().enumerate() {
+            match Uuid::parse_str(uuid) {
+                Ok(uuid) => {
+                    service_uuids.insert(uuid);
+                }
+                Err(_) => errors.push(format!(
+                    "discovery.service_uuids[{}]: invalid UUID {:?}",
+                    i, uuid
+                )),
+            }
+        }
+        let discovery_timeout = Duration::from_secs(self.discovery.timeout_secs);
+
+        if self.effects.frame_interval_ms == 0 {
+            errors.push("effects.frame_interval_ms: must not be 0".to_string());
+        }
+
+        let location = self.location.map(|location| {
+            if !(-90.0..=90.0).contains(&location.latitude) {
+                errors.push("location.latitude: must be between -90 and 90".to_string());
+            }
+            if !(-180.0..=180.0).contains(&location.longitude) {
+                errors.push("location.longitude: must be between -180 and 180".to_string());
+            }
+            Location {
+                latitude: location.latitude,
+                longitude: location.longitude,
+            }
+        });
+
+        let mqtt = self.mqtt.map(|mqtt| {
+            if mqtt.host.trim().is_empty() {
+                errors.push("mqtt.host: must not be empty".to_string());
+            }
+            if mqtt.port == 0 {
+                errors.push("mqtt.port: must not be 0".to_string());
+            }
+            if mqtt.client_id.is_empty() {
+                errors.push("mqtt.client_id: must not be empty".to_string());
+            }
+            for (field, topic) in [
+                ("discovery_prefix", &mqtt.discovery_prefix),
+                ("base_topic", &mqtt.base_topic),
+            ] {
+                if topic.is_empty() || topic.contains(['+', '#']) || topic.ends_with('/') {
+                    errors.push(format!("mqtt.{}: invalid topic {:?}", field, topic));
+                }
+            }
+            let credentials = match (mqtt.username, mqtt.password) {
+                (Some(username), password) => Some((username, password.unwrap_or_default())),
+                (None, Some(_)) => {
+                    errors.push("mqtt.password: needs a username".to_string());
+                    None
+                }
+                (None, None) => None,
+            };
+
+            MqttConfig {
+                host: mqtt.host,
+                port: mqtt.port,
+                client_id: mqtt.client_id,
+                credentials,
+                discovery_prefix: mqtt.discovery_prefix,
+                base_topic: mqtt.base_topic,
+            }
+        });
+
+        let mut addresses = HashSet::new();
+        let mut devices = Vec::new();
+        for (i, device) in self.devices.into_iter().enumerate() {
+            let context = format!("devices[{}]", i);
+            if let Some(device) = device.validate(
+                &context,
+                &self.bluetooth.adapters,
+                discovery_timeout,
+                &mut errors,
+            ) {
+                if !addresses.insert(device.address) {
+                    errors.push(format!(
+                        "{}.address: duplicate device {}",
+                        context, device.address
+                    ));
+                }
+                devices.push(device);
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(Config {
+            server: ServerConfig {
+                addr: SocketAddr::new(bind, self.server.port),
+            },
+            storage: StorageConfig {
+                data_dir: self.storage.data_dir,
+            },
+            bluetooth: BluetoothConfig {
+                adapters: self.bluetooth.adapters,
+            },
+            discovery: DiscoveryConfig {
+                timeout: discovery_timeout,
+                scan: DiscoveryOptions {
+                    timeout: Duration::from_secs(self.discovery.scan_timeout_secs),
+                    service_uuids,
+                    rssi: self.discovery.rssi,
+                },
+            },
+            queue: QueueConfig {
+                min_write_interval: Duration::from_millis(self.queue.min_write_interval_ms),
+            },
+            effects: EffectsConfig {
+                frame_interval: Duration::from_millis(self.effects.frame_interval_ms),
+            },
+            location,
+            mqtt,
+            devices,
+        })
+    }
+}
+
+impl RawDeviceConfig {
+    fn validate(
+        self,
+        context: &str,
+        adapters: &[String],
+        discovery_timeout: Duration,
+        errors: &mut Vec<String>,
+    ) -> Option<DeviceConfig> {
+        let errors_before = errors.len();
+
+        // Devices without an adapter use the first configured one, or the default adapter.
+        let adapter = match self.adapter {
+            Some(adapter) if !adapters.is_empty() && !adapters.contains(&adapter) => {
+                errors.push(format!(
+                    "{}.adapter: {:?} is not listed in bluetooth.adapters",
+                    context, adapter
+                ));
+                None
+            }
+            Some(adapter) => Some(adapter),
+            None => adapters.first().cloned(),
+        };
+
+        let address = Address::from_str(&self.address)
+            .map_err(|_| {
+                errors.push(format!(
+                    "{}.address: invalid Bluetooth address {:?}",
+                    context, self.address
+                ))
+            })
+            .ok();
+
+        let mut uuid = |field: &str, value: Option<String>, default: Uuid| match value {
+            Some(value) => Uuid::parse_str(&value).unwrap_or_else(|_| {
+                errors.push(format!("{}.{}: invalid UUID {:?}", context, field, value));
+                default
+            }),
+            None => default,
+        };
+
+        let (service_uuid, characteristic_uuid) = match self.kind {
+            DeviceKind::Govee => (govee::SERVICE_UUID, govee::CHARACTERISTIC_UUID),
+            DeviceKind::Esp => (esp::SERVICE_UUID, esp::CHARACTERISTIC_UUID),
+        };
+        let service_uuid = uuid("service_uuid", self.service_uuid, service_uuid);
+        let characteristic_uuid = uuid(
+            "characteristic_uuid",
+            self.characteristic_uuid,
+            characteristic_uuid,
+        );
+        let notify_characteristic_uuid = self
+            .notify_characteristic_uuid
+            .map(|value| uuid("notify_characteristic_uuid", Some(value), Uuid::nil()));
+
+        if self.kind != DeviceKind::Govee && notify_characteristic_uuid.is_some() {
+            errors.push(format!(
+                "{}.notify_characteristic_uuid: only supported by govee devices",
+                context
+            ));
+        }
+        if self.name.as_deref().map(str::trim) == Some("") {
+            errors.push(format!("{}.name: must not be empty", context));
+        }
+
+        let power_on = match (self.power_on, self.default_state) {
+            (RawPowerOnPolicy::Default, Some(default_state)) => {
+                let color = default_state.color.and_then(|color| {
+                    Color::from_str(&color)
+                        .map_err(|e| errors.push(format!("{}.default_state.color: {}", context, e)))
+                        .ok()
+                });
+
+                PowerOnPolicy::Default(DeviceState {
+                    power: default_state.power,
+                    brightness: default_state.brightness,
+                    mode: color.map(Mode::Color),
+                    ..Default::default()
+                })
+            }
+            (RawPowerOnPolicy::Default, None) => {
+                errors.push(format!(
+                    "{}.default_state: required when power_on = \"default\"",
+                    context
+                ));
+                PowerOnPolicy::Untouched
+            }
+            (_, Some(_)) => {
+                errors.push(format!(
+                    "{}.default_state: only used when power_on = \"default\"",
+                    context
+                ));
+                PowerOnPolicy::Untouched
+            }
+            (RawPowerOnPolicy::Restore, None) => PowerOnPolicy::Restore,
+            (RawPowerOnPolicy::Untouched, None) => PowerOnPolicy::Untouched,
+        };
+
+        if errors.len() > errors_before {
+            return None;
+        }
+
+        let address = address?;
+        Some(DeviceConfig {
+            kind: self.kind,
+            address,
+            name: self.name.unwrap_or_else(|| address.to_string()),
+            room: self.room,
+            adapter,
+            discovery_timeout,
+            service_uuid,
+            characteristic_uuid,
+            notify_characteristic_uuid,
+            power_on,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(content: &str) -> Result<Config, Vec<String>> {
+        toml::from_str::<RawConfig>(content).unwrap().validate()
+    }
+
+    #[test]
+    fn mqtt_is_optional_and_has_defaults() {
+        assert_eq!(validate("").unwrap().mqtt, None);
+
+        let mqtt = validate("[mqtt]").unwrap().mqtt.unwrap();
+        assert_eq!((mqtt.host.as_str(), mqtt.port), ("localhost", 1883));
+        assert_eq!(mqtt.client_id, "gatt");
+        assert_eq!(mqtt.credentials, None);
+        assert_eq!(mqtt.discovery_prefix, "homeassistant");
+        assert_eq!(mqtt.base_topic, "gatt");
+    }
+
+    #[test]
+    fn mqtt_credentials_need_a_username() {
+        let mqtt = validate("[mqtt]\nusername = \"ha\"").unwrap().mqtt.unwrap();
+        assert_eq!(mqtt.credentials, Some(("ha".to_string(), String::new())));
+
+        let errors = validate("[mqtt]\npassword = \"secret\"").unwrap_err();
+        assert_eq!(errors, ["mqtt.password: needs a username"]);
+    }
+
+    #[test]
+    fn rejects_invalid_mqtt_settings() {
+        let errors = validate(
+            r#"
+            [mqtt]
+            host = " "
+            port = 0
+            client_id = ""
+            discovery_prefix = "home/#"
+            base_topic = "gatt/"
+            "#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors,
+            [
+                "mqtt.host: must not be empty",
+                "mqtt.port: must not be 0",
+                "mqtt.client_id: must not be empty",
+                "mqtt.discovery_prefix: invalid topic \"home/#\"",
+                "mqtt.base_topic: invalid topic \"gatt/\"",
+            ]
+        );
+
+        for topic in ["", "+", "a/+/b"] {
+            let content = format!("[mqtt]\nbase_topic = {:?}", topic);
+            assert_eq!(validate(&content).unwrap_err().len(), 1, "{:?}", topic);
+        }
+        assert!(toml::from_str::<RawConfig>("[mqtt]\ntopic = \"gatt\"").is_err());
+    }
+
+    #[test]
+    fn defaults_to_every_address_on_port_3000() {
+        let config = validate("").unwrap();
+        assert_eq!(config.server.addr, "0.0.0.0:3000".parse().unwrap());
+        assert!(config.devices.is_empty());
+
+        let config = validate("[server]\nbind = \"127.0.0.1\"\nport = 8080").unwrap();
+        assert_eq!(config.server.addr, "127.0.0.1:8080".parse().unwrap());
+    }
+
+    #[test]
+    fn rejects_invalid_server_settings() {
+        let errors = validate("[server]\nbind = \"localhost\"\nport = 0").unwrap_err();
+        assert_eq!(
+            errors,
+            [
+                "server.bind: invalid IP address \"localhost\"",
+                "server.port: must not be 0",
+            ]
+        );
+        assert!(toml::from_str::<RawConfig>("[server]\nport = 70000").is_err());
+    }
+
+    #[test]
+    fn validates_devices() {
+        let config = validate(
+            r#"
+            [[devices]]
+            kind = "govee"
+            address = "A4:C1:38:EC:91:32"
+
+            [[devices]]
+            kind = "esp"
+            address = "A4:C1:38:EC:91:33"
+            name = "Shelf"
+            service_uuid = "0000ffe0-0000-1000-8000-00805f9b34fb"
+            "#,
+        )
+        .unwrap();
+        let [govee, esp] = &config.devices[..] else {
+            panic!("{:?}", config.devices);
+        };
+        assert_eq!(govee.name, "A4:C1:38:EC:91:32");
+        assert_eq!(govee.service_uuid, govee::SERVICE_UUID);
+        assert_eq!(govee.characteristic_uuid, govee::CHARACTERISTIC_UUID);
+        assert_eq!(esp.name, "Shelf");
+        assert_eq!(
+            esp.service_uuid,
+            Uuid::parse_str("0000ffe0-0000-1000-8000-00805f9b34fb").unwrap()
+        );
+        assert_eq!(esp.characteristic_uuid, esp::CHARACTERISTIC_UUID);
+    }
+
+    #[test]
+    fn rejects_invalid_devices() {
+        let errors = validate(
+            r#"
+            [[devices]]
+            kind = "govee"
+            address = "A4:C1:38:EC:91"
+
+            [[devices]]
+            kind = "govee"
+            address = "A4:C1:38:EC:91:32"
+            characteristic_uuid = "not-a-uuid"
+
+            [[devices]]
+            kind = "esp"
+            address = "A4:C1:38:EC:91:32"
+            name = " "
+            "#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors,
+            [
+                "devices[0].address: invalid Bluetooth address \"A4:C1:38:EC:91\"",
+                "devices[1].characteristic_uuid: invalid UUID \"not-a-uuid\"",
+                "devices[2].name: must not be empty",
+            ]
+        );
+
+        let errors = validate(
+            r#"
+            [[devices]]
+            kind = "govee"
+            address = "A4:C1:38:EC:91:32"
+
+            [[devices]]
+            kind = "esp"
+            address = "A4:C1:38:EC:91:32"
+            "#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors,
+            ["devices[1].address: duplicate device A4:C1:38:EC:91:32"]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_device_kinds() {
+        let error = toml::from_str::<RawConfig>(
+            r#"
+            [[devices]]
+            kind = "hue"
+            address = "A4:C1:38:EC:91:32"
+            "#,
+        )
+        .unwrap_err();
+        assert!(
+            error.to_string().contains("unknown variant `hue`"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn lists_every_error_with_the_path_of_the_config() {
+        let path = std::env::temp_dir().join(format!("gatt-config-{}.toml", std::process::id()));
+        fs::write(&path, "[server]\nport = 0\n[discovery]\ntimeout_secs = 0\n").unwrap();
+        let error = Config::load(&path).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Invalid config {}:\n  - server.port: must not be 0\n  - discovery.timeout_secs: must not be 0",
+                path.display()
+            )
+        );
+
+        fs::write(&path, "[server\n").unwrap();
+        let error = Config::load(&path).unwrap_err();
+        assert!(matches!(error, ConfigError::Parse(..)), "{:?}", error);
+        assert!(error.to_string().starts_with("Failed to parse config"));
+        fs::remove_file(&path).unwrap();
+
+        let error = Config::load(&path).unwrap_err();
+        assert!(matches!(error, ConfigError::Read(..)), "{:?}", error);
+    }
+}
@@ -0,0 +1,152 @@
+use bluer::Address;
+use devices::{
+    effects::{Animation, Effect},
+    Devices, Event, LedDevice,
+};
+use log::{debug, info};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    sync::watch,
+    task::JoinHandle,
+    time::{self, Instant},
+};
+
+use crate::{events::LiveEvent, GlobalState, RegisteredDevice};
+
+#[derive(Debug)]
+struct Running {
+    params: watch::Sender<Effect>,
+    task: JoinHandle<()>,
+}
+
+/// Effects animated by the server, at most one per device.
+#[derive(Debug, Default)]
+pub struct Effects {
+    running: Mutex<HashMap<Address, Running>>,
+}
+
+impl Effects {
+    pub fn get(&self, addr: &Address) -> Option<Effect> {
+        let running = self.running.lock().unwrap();
+        running
+            .get(addr)
+            .map(|running| running.params.borrow().clone())
+    }
+}
+
+/// Starts `effect` on the device of `entry`, replacing the effect running there.
+pub fn start(
+    state: &GlobalState,
+    entry: Arc<RegisteredDevice<Devices>>,
+    effect: Effect,
+) -> devices::Result<()> {
+    effect.validate()?;
+    let addr = entry.config().address;
+    info!("Starting effect {} on {}", effect.name(), addr);
+
+    let (params, receiver) = watch::channel(effect.clone());
+    let task = tokio::spawn(animate(state.clone(), entry, receiver));
+    let previous = state
+        .effects
+        .running
+        .lock()
+        .unwrap()
+        .insert(addr, Running { params, task });
+    if let Some(previous) = previous {
+        previous.task.abort();
+    }
+
+    state.events.publish(LiveEvent::Effect {
+        address: addr,
+        effect: Some(effect),
+    });
+    Ok(())
+}
+
+/// Changes the parameters of the effect running on `addr`, returns whether one is running.
+pub fn update(state: &GlobalState, addr: Address, effect: Effect) -> devices::Result<bool> {
+    effect.validate()?;
+    {
+        let running = state.effects.running.lock().unwrap();
+        let Some(running) = running.get(&addr) else {
+            return Ok(false);
+        };
+        running.params.send_replace(effect.clone());
+    }
+
+    state.events.publish(LiveEvent::Effect {
+        address: addr,
+        effect: Some(effect),
+    });
+    Ok(true)
+}
+
+/// Stops the effect running on `addr`, returns whether one was running.
+pub fn stop(state: &GlobalState, addr: Address) -> bool {
+    let Some(running) = state.effects.running.lock().unwrap().remove(&addr) else {
+        return false;
+    };
+    running.task.abort();
+
+    info!("Stopped effect on {}", addr);
+    state.events.publish(LiveEvent::Effect {
+        address: addr,
+        effect: None,
+    });
+    true
+}
+
+/// Writes the frames of an effect until it is stopped. Frames are not recorded as the state of
+/// the device, it returns to its recorded state with the next command.
+async fn animate(
+    state: GlobalState,
+    entry: Arc<RegisteredDevice<Devices>>,
+    mut params: watch::Receiver<Effect>,
+) {
+    let addr = entry.config().address;
+    let mut animation = Animation::new(params.borrow_and_update().clone());
+    let mut last = None;
+
+    if let Err(e) = entry.device.lock().await.on_event(Event::On).await {
+        debug!("Failed to turn on {} for its effect: {}", addr, e);
+    }
+
+    loop {
+        let (smooth, min_interval) = {
+            let config = state.config.read().unwrap();
+            (
+                config.effects.frame_interval,
+                config.queue.min_write_interval,
+            )
+        };
+
+        let color = animation.frame(Instant::now());
+        if last != Some(color) {
+            // Failing frames are skipped, the supervisor reconnects lost devices meanwhile.
+            match entry
+                .device
+                .lock()
+                .await
+                .on_event(Event::Color(color))
+                .await
+            {
+                Ok(()) => last = Some(color),
+                Err(e) => debug!("Skipping effect frame for {}: {}", addr, e),
+            }
+        }
+
+        let interval = animation.frame_interval(smooth).max(min_interval);
+        tokio::select! {
+            _ = time::sleep(interval) => {}
+            changed = params.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+                animation.set_effect(params.borrow_and_update().clone());
+            }
+        }
+    }
+}
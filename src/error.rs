@@ -0,0 +1,216 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use bluer::Address;
+use serde::Serialize;
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+/// A failed API request, answered with its status code and a JSON [`ErrorBody`].
+#[derive(Debug)]
+pub enum ApiError {
+    Device(devices::Error),
+    BadRequest(String),
+    NotFound(String),
+    Conflict(String),
+    Internal(String),
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// Stable name of the error, like `not_connected`.
+    pub error: &'static str,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn device_not_found(addr: Address) -> Self {
+        ApiError::NotFound(format!("Device {} is not registered", addr))
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::Device(e) => match e {
+                devices::Error::NotFound(_) => StatusCode::NOT_FOUND,
+                devices::Error::NotConnected(_) => StatusCode::CONFLICT,
+                devices::Error::CharacteristicMissing(_) | devices::Error::Ble(_) => {
+                    StatusCode::BAD_GATEWAY
+                }
+                devices::Error::InvalidCommand(_) => StatusCode::UNPROCESSABLE_ENTITY,
+                devices::Error::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+                devices::Error::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                devices::Error::Superseded => StatusCode::CONFLICT,
+            },
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        let (error, message) = match self {
+            ApiError::Device(e) => (e.code(), e.to_string()),
+            ApiError::BadRequest(message) => ("bad_request", message.clone()),
+            ApiError::NotFound(message) => ("not_found", message.clone()),
+            ApiError::Conflict(message) => ("conflict", message.clone()),
+            ApiError::Internal(message) => ("internal", message.clone()),
+        };
+        ErrorBody { error, message }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status(), Json(self.body())).into_response()
+    }
+}
+
+impl From<devices::Error> for ApiError {
+    fn from(e: devices::Error) -> Self {
+        ApiError::Device(e)
+    }
+}
+
+impl From<bluer::Error> for ApiError {
+    fn from(e: bluer::Error) -> Self {
+        ApiError::Device(e.into())
+    }
+}
+
+pub fn parse_address(addr: &str) -> Result<Address, ApiError> {
+    Address::from_str(addr)
+        .map_err(|_| ApiError::BadRequest(format!("Invalid Bluetooth address {:?}", addr)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::HttpBody;
+    use bluer::Uuid;
+
+    const ADDR: Address = Address::new([0xA4, 0xC1, 0x38, 0xEC, 0x91, 0x32]);
+
+    #[test]
+    fn maps_device_errors_to_status_and_body() {
+        let uuid = Uuid::from_u128(0x000102030405060708090a0b0c0d2b11);
+        let ble = bluer::Error {
+            kind: bluer::ErrorKind::Failed,
+            message: "Operation failed".to_string(),
+        };
+        let cases = [
+            (
+                devices::Error::NotFound(ADDR),
+                StatusCode::NOT_FOUND,
+                "not_found",
+                "Device A4:C1:38:EC:91:32 not found",
+            ),
+            (
+                devices::Error::NotConnected(ADDR),
+                StatusCode::CONFLICT,
+                "not_connected",
+                "Device A4:C1:38:EC:91:32 not connected",
+            ),
+            (
+                devices::Error::CharacteristicMissing(uuid),
+                StatusCode::BAD_GATEWAY,
+                "characteristic_missing",
+                "Characteristic 00010203-0405-0607-0809-0a0b0c0d2b11 not found",
+            ),
+            (
+                devices::Error::Ble(ble),
+                StatusCode::BAD_GATEWAY,
+                "ble",
+                "Bluetooth error: Bluetooth operation failed: Operation failed",
+            ),
+            (
+                devices::Error::InvalidCommand("Scenes are not supported".to_string()),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_command",
+                "Scenes are not supported",
+            ),
+            (
+                devices::Error::Timeout("connecting".to_string()),
+                StatusCode::GATEWAY_TIMEOUT,
+                "timeout",
+                "Timed out connecting",
+            ),
+            (
+                devices::Error::Internal("queue stopped".to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal",
+                "Internal error: queue stopped",
+            ),
+            (
+                devices::Error::Superseded,
+                StatusCode::CONFLICT,
+                "superseded",
+                "Stopped by a newer command before the transition finished",
+            ),
+        ];
+
+        for (error, status, code, message) in cases {
+            let error = ApiError::from(error);
+            assert_eq!(error.status(), status, "{:?}", error);
+            let body = error.body();
+            assert_eq!((body.error, body.message.as_str()), (code, message));
+        }
+    }
+
+    #[test]
+    fn maps_request_errors_to_status_and_body() {
+        let cases = [
+            (
+                ApiError::BadRequest("Invalid color".to_string()),
+                StatusCode::BAD_REQUEST,
+                "bad_request",
+            ),
+            (
+                ApiError::device_not_found(ADDR),
+                StatusCode::NOT_FOUND,
+                "not_found",
+            ),
+            (
+                ApiError::Conflict("Device is already registered".to_string()),
+                StatusCode::CONFLICT,
+                "conflict",
+            ),
+            (
+                ApiError::Internal("Failed to save".to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal",
+            ),
+        ];
+
+        for (error, status, code) in cases {
+            assert_eq!(error.status(), status, "{:?}", error);
+            assert_eq!(error.body().error, code);
+        }
+        assert!(matches!(
+            parse_address("A4:C1:38"),
+            Err(ApiError::BadRequest(message)) if message == "Invalid Bluetooth address \"A4:C1:38\""
+        ));
+    }
+
+    #[tokio::test]
+    async fn answers_with_the_status_and_a_json_body() {
+        let response = ApiError::from(devices::Error::NotConnected(ADDR)).into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let mut body = response.into_body();
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.data().await {
+            bytes.extend_from_slice(&chunk.unwrap());
+        }
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "error": "not_connected",
+                "message": "Device A4:C1:38:EC:91:32 not connected",
+            })
+        );
+    }
+}
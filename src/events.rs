@@ -0,0 +1,151 @@
+use axum::{
+    extract::State,
+    response::sse::{self, KeepAlive, Sse},
+};
+use bluer::Address;
+use devices::{effects::Effect, DeviceState};
+use futures::{stream, Stream, StreamExt};
+use log::{debug, warn};
+use serde::Serialize;
+use std::convert::Infallible;
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    watch,
+};
+
+use crate::{v2::ConnectionStatus, GlobalState};
+
+/// Events a client may fall behind by before it misses some and gets a fresh snapshot.
+const CAPACITY: usize = 256;
+
+/// Something that changed on one of the registered devices.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveEvent {
+    Connection {
+        address: Address,
+        status: ConnectionStatus,
+    },
+    /// A command was written, `state` is the state set through the server since.
+    Command {
+        address: Address,
+        state: DeviceState,
+    },
+    /// State read back from the strip.
+    State {
+        address: Address,
+        state: DeviceState,
+    },
+    /// An effect was started, changed or stopped.
+    Effect {
+        address: Address,
+        effect: Option<Effect>,
+    },
+}
+
+/// Fans changes out to every client of `GET /api/events`.
+#[derive(Debug)]
+pub struct EventBus {
+    sender: broadcast::Sender<LiveEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl EventBus {
+    pub fn publish(&self, event: LiveEvent) {
+        // Failing only means nobody is listening.
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn connection(&self, address: Address, status: ConnectionStatus) {
+        self.publish(LiveEvent::Connection { address, status });
+    }
+}
+
+/// Publishes the state a strip reports by itself, like its replies to keep alive queries,
+/// until the device is dropped.
+pub async fn forward_state(
+    state: GlobalState,
+    address: Address,
+    mut updates: watch::Receiver<DeviceState>,
+) {
+    while updates.changed().await.is_ok() {
+        let reported = updates.borrow_and_update().clone();
+        // Disconnecting resets the state, which is nothing the strip reported.
+        if reported.connected {
+            state.events.publish(LiveEvent::State {
+                address,
+                state: reported,
+            });
+        }
+    }
+}
+
+/// The connection and last known state of every device, for clients that just subscribed.
+fn snapshot(state: &GlobalState) -> Vec<LiveEvent> {
+    state
+        .devices
+        .entries()
+        .iter()
+        .flat_map(|entry| {
+            let address = entry.config().address;
+            let mut events = vec![LiveEvent::Connection {
+                address,
+                status: ConnectionStatus::of(entry),
+            }];
+            if let Some(state) = state.store.get(&address) {
+                events.push(LiveEvent::Command { address, state });
+            }
+            if let Some(effect) = state.effects.get(&address) {
+                events.push(LiveEvent::Effect {
+                    address,
+                    effect: Some(effect),
+                });
+            }
+            events
+        })
+        .collect()
+}
+
+fn to_sse(event: &LiveEvent) -> sse::Event {
+    sse::Event::default().json_data(event).unwrap_or_else(|e| {
+        warn!("Failed to serialize {:?}: {}", event, e);
+        sse::Event::default().comment("unserializable event")
+    })
+}
+
+/// Streams a snapshot of all devices followed by every change, as server-sent events.
+pub async fn stream_events(
+    State(state): State<GlobalState>,
+) -> Sse<impl Stream<Item = Result<sse::Event, Infallible>>> {
+    // Subscribing before taking the snapshot means no change falls in between.
+    let receiver = state.events.subscribe();
+    let initial = snapshot(&state);
+
+    let changes = stream::unfold((state, receiver), |(state, mut receiver)| async move {
+        let events = match receiver.recv().await {
+            Ok(event) => vec![event],
+            Err(RecvError::Lagged(missed)) => {
+                debug!("Event client missed {} events, resending snapshot", missed);
+                snapshot(&state)
+            }
+            Err(RecvError::Closed) => return None,
+        };
+        Some((stream::iter(events), (state, receiver)))
+    });
+
+    let events = stream::iter(initial)
+        .chain(changes.flatten())
+        .map(|event| Ok(to_sse(&event)));
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
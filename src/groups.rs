@@ -0,0 +1,293 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, post, put},
+    Json, Router,
+};
+use bluer::Address;
+use devices::Event;
+use futures::future::join_all;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, time::Duration};
+use utoipa::ToSchema;
+
+use crate::{
+    error::{ApiError, ErrorBody},
+    queue, GlobalState, SetLedEvent,
+};
+
+pub fn router() -> Router<GlobalState> {
+    Router::new()
+        .route("/groups", get(list_groups))
+        .route("/groups/:name", put(set_group).delete(delete_group))
+        .route("/groups/:name/set", post(set_group_led))
+        .route("/rooms", get(list_rooms))
+        .route("/rooms/:room/set", post(set_room_led))
+}
+
+fn group_not_found(name: &str) -> ApiError {
+    ApiError::NotFound(format!("Group {:?} does not exist", name))
+}
+
+fn save_failed(e: std::io::Error) -> ApiError {
+    ApiError::Internal(format!("Failed to save group: {}", e))
+}
+
+async fn list_groups(State(state): State<GlobalState>) -> impl IntoResponse {
+    Json(state.registry.groups())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GroupRequest {
+    members: Vec<Address>,
+}
+
+async fn set_group(
+    Path(name): Path<String>,
+    State(state): State<GlobalState>,
+    Json(input): Json<GroupRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    if name.trim().is_empty() {
+        return Err(ApiError::BadRequest(
+            "Group name must not be empty".to_string(),
+        ));
+    }
+    if input.members.is_empty() {
+        return Err(ApiError::BadRequest(
+            "members must not be empty".to_string(),
+        ));
+    }
+
+    let mut members = input.members;
+    members.sort();
+    members.dedup();
+    let _changes = state.devices.changes.lock().await;
+    if let Some(addr) = members.iter().find(|addr| !state.devices.contains(addr)) {
+        return Err(ApiError::device_not_found(*addr));
+    }
+
+    let replaced = state
+        .registry
+        .set_group(name.clone(), members.clone())
+        .map_err(save_failed)?;
+    info!("Set group {:?} to {} devices", name, members.len());
+
+    let status = match replaced {
+        true => StatusCode::OK,
+        false => StatusCode::CREATED,
+    };
+    Ok((status, Json(members)))
+}
+
+async fn delete_group(
+    Path(name): Path<String>,
+    State(state): State<GlobalState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let _changes = state.devices.changes.lock().await;
+    match state.registry.remove_group(&name).map_err(save_failed)? {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(group_not_found(&name)),
+    }
+}
+
+/// The registered devices by room, devices without a room are left out.
+async fn list_rooms(State(state): State<GlobalState>) -> impl IntoResponse {
+    let mut rooms = BTreeMap::<String, Vec<Address>>::new();
+    for entry in state.devices.entries() {
+        let config = entry.config();
+        if let Some(room) = config.room {
+            rooms.entry(room).or_default().push(config.address);
+        }
+    }
+    for members in rooms.values_mut() {
+        members.sort();
+    }
+    Json(rooms)
+}
+
+/// The outcome of a command sent to several devices, for one of them.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MemberResult {
+    #[schema(value_type = String, example = "A4:C1:38:EC:91:32")]
+    address: Address,
+    /// Missing when the device applied the command.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorBody>,
+}
+
+impl MemberResult {
+    pub fn new(address: Address, result: Result<(), ApiError>) -> Self {
+        Self {
+            address,
+            error: result.err().map(|e| e.body()),
+        }
+    }
+}
+
+/// `200` when every device applied the command, `207` when some failed.
+pub fn status(results: &[MemberResult]) -> StatusCode {
+    match results.iter().all(|result| result.error.is_none()) {
+        true => StatusCode::OK,
+        false => StatusCode::MULTI_STATUS,
+    }
+}
+
+async fn set_group_led(
+    Path(name): Path<String>,
+    State(state): State<GlobalState>,
+    Json(input): Json<SetLedEvent>,
+) -> Result<impl IntoResponse, ApiError> {
+    let members = state
+        .registry
+        .group(&name)
+        .ok_or_else(|| group_not_found(&name))?;
+    fan_out(&state, members, input).await
+}
+
+async fn set_room_led(
+    Path(room): Path<String>,
+    State(state): State<GlobalState>,
+    Json(input): Json<SetLedEvent>,
+) -> Result<impl IntoResponse, ApiError> {
+    let members = state
+        .devices
+        .entries()
+        .into_iter()
+        .map(|entry| entry.config())
+        .filter(|config| config.room.as_deref() == Some(room.as_str()))
+        .map(|config| config.address)
+        .collect::<Vec<_>>();
+    if members.is_empty() {
+        return Err(ApiError::NotFound(format!(
+            "No device is in room {:?}",
+            room
+        )));
+    }
+    fan_out(&state, members, input).await
+}
+
+/// Sends the event to all `members` at once, so they change together. Answers `200` when every
+/// member applied it and `207` with the failures otherwise.
+async fn fan_out(
+    state: &GlobalState,
+    members: Vec<Address>,
+    input: SetLedEvent,
+) -> Result<impl IntoResponse, ApiError> {
+    let transition = input.transition_ms.map(Duration::from_millis);
+    let event = Event::try_from(input).map_err(ApiError::BadRequest)?;
+
+    let sends = members.into_iter().map(|addr| {
+        let event = event.clone();
+        async move {
+            let sent = match state.devices.get_entry(&addr) {
+                Some(entry) => queue::send(state, entry, event, transition)
+                    .await
+                    .map_err(ApiError::from),
+                None => Err(ApiError::device_not_found(addr)),
+            };
+            MemberResult::new(addr, sent)
+        }
+    });
+    let results = join_all(sends).await;
+    Ok((status(&results), Json(results)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::HttpBody;
+    use serde_json::Value;
+
+    const DEVICES: &str = r#"
+        [[devices]]
+        kind = "govee"
+        address = "A4:C1:38:EC:91:32"
+        name = "Desk"
+
+        [[devices]]
+        kind = "govee"
+        address = "A4:C1:38:EC:91:33"
+        name = "Shelf"
+    "#;
+
+    async fn set(state: &GlobalState, name: &str) -> (StatusCode, Value) {
+        let input = serde_json::from_str(r#"{"event_type": "on"}"#).unwrap();
+        let response =
+            match set_group_led(Path(name.to_string()), State(state.clone()), Json(input)).await {
+                Ok(response) => response.into_response(),
+                Err(e) => e.into_response(),
+            };
+
+        let status = response.status();
+        let mut body = response.into_body();
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.data().await {
+            bytes.extend_from_slice(&chunk.unwrap());
+        }
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[test]
+    fn status_is_multi_status_when_any_member_failed() {
+        let (desk, shelf) = (
+            "A4:C1:38:EC:91:32".parse().unwrap(),
+            "A4:C1:38:EC:91:33".parse().unwrap(),
+        );
+        let applied = [
+            MemberResult::new(desk, Ok(())),
+            MemberResult::new(shelf, Ok(())),
+        ];
+        assert_eq!(status(&applied), StatusCode::OK);
+        let failed = [
+            MemberResult::new(desk, Ok(())),
+            MemberResult::new(shelf, Err(ApiError::device_not_found(shelf))),
+        ];
+        assert_eq!(status(&failed), StatusCode::MULTI_STATUS);
+    }
+
+    #[tokio::test]
+    async fn sends_to_every_member_of_a_group() {
+        let state = GlobalState::for_tests("groups", DEVICES).await;
+        let (desk, shelf) = (
+            "A4:C1:38:EC:91:32".parse().unwrap(),
+            "A4:C1:38:EC:91:33".parse().unwrap(),
+        );
+        let mock = state.attach_mock(desk).await;
+        state
+            .registry
+            .set_group("desk".to_string(), vec![desk])
+            .unwrap();
+        state
+            .registry
+            .set_group("office".to_string(), vec![desk, shelf])
+            .unwrap();
+
+        let (status, results) = set(&state, "desk").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            results,
+            serde_json::json!([{"address": "A4:C1:38:EC:91:32"}])
+        );
+        assert!(mock
+            .frames()
+            .iter()
+            .any(|frame| frame[..3] == [0x33, 0x01, 0x01]));
+
+        // Shelf has no connection, so only desk applies the command.
+        let (status, results) = set(&state, "office").await;
+        assert_eq!(status, StatusCode::MULTI_STATUS);
+        assert!(results[0].get("error").is_none());
+        assert_eq!(results[1]["address"], "A4:C1:38:EC:91:33");
+        assert_eq!(results[1]["error"]["error"], "not_connected");
+
+        let (status, _) = set(&state, "hall").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let config = state.config.read().unwrap().clone();
+        state.registry.remove(desk, &config).unwrap();
+        let (status, _) = set(&state, "desk").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
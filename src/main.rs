@@ -1,36 +1,215 @@
+mod config;
+mod effects;
+mod error;
+mod events;
+mod groups;
+mod mqtt;
+mod queue;
+mod registry;
+mod scheduler;
+mod snapshots;
+mod store;
+mod sun;
+mod supervisor;
+mod v2;
+
 use askama::Template;
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::{Html, IntoResponse, Response},
-    routing::{get, post},
+    routing::{get, patch, post},
     Json, Router,
 };
 use bluer::{Address, Uuid};
-use log::{debug, error, info};
-use serde::Deserialize;
-use std::{collections::HashMap, str::FromStr, sync::Arc};
-use tokio::sync::Mutex;
+use error::{parse_address, ApiError};
+use events::{EventBus, LiveEvent};
+use log::{error, info, warn};
+use queue::CommandQueue;
+use registry::Registry;
+use scheduler::Schedules;
+use serde::{Deserialize, Serialize};
+use snapshots::Snapshots;
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use store::StateStore;
+use supervisor::Supervisor;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::Mutex,
+};
 use tower_http::services::ServeDir;
+use v2::ConnectionStatus;
 
-use devices::{esp::EspLed, govee::GoveeLed, Devices, Event, LedDevice};
+use config::{
+    Config, DeviceConfig, DevicePatch, PowerOnPolicy, RawDeviceConfig, ServerConfig,
+    DEFAULT_CONFIG_PATH,
+};
+use devices::{
+    discovery::{self, DiscoveryOptions, ScannedDevice},
+    govee::{
+        self,
+        protocol::{Diy, DiyStyle, MusicMode},
+    },
+    Color, DeviceKind, Devices, Event, LedDevice,
+};
 
-#[derive(Debug, Clone)]
+/// A device together with its settings. The device has its own lock, so operations on
+/// different devices run in parallel while operations on one device stay ordered.
+#[derive(Debug)]
+struct RegisteredDevice<T: LedDevice> {
+    config: RwLock<DeviceConfig>,
+    device: Mutex<T>,
+    queue: CommandQueue,
+}
+
+impl<T: LedDevice> RegisteredDevice<T> {
+    fn config(&self) -> DeviceConfig {
+        self.config.read().unwrap().clone()
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DeviceInfo {
+    address: String,
+    name: String,
+    room: Option<String>,
+}
+
+impl From<&DeviceConfig> for DeviceInfo {
+    fn from(config: &DeviceConfig) -> Self {
+        Self {
+            address: config.address.to_string(),
+            name: config.name.clone(),
+            room: config.room.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
 struct DevicesState<T: LedDevice> {
-    devices: HashMap<Address, T>,
+    /// Only locked to look devices up or swap them, never across an await.
+    devices: RwLock<HashMap<Address, Arc<RegisteredDevice<T>>>>,
+    /// Orders changes to which devices are registered.
+    changes: Mutex<()>,
 }
 
 impl<T: LedDevice> DevicesState<T> {
-    fn add_device(&mut self, addr: Address, device: T) {
-        self.devices.insert(addr, device);
+    fn add_device(&self, config: DeviceConfig, device: T) -> Option<Arc<RegisteredDevice<T>>> {
+        let entry = RegisteredDevice {
+            config: RwLock::new(config.clone()),
+            device: Mutex::new(device),
+            queue: Default::default(),
+        };
+        self.devices
+            .write()
+            .unwrap()
+            .insert(config.address, Arc::new(entry))
+    }
+
+    fn get_entry(&self, addr: &Address) -> Option<Arc<RegisteredDevice<T>>> {
+        self.devices.read().unwrap().get(addr).cloned()
+    }
+
+    fn entries(&self) -> Vec<Arc<RegisteredDevice<T>>> {
+        self.devices.read().unwrap().values().cloned().collect()
     }
 
-    fn get_device(&mut self, addr: &Address) -> Option<&mut T> {
-        self.devices.get_mut(addr)
+    fn contains(&self, addr: &Address) -> bool {
+        self.devices.read().unwrap().contains_key(addr)
     }
 
-    fn get_device_names(&self) -> Vec<String> {
-        self.devices.keys().map(|a| a.to_string()).collect()
+    fn get_device_infos(&self) -> Vec<DeviceInfo> {
+        let mut infos = self
+            .devices
+            .read()
+            .unwrap()
+            .values()
+            .map(|entry| DeviceInfo::from(&*entry.config.read().unwrap()))
+            .collect::<Vec<_>>();
+        infos.sort_by(|a, b| a.name.cmp(&b.name));
+        infos
+    }
+}
+
+impl DevicesState<Devices> {
+    /// Makes the registry match `devices`, keeping the connections of devices whose
+    /// Bluetooth settings did not change. Returns the devices that were removed or replaced.
+    async fn apply_config(&self, devices: Vec<DeviceConfig>) -> Vec<Address> {
+        let _changes = self.changes.lock().await;
+
+        let addresses = devices.iter().map(|d| d.address).collect::<HashSet<_>>();
+        let removed = self
+            .devices
+            .read()
+            .unwrap()
+            .keys()
+            .filter(|addr| !addresses.contains(addr))
+            .copied()
+            .collect::<Vec<_>>();
+
+        let mut dropped = removed.clone();
+        for addr in removed {
+            self.remove_device(&addr).await;
+        }
+
+        for config in devices {
+            let addr = config.address;
+            if self.upsert_device(config).await {
+                dropped.push(addr);
+            }
+        }
+
+        dropped
+    }
+
+    /// Adds or updates a device, returns whether an existing device was replaced.
+    async fn upsert_device(&self, config: DeviceConfig) -> bool {
+        let addr = config.address;
+        match self.get_entry(&addr) {
+            Some(entry) if entry.config().same_device(&config) => {
+                *entry.config.write().unwrap() = config;
+                false
+            }
+            Some(_) => {
+                info!("Replacing device {}", addr);
+                let device = config.build();
+                if let Some(previous) = self.add_device(config, device) {
+                    disconnect_removed(addr, &previous).await;
+                }
+                true
+            }
+            None => {
+                info!("Adding device {} ({})", addr, config.name);
+                let device = config.build();
+                self.add_device(config, device);
+                false
+            }
+        }
+    }
+
+    /// Disconnects and forgets a device, returns whether it was registered.
+    async fn remove_device(&self, addr: &Address) -> bool {
+        let Some(entry) = self.devices.write().unwrap().remove(addr) else {
+            return false;
+        };
+
+        info!("Removing device {}", addr);
+        disconnect_removed(*addr, &entry).await;
+        true
+    }
+}
+
+/// Disconnects a device that is no longer registered, once its pending operation finished.
+async fn disconnect_removed(addr: Address, entry: &RegisteredDevice<Devices>) {
+    if let Err(e) = entry.device.lock().await.disconnect().await {
+        warn!("Failed to disconnect from {}: {}", addr, e);
     }
 }
 
@@ -41,44 +220,138 @@ where
     fn default() -> DevicesState<T> {
         Self {
             devices: Default::default(),
+            changes: Default::default(),
         }
     }
 }
 
-type GlobalState = Arc<Mutex<DevicesState<Devices>>>;
+#[derive(Debug, Clone)]
+struct GlobalState {
+    devices: Arc<DevicesState<Devices>>,
+    store: Arc<StateStore>,
+    supervisor: Arc<Supervisor>,
+    /// The config that was loaded last.
+    config: Arc<RwLock<Config>>,
+    registry: Arc<Registry>,
+    /// Supported devices seen by the last `GET /api/detected`, by address.
+    detected: Arc<std::sync::Mutex<HashMap<Address, ScannedDevice>>>,
+    events: Arc<EventBus>,
+    effects: Arc<effects::Effects>,
+    schedules: Arc<Schedules>,
+    snapshots: Arc<Snapshots>,
+}
+
+impl GlobalState {
+    /// The state of a server running with `config`, before its devices are added.
+    fn new(config: Config) -> Self {
+        Self {
+            devices: Default::default(),
+            store: Arc::new(StateStore::load(config.storage.state_file())),
+            supervisor: Default::default(),
+            registry: Arc::new(Registry::load(config.storage.registry_file())),
+            detected: Default::default(),
+            events: Default::default(),
+            effects: Default::default(),
+            schedules: Arc::new(Schedules::load(config.storage.schedules_file())),
+            snapshots: Arc::new(Snapshots::load(config.storage.snapshots_file())),
+            config: Arc::new(RwLock::new(config)),
+        }
+    }
+
+    /// Applies `event` to `device` and remembers it as the last known state of the device.
+    async fn apply_event(
+        &self,
+        addr: Address,
+        device: &mut impl LedDevice,
+        event: Event,
+    ) -> devices::Result<()> {
+        device.on_event(event.clone()).await?;
+        self.record(addr, &event);
+        Ok(())
+    }
+
+    /// Remembers that `event` was applied to `addr` and tells subscribers.
+    fn record(&self, addr: Address, event: &Event) {
+        self.store.record(addr, event);
+        self.events.publish(LiveEvent::Command {
+            address: addr,
+            state: self.store.get(&addr).unwrap_or_default(),
+        });
+    }
+
+    /// Runs the power-on policy of `device`, which just connected.
+    async fn power_on(&self, config: &DeviceConfig, device: &mut Devices) {
+        let addr = config.address;
+        let state = match &config.power_on {
+            PowerOnPolicy::Restore => match self.store.get(&addr) {
+                Some(state) => state,
+                None => return,
+            },
+            PowerOnPolicy::Default(state) => state.clone(),
+            PowerOnPolicy::Untouched => return,
+        };
+
+        info!("Applying power-on state to {}: {:?}", addr, state);
+        for event in state.events() {
+            if let Err(e) = self.apply_event(addr, device, event).await {
+                warn!("Failed to apply power-on state to {}: {}", addr, e);
+                return;
+            }
+        }
+    }
+}
 
 #[tokio::main]
-async fn main() -> bluer::Result<()> {
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
 
-    let state = GlobalState::default();
+    let config_path = env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH));
+    let config = match Config::load(&config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
-    let govee_led_addr = Address::from_str("A4:C1:38:EC:91:32")?;
-    let govee_service_uuid = Uuid::parse_str("000102030405060708090a0b0c0d1910").unwrap();
-    let govee_characteristic_uuid = Uuid::parse_str("000102030405060708090a0b0c0d2b11").unwrap();
+    power_on_adapters(&config.bluetooth.adapters).await;
 
-    let govee_leds = Devices::Govee(GoveeLed::new(
-        govee_led_addr.clone(),
-        govee_service_uuid,
-        govee_characteristic_uuid,
-    ));
-    state.lock().await.add_device(govee_led_addr, govee_leds);
+    let state = GlobalState::new(config.clone());
+    state
+        .devices
+        .apply_config(state.registry.devices(&config))
+        .await;
 
-    let esp_led_addr = Address::from_str("40:22:D8:EA:CB:FA")?;
-    let esp_service_uuid = Uuid::parse_str("1afc47f3-4a31-4c4e-9f54-ca1ede6e2e1f").unwrap();
-    let esp_characteristic_uuid = Uuid::parse_str("21b3e7c8-bc41-47c7-af6c-1fe47aad759f").unwrap();
+    tokio::spawn(state.store.clone().run_writer());
+    tokio::spawn(scheduler::run(state.clone()));
+    if let Some(mqtt) = config.mqtt.clone() {
+        mqtt::spawn(mqtt, state.clone());
+    }
 
-    let esp_leds = Devices::Esp(EspLed::new(
-        esp_led_addr.clone(),
-        esp_service_uuid,
-        esp_characteristic_uuid,
+    tokio::spawn(reload_on_sighup(
+        config_path,
+        config.server.clone(),
+        state.clone(),
     ));
-    state.lock().await.add_device(esp_led_addr, esp_leds);
 
     let api_router = Router::new()
         .route("/set/:addr", post(set_led))
+        .route("/state/:addr", get(device_state))
+        .route("/queue/:addr", get(queue_stats))
+        .route("/scenes", get(list_scenes))
+        .route("/scan", get(scan_devices))
+        .route("/detected", get(detected_devices))
+        .route("/adopt/:addr", post(adopt_device))
+        .route("/devices", get(list_devices).post(add_device))
+        .route("/devices/:addr", patch(update_device).delete(delete_device))
         .route("/connect/:addr", post(connect_to_led))
-        .route("/disconnect/:addr", post(disconnect_from_led));
+        .route("/disconnect/:addr", post(disconnect_from_led))
+        .route("/events", get(events::stream_events))
+        .merge(groups::router())
+        .nest("/v2", v2::router());
 
     let app_router = Router::new()
         .route("/", get(index))
@@ -86,55 +359,118 @@ async fn main() -> bluer::Result<()> {
         .nest_service("/assets", ServeDir::new("assets"))
         .with_state(state);
 
-    println!("start axum server");
-    axum::Server::bind(&"0.0.0.0:3000".parse().unwrap())
+    println!("start axum server on {}", config.server.addr);
+    axum::Server::bind(&config.server.addr)
         .serve(app_router.into_make_service())
-        .await
-        .unwrap();
+        .await?;
 
     Ok(())
 }
 
+async fn power_on_adapters(adapters: &[String]) {
+    for name in adapters {
+        let powered = match devices::adapter(Some(name)).await {
+            Ok(adapter) => adapter.set_powered(true).await,
+            Err(e) => Err(e),
+        };
+
+        match powered {
+            Ok(()) => info!("Using Bluetooth adapter {}", name),
+            Err(e) => warn!("Bluetooth adapter {} is not available: {}", name, e),
+        }
+    }
+}
+
+async fn reload_on_sighup(path: PathBuf, server: ServerConfig, state: GlobalState) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            error!("Failed to listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        info!("Reloading config {}", path.display());
+
+        match Config::load(&path) {
+            Ok(config) => {
+                if config.server != server {
+                    warn!("Changes to [server] only take effect after a restart");
+                }
+                if config.mqtt != state.config.read().unwrap().mqtt {
+                    warn!("Changes to [mqtt] only take effect after a restart");
+                }
+                power_on_adapters(&config.bluetooth.adapters).await;
+                let devices = state.registry.devices(&config);
+                *state.config.write().unwrap() = config;
+                let dropped = state.devices.apply_config(devices).await;
+                for addr in dropped {
+                    state.supervisor.stop(&addr);
+                    effects::stop(&state, addr);
+                    state
+                        .events
+                        .connection(addr, ConnectionStatus::Disconnected);
+                }
+            }
+            Err(e) => error!("{}, keeping the current config", e),
+        }
+    }
+}
+
 async fn connect_to_led(
     Path(addr): Path<String>,
     State(state): State<GlobalState>,
-) -> impl IntoResponse {
-    let addr = Address::from_str(&addr).unwrap();
-    let mut state = state.lock().await;
-
-    if let Some(device) = state.get_device(&addr) {
-        match device.connect().await {
-            Ok(()) => "Successfully connected".into_response(),
-            Err(e) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to connect: {}", e),
-            )
-                .into_response(),
-        }
-    } else {
-        "Device not found".into_response()
-    }
+) -> Result<impl IntoResponse, ApiError> {
+    let addr = parse_address(&addr)?;
+    let entry = state
+        .devices
+        .get_entry(&addr)
+        .ok_or(ApiError::device_not_found(addr))?;
+
+    let mut device = entry.device.lock().await;
+    device.connect().await?;
+    state.power_on(&entry.config(), &mut device).await;
+    state.events.connection(addr, ConnectionStatus::Connected);
+    state.supervisor.watch(state.clone(), addr);
+    Ok("Successfully connected")
 }
 
 async fn disconnect_from_led(
     Path(addr): Path<String>,
     State(state): State<GlobalState>,
-) -> impl IntoResponse {
-    let addr = Address::from_str(&addr).unwrap();
-    let mut state = state.lock().await;
-
-    if let Some(device) = state.get_device(&addr) {
-        match device.disconnect().await {
-            Ok(()) => "Successfully disconnected".into_response(),
-            Err(e) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to disconnect: {}", e),
-            )
-                .into_response(),
-        }
-    } else {
-        "Device not found".into_response()
-    }
+) -> Result<impl IntoResponse, ApiError> {
+    let addr = parse_address(&addr)?;
+    state.supervisor.stop(&addr);
+    effects::stop(&state, addr);
+    let entry = state
+        .devices
+        .get_entry(&addr)
+        .ok_or(ApiError::device_not_found(addr))?;
+
+    entry.device.lock().await.disconnect().await?;
+    state
+        .events
+        .connection(addr, ConnectionStatus::Disconnected);
+    Ok("Successfully disconnected")
+}
+
+async fn device_state(
+    Path(addr): Path<String>,
+    State(state): State<GlobalState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let addr = parse_address(&addr)?;
+    let entry = state
+        .devices
+        .get_entry(&addr)
+        .ok_or(ApiError::device_not_found(addr))?;
+
+    let device_state = entry.device.lock().await.state().await?;
+    state.events.publish(LiveEvent::State {
+        address: addr,
+        state: device_state.clone(),
+    });
+    Ok(Json(device_state))
 }
 
 #[derive(Debug, Deserialize)]
@@ -142,18 +478,65 @@ pub struct SetLedEvent {
     event_type: String,
     color: Option<String>,
     brightness: Option<u8>,
+    scene: Option<String>,
+    music_mode: Option<String>,
+    style: Option<String>,
+    speed: Option<u8>,
+    colors: Option<Vec<String>>,
     other_ev: Option<String>,
+    /// Fades colors and brightness instead of jumping to them.
+    transition_ms: Option<u64>,
 }
 
-impl From<SetLedEvent> for Event {
-    fn from(val: SetLedEvent) -> Self {
-        match val.event_type.as_str() {
+fn parse_color(color: &str) -> Result<Color, String> {
+    Color::from_str(color).map_err(|e| format!("Invalid color: {}", e))
+}
+
+impl TryFrom<SetLedEvent> for Event {
+    type Error = String;
+
+    fn try_from(val: SetLedEvent) -> Result<Self, Self::Error> {
+        let event = match val.event_type.as_str() {
             "on" => Event::On,
             "off" => Event::Off,
-            "color" if val.color.is_some() => Event::Color(val.color.unwrap()),
+            "color" if val.color.is_some() => Event::Color(parse_color(&val.color.unwrap())?),
             "brightness" if val.brightness.is_some() => Event::Brightness(val.brightness.unwrap()),
+            "scene" if val.scene.is_some() => {
+                let name = val.scene.unwrap();
+                govee::find_scene(&name).ok_or(format!("Unknown scene: {}", name))?
+            }
+            "music" if val.music_mode.is_some() => {
+                let name = val.music_mode.unwrap();
+                let mode = MusicMode::ALL
+                    .into_iter()
+                    .find(|m| m.name().eq_ignore_ascii_case(&name))
+                    .ok_or(format!("Unknown music mode: {}", name))?;
+                Event::Music(mode)
+            }
+            "diy" if val.colors.is_some() => {
+                let style = match val.style {
+                    Some(name) => DiyStyle::ALL
+                        .into_iter()
+                        .find(|s| s.name().eq_ignore_ascii_case(&name))
+                        .ok_or(format!("Unknown diy style: {}", name))?,
+                    None => DiyStyle::Fade,
+                };
+                let colors = val
+                    .colors
+                    .unwrap()
+                    .iter()
+                    .map(|c| parse_color(c))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Event::Diy(Diy {
+                    style,
+                    speed: val.speed.unwrap_or(0),
+                    colors,
+                })
+            }
             _ => Event::Other(val.other_ev),
-        }
+        };
+        Ok(event)
     }
 }
 
@@ -161,22 +544,290 @@ async fn set_led(
     Path(addr): Path<String>,
     State(state): State<GlobalState>,
     Json(input): Json<SetLedEvent>,
-) -> impl IntoResponse {
-    let addr = Address::from_str(&addr).unwrap();
-    let mut state = state.lock().await;
+) -> Result<impl IntoResponse, ApiError> {
+    let addr = parse_address(&addr)?;
+    let transition = input.transition_ms.map(Duration::from_millis);
+    let event = Event::try_from(input).map_err(ApiError::BadRequest)?;
+    let entry = state
+        .devices
+        .get_entry(&addr)
+        .ok_or(ApiError::device_not_found(addr))?;
 
-    if let Some(device) = state.get_device(&addr) {
-        match device.on_event(input.into()).await {
-            _ => {}
+    queue::send(&state, entry, event, transition).await?;
+    Ok(StatusCode::OK)
+}
+
+async fn queue_stats(
+    Path(addr): Path<String>,
+    State(state): State<GlobalState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let addr = parse_address(&addr)?;
+    let entry = state
+        .devices
+        .get_entry(&addr)
+        .ok_or(ApiError::device_not_found(addr))?;
+
+    Ok(Json(entry.queue.stats()))
+}
+
+/// Longest scan a request may ask for, it blocks the request for that long.
+const MAX_SCAN_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Deserialize)]
+struct ScanQuery {
+    adapter: Option<String>,
+    timeout_secs: Option<u64>,
+    rssi: Option<i16>,
+    /// Comma separated.
+    service_uuids: Option<String>,
+}
+
+/// The options of the config, overridden by the ones given in `query`.
+fn scan_options(
+    mut options: DiscoveryOptions,
+    query: &ScanQuery,
+) -> Result<DiscoveryOptions, ApiError> {
+    if let Some(secs) = query.timeout_secs {
+        let timeout = Duration::from_secs(secs);
+        if timeout.is_zero() || timeout > MAX_SCAN_TIMEOUT {
+            return Err(ApiError::BadRequest(format!(
+                "timeout_secs must be between 1 and {}",
+                MAX_SCAN_TIMEOUT.as_secs()
+            )));
         }
+        options.timeout = timeout;
     }
+    if query.rssi.is_some() {
+        options.rssi = query.rssi;
+    }
+    if let Some(uuids) = &query.service_uuids {
+        options.service_uuids = uuids
+            .split(',')
+            .filter(|uuid| !uuid.trim().is_empty())
+            .map(|uuid| {
+                Uuid::parse_str(uuid.trim())
+                    .map_err(|_| ApiError::BadRequest(format!("Invalid UUID: {:?}", uuid)))
+            })
+            .collect::<Result<_, _>>()?;
+    }
+    Ok(options)
 }
 
-async fn index(State(state): State<GlobalState>) -> impl IntoResponse {
-    let state = state.lock().await;
+async fn scan_devices(
+    State(state): State<GlobalState>,
+    Query(query): Query<ScanQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (adapter, options) = {
+        let config = state.config.read().unwrap();
+        (
+            config.bluetooth.adapters.first().cloned(),
+            config.discovery.scan.clone(),
+        )
+    };
+
+    let options = scan_options(options, &query)?;
+    let adapter = query.adapter.or(adapter);
+
+    let devices = discovery::scan(adapter.as_deref(), &options).await?;
+    Ok(Json(devices))
+}
+
+/// Scans for supported strips that are not registered yet.
+async fn detected_devices(State(state): State<GlobalState>) -> Result<impl IntoResponse, ApiError> {
+    let (adapter, mut options) = {
+        let config = state.config.read().unwrap();
+        (
+            config.bluetooth.adapters.first().cloned(),
+            config.discovery.scan.clone(),
+        )
+    };
+    // Govee strips are recognized by name, they do not advertise their service.
+    options.service_uuids.clear();
+
+    let scanned = discovery::scan(adapter.as_deref(), &options).await?;
+    let detected = scanned
+        .into_iter()
+        .filter(|device| device.kind.is_some() && !state.devices.contains(&device.address))
+        .collect::<Vec<_>>();
+
+    *state.detected.lock().unwrap() = detected
+        .iter()
+        .map(|device| (device.address, device.clone()))
+        .collect();
+    Ok(Json(detected))
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AdoptRequest {
+    /// Needed for devices that were not detected.
+    kind: Option<DeviceKind>,
+    name: Option<String>,
+    room: Option<String>,
+}
 
+/// Registers a detected strip, using the default UUIDs of its kind.
+async fn adopt_device(
+    Path(addr): Path<String>,
+    State(state): State<GlobalState>,
+    input: Option<Json<AdoptRequest>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let addr = parse_address(&addr)?;
+    let input = input.map(|Json(input)| input).unwrap_or_default();
+
+    let scanned = state.detected.lock().unwrap().get(&addr).cloned();
+    let kind = input
+        .kind
+        .or_else(|| scanned.as_ref().and_then(|device| device.kind))
+        .ok_or_else(|| {
+            ApiError::NotFound(
+                "Device was not detected, scan with GET /api/detected or pass its kind".to_string(),
+            )
+        })?;
+
+    let mut raw = RawDeviceConfig::new(kind, addr);
+    raw.name = input
+        .name
+        .or_else(|| scanned.and_then(|device| device.name));
+    raw.room = input.room;
+
+    let info = register_device(&state, raw).await?;
+    state.detected.lock().unwrap().remove(&addr);
+    Ok((StatusCode::CREATED, Json(info)))
+}
+
+fn invalid_device(errors: Vec<String>) -> ApiError {
+    ApiError::BadRequest(errors.join("\n"))
+}
+
+fn save_failed(e: std::io::Error) -> ApiError {
+    ApiError::Internal(format!("Failed to save device: {}", e))
+}
+
+/// Validates, persists and registers a device that is not registered yet.
+async fn register_device(
+    state: &GlobalState,
+    raw: RawDeviceConfig,
+) -> Result<DeviceInfo, ApiError> {
+    let config = state
+        .config
+        .read()
+        .unwrap()
+        .device(raw.clone())
+        .map_err(invalid_device)?;
+    let addr = config.address;
+
+    let _changes = state.devices.changes.lock().await;
+    if state.devices.contains(&addr) {
+        return Err(ApiError::Conflict(format!(
+            "Device {} is already registered",
+            addr
+        )));
+    }
+    state.registry.set(addr, raw).map_err(save_failed)?;
+
+    let info = DeviceInfo::from(&config);
+    state.devices.upsert_device(config).await;
+    state
+        .events
+        .connection(addr, ConnectionStatus::Disconnected);
+    Ok(info)
+}
+
+async fn list_devices(State(state): State<GlobalState>) -> impl IntoResponse {
+    Json(state.devices.get_device_infos())
+}
+
+async fn add_device(
+    State(state): State<GlobalState>,
+    Json(input): Json<RawDeviceConfig>,
+) -> Result<impl IntoResponse, ApiError> {
+    let info = register_device(&state, input).await?;
+    Ok((StatusCode::CREATED, Json(info)))
+}
+
+async fn update_device(
+    Path(addr): Path<String>,
+    State(state): State<GlobalState>,
+    Json(patch): Json<DevicePatch>,
+) -> Result<impl IntoResponse, ApiError> {
+    let addr = parse_address(&addr)?;
+
+    let _changes = state.devices.changes.lock().await;
+    let entry = state
+        .devices
+        .get_entry(&addr)
+        .ok_or(ApiError::device_not_found(addr))?;
+
+    let config = {
+        let config = state.config.read().unwrap();
+        let raw = state
+            .registry
+            .patched(addr, &patch, &config)
+            .ok_or(ApiError::device_not_found(addr))?;
+        config.device(raw).map_err(invalid_device)?
+    };
+    state.registry.patch(addr, patch).map_err(save_failed)?;
+
+    let info = DeviceInfo::from(&config);
+    if state.devices.upsert_device(config).await {
+        state.supervisor.stop(&addr);
+        effects::stop(&state, addr);
+        state
+            .events
+            .connection(addr, ConnectionStatus::Disconnected);
+    } else {
+        // Lets subscribers pick up the new name and room.
+        state.events.connection(addr, ConnectionStatus::of(&entry));
+    }
+    Ok(Json(info))
+}
+
+async fn delete_device(
+    Path(addr): Path<String>,
+    State(state): State<GlobalState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let addr = parse_address(&addr)?;
+
+    let _changes = state.devices.changes.lock().await;
+    if !state.devices.contains(&addr) {
+        return Err(ApiError::device_not_found(addr));
+    }
+    let removed = state.registry.remove(addr, &state.config.read().unwrap());
+    removed.map_err(save_failed)?;
+
+    state.supervisor.stop(&addr);
+    effects::stop(&state, addr);
+    state.devices.remove_device(&addr).await;
+    state
+        .events
+        .connection(addr, ConnectionStatus::Disconnected);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Serialize)]
+struct SceneInfo {
+    name: String,
+    kind: &'static str,
+}
+
+async fn list_scenes() -> impl IntoResponse {
+    let scenes = govee::scene_catalog()
+        .into_iter()
+        .map(|(name, event)| SceneInfo {
+            name,
+            kind: match event {
+                Event::Music(_) => "music",
+                _ => "scene",
+            },
+        })
+        .collect::<Vec<_>>();
+
+    Json(scenes)
+}
+
+async fn index(State(state): State<GlobalState>) -> impl IntoResponse {
     let template = IndexTemplate {
-        devices: serde_json::to_string(&state.get_device_names()).unwrap(),
+        devices: serde_json::to_string(&state.devices.get_device_infos()).unwrap(),
     };
     HtmlTemplate(template)
 }
@@ -204,3 +855,157 @@ where
         }
     }
 }
+
+#[cfg(test)]
+impl GlobalState {
+    /// A server configured by the TOML `config`, keeping its files in a fresh temporary
+    /// directory named after `name`.
+    async fn for_tests(name: &str, config: &str) -> Self {
+        let data_dir = env::temp_dir().join(format!("gatt-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let config =
+            Config::from_toml(&format!("[storage]\ndata_dir = {:?}\n{}", data_dir, config));
+
+        let state = GlobalState::new(config);
+        let config = state.config.read().unwrap().clone();
+        state
+            .devices
+            .apply_config(state.registry.devices(&config))
+            .await;
+        state
+    }
+
+    /// Drives the Govee strip `addr` through a mock instead of Bluetooth.
+    async fn attach_mock(&self, addr: Address) -> devices::transport::MockTransport {
+        let mock = devices::transport::MockTransport::new();
+        let entry = self.devices.get_entry(&addr).unwrap();
+        match &mut *entry.device.lock().await {
+            Devices::Govee(led) => led.attach_transport(Arc::new(mock.clone())),
+            Devices::Esp(led) => led.attach_transport(Arc::new(mock.clone())),
+        }
+        mock
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(timeout_secs: Option<u64>, service_uuids: Option<&str>) -> ScanQuery {
+        ScanQuery {
+            adapter: None,
+            timeout_secs,
+            rssi: None,
+            service_uuids: service_uuids.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn scan_query_overrides_the_config() {
+        let config = DiscoveryOptions::default();
+        let options = scan_options(config.clone(), &query(None, None)).unwrap();
+        assert_eq!(options, config);
+
+        let query = ScanQuery {
+            rssi: Some(-70),
+            ..query(Some(60), Some("0000180d-0000-1000-8000-00805f9b34fb, "))
+        };
+        let options = scan_options(config, &query).unwrap();
+        assert_eq!(options.timeout, Duration::from_secs(60));
+        assert_eq!(options.rssi, Some(-70));
+        assert_eq!(
+            options.service_uuids,
+            [Uuid::from_u128(0x0000180d_0000_1000_8000_00805f9b34fb)].into()
+        );
+    }
+
+    #[test]
+    fn scan_timeout_is_between_1_and_60_seconds() {
+        for timeout in [0, 61] {
+            let query = query(Some(timeout), None);
+            match scan_options(DiscoveryOptions::default(), &query) {
+                Err(ApiError::BadRequest(message)) => {
+                    assert_eq!(message, "timeout_secs must be between 1 and 60")
+                }
+                other => panic!("{}: {:?}", timeout, other),
+            }
+        }
+        let query = query(Some(1), None);
+        assert!(scan_options(DiscoveryOptions::default(), &query).is_ok());
+    }
+
+    #[test]
+    fn scan_rejects_invalid_service_uuids() {
+        let query = query(None, Some("180d"));
+        assert!(matches!(
+            scan_options(DiscoveryOptions::default(), &query),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    async fn adopt(state: &GlobalState, addr: &str, input: Option<AdoptRequest>) -> StatusCode {
+        let result = adopt_device(
+            Path(addr.to_string()),
+            State(state.clone()),
+            input.map(Json),
+        );
+        match result.await {
+            Ok(response) => response.into_response().status(),
+            Err(e) => e.status(),
+        }
+    }
+
+    #[tokio::test]
+    async fn adopts_detected_and_named_strips() {
+        let state = GlobalState::for_tests("adopt", "").await;
+        let esp: Address = "A4:C1:38:EC:91:33".parse().unwrap();
+        state.detected.lock().unwrap().insert(
+            esp,
+            ScannedDevice {
+                address: esp,
+                address_type: bluer::AddressType::LePublic,
+                name: Some("Shelf".to_string()),
+                rssi: Some(-60),
+                uuids: vec![devices::esp::SERVICE_UUID],
+                kind: Some(DeviceKind::Esp),
+            },
+        );
+
+        assert_eq!(
+            adopt(&state, &esp.to_string(), None).await,
+            StatusCode::CREATED
+        );
+        let entry = state.devices.get_entry(&esp).unwrap();
+        let config = entry.config();
+        assert_eq!(config.name, "Shelf");
+        assert_eq!(config.service_uuid, devices::esp::SERVICE_UUID);
+        assert_eq!(
+            config.characteristic_uuid,
+            devices::esp::CHARACTERISTIC_UUID
+        );
+        assert!(matches!(&*entry.device.lock().await, Devices::Esp(_)));
+        assert!(state.detected.lock().unwrap().is_empty());
+        assert_eq!(
+            adopt(&state, &esp.to_string(), None).await,
+            StatusCode::NOT_FOUND
+        );
+
+        let govee = "A4:C1:38:EC:91:32";
+        assert_eq!(adopt(&state, govee, None).await, StatusCode::NOT_FOUND);
+        let input = AdoptRequest {
+            kind: Some(DeviceKind::Govee),
+            room: Some("Office".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(adopt(&state, govee, Some(input)).await, StatusCode::CREATED);
+        let entry = state.devices.get_entry(&govee.parse().unwrap()).unwrap();
+        let config = entry.config();
+        assert_eq!(
+            (config.name.as_str(), config.room.as_deref()),
+            (govee, Some("Office"))
+        );
+        assert_eq!(config.service_uuid, govee::SERVICE_UUID);
+        assert_eq!(config.characteristic_uuid, govee::CHARACTERISTIC_UUID);
+        assert!(matches!(&*entry.device.lock().await, Devices::Govee(_)));
+    }
+}
@@ -0,0 +1,673 @@
+use bluer::Address;
+use devices::{
+    backoff::Backoff, govee, govee::protocol::Mode, Color, DeviceKind, DeviceState, Event,
+};
+use log::{debug, info, warn};
+use rumqttc::{AsyncClient, Event as MqttEvent, LastWill, MqttOptions, Packet, Publish, QoS};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::{collections::HashSet, sync::Arc, sync::Mutex, time::Duration};
+use tokio::{sync::broadcast::error::RecvError, time};
+
+use crate::{
+    config::{DeviceConfig, MqttConfig},
+    events::LiveEvent,
+    queue,
+    v2::ConnectionStatus,
+    GlobalState,
+};
+
+/// Requests the client may queue while the connection to the broker is down.
+const CAPACITY: usize = 64;
+
+/// Publishes the registered devices as Home Assistant lights and carries out the commands
+/// Home Assistant sends for them.
+struct Bridge {
+    config: MqttConfig,
+    client: AsyncClient,
+    state: GlobalState,
+    /// Devices with a discovery config on the broker.
+    announced: Mutex<HashSet<Address>>,
+}
+
+/// Connects to the broker and keeps the bridge running for the lifetime of the server.
+pub fn spawn(config: MqttConfig, state: GlobalState) {
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let Some((username, password)) = &config.credentials {
+        options.set_credentials(username, password);
+    }
+    let bridge_topic = format!("{}/status", config.base_topic);
+    options.set_last_will(LastWill::new(
+        &bridge_topic,
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+
+    let (client, mut event_loop) = AsyncClient::new(options, CAPACITY);
+    let bridge = Arc::new(Bridge {
+        config,
+        client,
+        state,
+        announced: Default::default(),
+    });
+
+    tokio::spawn(bridge.clone().forward_changes());
+    tokio::spawn(async move {
+        let mut backoff = Backoff::default();
+        loop {
+            match event_loop.poll().await {
+                Ok(MqttEvent::Incoming(Packet::ConnAck(_))) => {
+                    info!("Connected to MQTT broker {}", bridge.config.host);
+                    backoff.reset();
+                    tokio::spawn(bridge.clone().on_connect());
+                }
+                // Handled here, so commands are queued in the order they arrived.
+                Ok(MqttEvent::Incoming(Packet::Publish(publish))) => bridge.on_publish(publish),
+                Ok(_) => {}
+                Err(e) => {
+                    let delay = backoff.next_delay();
+                    warn!(
+                        "MQTT connection to {}:{} failed, retrying in {:?}: {}",
+                        bridge.config.host, bridge.config.port, delay, e
+                    );
+                    time::sleep(delay).await;
+                }
+            }
+        }
+    });
+}
+
+/// The part of topics that identifies a device, its address without colons.
+fn object_id(addr: Address) -> String {
+    addr.to_string().replace(':', "").to_lowercase()
+}
+
+/// The name Home Assistant shows for the scene or music mode of `mode`.
+fn effect_name(mode: &Mode) -> Option<String> {
+    match mode {
+        Mode::Scene(scene) => Some(scene.name().to_string()),
+        Mode::Music(mode) => Some(format!("Music: {}", mode.name())),
+        Mode::Color(_) | Mode::Diy(_) => None,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RgbColor {
+    r: u8,
+    g: u8,
+    b: u8,
+}
+
+/// A command of a Home Assistant light using the JSON schema.
+#[derive(Debug, Deserialize)]
+struct LightCommand {
+    state: Option<String>,
+    brightness: Option<u8>,
+    color: Option<RgbColor>,
+    effect: Option<String>,
+    /// Seconds to fade colors and brightness over.
+    transition: Option<f64>,
+}
+
+impl LightCommand {
+    fn transition(&self) -> Result<Option<Duration>, String> {
+        self.transition
+            .map(|secs| {
+                Duration::try_from_secs_f64(secs)
+                    .map_err(|_| format!("Invalid transition {}", secs))
+            })
+            .transpose()
+    }
+
+    /// The events that carry out the command, in order.
+    fn events(self) -> Result<Vec<Event>, String> {
+        match self.state.as_deref() {
+            Some("OFF") => return Ok(vec![Event::Off]),
+            Some("ON") | None => {}
+            Some(state) => return Err(format!("Unknown state {:?}", state)),
+        }
+        let mut events = Vec::new();
+        if self.state.is_some() {
+            events.push(Event::On);
+        }
+        if let Some(RgbColor { r, g, b }) = self.color {
+            events.push(Event::Color(Color::new(r, g, b)));
+        }
+        if let Some(effect) = self.effect {
+            events.push(govee::find_scene(&effect).ok_or(format!("Unknown effect {:?}", effect))?);
+        }
+        if let Some(brightness) = self.brightness {
+            events.push(Event::Brightness(brightness));
+        }
+        Ok(events)
+    }
+}
+
+fn device_topic(mqtt: &MqttConfig, addr: Address, topic: &str) -> String {
+    format!("{}/{}/{}", mqtt.base_topic, object_id(addr), topic)
+}
+
+/// The Home Assistant discovery config of the light of a device.
+fn discovery_config(mqtt: &MqttConfig, config: &DeviceConfig) -> Value {
+    let addr = config.address;
+    let unique_id = format!("gatt_{}", object_id(addr));
+    let (manufacturer, model) = match config.kind {
+        DeviceKind::Govee => ("Govee", "H6127"),
+        DeviceKind::Esp => ("Espressif", "ESP32"),
+    };
+
+    let mut discovery = json!({
+        "name": null,
+        "unique_id": unique_id,
+        "schema": "json",
+        "command_topic": device_topic(mqtt, addr, "set"),
+        "state_topic": device_topic(mqtt, addr, "state"),
+        "availability": [
+            { "topic": format!("{}/status", mqtt.base_topic) },
+            { "topic": device_topic(mqtt, addr, "availability") },
+        ],
+        "availability_mode": "all",
+        "brightness": true,
+        "brightness_scale": 255,
+        "supported_color_modes": ["rgb"],
+        "device": {
+            "identifiers": [unique_id],
+            "connections": [["bluetooth", addr.to_string()]],
+            "name": config.name,
+            "manufacturer": manufacturer,
+            "model": model,
+            "suggested_area": config.room,
+        },
+    });
+    if config.kind == DeviceKind::Govee {
+        let effects = govee::scene_catalog()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        discovery["effect"] = json!(true);
+        discovery["effect_list"] = json!(effects);
+    }
+    discovery
+}
+
+/// `state` as the JSON schema of Home Assistant lights has it.
+fn state_payload(state: &DeviceState) -> Value {
+    let mut payload = Map::new();
+    if let Some(power) = state.power {
+        payload.insert("state".into(), json!(if power { "ON" } else { "OFF" }));
+    }
+    if let Some(brightness) = state.brightness {
+        payload.insert("brightness".into(), json!(brightness));
+    }
+    match &state.mode {
+        Some(Mode::Color(color)) => {
+            payload.insert("color_mode".into(), json!("rgb"));
+            payload.insert(
+                "color".into(),
+                json!({ "r": color.r, "g": color.g, "b": color.b }),
+            );
+        }
+        Some(mode) => {
+            payload.insert("effect".into(), json!(effect_name(mode)));
+        }
+        None => {}
+    }
+    Value::Object(payload)
+}
+
+impl Bridge {
+    fn device_topic(&self, addr: Address, topic: &str) -> String {
+        device_topic(&self.config, addr, topic)
+    }
+
+    fn discovery_topic(&self, addr: Address) -> String {
+        format!(
+            "{}/light/gatt_{}/config",
+            self.config.discovery_prefix,
+            object_id(addr)
+        )
+    }
+
+    async fn publish(&self, topic: String, retain: bool, payload: impl Into<Vec<u8>>) {
+        if let Err(e) = self
+            .client
+            .publish(&topic, QoS::AtLeastOnce, retain, payload)
+            .await
+        {
+            warn!("Failed to publish to {}: {}", topic, e);
+        }
+    }
+
+    async fn on_connect(self: Arc<Self>) {
+        let commands = format!("{}/+/set", self.config.base_topic);
+        let birth = format!("{}/status", self.config.discovery_prefix);
+        for topic in [commands, birth] {
+            if let Err(e) = self.client.subscribe(&topic, QoS::AtLeastOnce).await {
+                warn!("Failed to subscribe to {}: {}", topic, e);
+            }
+        }
+
+        let bridge_topic = format!("{}/status", self.config.base_topic);
+        self.publish(bridge_topic, true, "online").await;
+        self.announce_all().await;
+    }
+
+    /// Publishes the discovery config, availability and state of every registered device.
+    async fn announce_all(&self) {
+        for entry in self.state.devices.entries() {
+            let config = entry.config();
+            self.announce(&config, ConnectionStatus::of(&entry)).await;
+            if let Some(state) = self.state.store.get(&config.address) {
+                self.publish_state(config.address, &state).await;
+            }
+        }
+    }
+
+    async fn announce(&self, config: &DeviceConfig, status: ConnectionStatus) {
+        let addr = config.address;
+        let discovery = discovery_config(&self.config, config);
+        self.announced.lock().unwrap().insert(addr);
+        self.publish(self.discovery_topic(addr), true, discovery.to_string())
+            .await;
+
+        let availability = match status {
+            ConnectionStatus::Connected => "online",
+            ConnectionStatus::Disconnected => "offline",
+            // The connection is about to change, its outcome is published then.
+            ConnectionStatus::Busy => return,
+        };
+        self.publish(self.device_topic(addr, "availability"), true, availability)
+            .await;
+    }
+
+    /// Removes the light of a device that is no longer registered from Home Assistant.
+    async fn withdraw(&self, addr: Address) {
+        if !self.announced.lock().unwrap().remove(&addr) {
+            return;
+        }
+        info!("Removing {} from Home Assistant", addr);
+        self.publish(self.discovery_topic(addr), true, "").await;
+        self.publish(self.device_topic(addr, "availability"), true, "")
+            .await;
+        self.publish(self.device_topic(addr, "state"), true, "")
+            .await;
+    }
+
+    async fn publish_state(&self, addr: Address, state: &DeviceState) {
+        let payload = state_payload(state).to_string();
+        self.publish(self.device_topic(addr, "state"), true, payload)
+            .await;
+    }
+
+    /// Mirrors changes made through any API onto the broker.
+    async fn forward_changes(self: Arc<Self>) {
+        let mut changes = self.state.events.subscribe();
+        loop {
+            match changes.recv().await {
+                Ok(LiveEvent::Connection { address, status }) => {
+                    match self.state.devices.get_entry(&address) {
+                        Some(entry) => self.announce(&entry.config(), status).await,
+                        None => self.withdraw(address).await,
+                    }
+                }
+                Ok(LiveEvent::Command { address, state } | LiveEvent::State { address, state }) => {
+                    self.publish_state(address, &state).await;
+                }
+                Ok(LiveEvent::Effect { .. }) => {}
+                Err(RecvError::Lagged(missed)) => {
+                    debug!("MQTT bridge missed {} changes, republishing", missed);
+                    self.announce_all().await;
+                }
+                Err(RecvError::Closed) => return,
+            }
+        }
+    }
+
+    /// Queues the command in `publish` right away, only waiting for it happens in the background.
+    fn on_publish(self: &Arc<Self>, publish: Publish) {
+        if publish.topic == format!("{}/status", self.config.discovery_prefix) {
+            // Home Assistant forgets lights that are not retained when it restarts.
+            if publish.payload.as_ref() == b"online" {
+                let bridge = self.clone();
+                tokio::spawn(async move { bridge.announce_all().await });
+            }
+            return;
+        }
+
+        let Some(object_id) = publish
+            .topic
+            .strip_prefix(&format!("{}/", self.config.base_topic))
+            .and_then(|topic| topic.strip_suffix("/set"))
+        else {
+            return;
+        };
+        let Some(entry) = self
+            .state
+            .devices
+            .entries()
+            .into_iter()
+            .find(|entry| self::object_id(entry.config().address) == object_id)
+        else {
+            warn!("Ignoring command for unknown device {}", object_id);
+            return;
+        };
+        let addr = entry.config().address;
+
+        let command = serde_json::from_slice::<LightCommand>(&publish.payload)
+            .map_err(|e| e.to_string())
+            .and_then(|command| Ok((command.transition()?, command.events()?)));
+        let (transition, events) = match command {
+            Ok(command) => command,
+            Err(e) => {
+                warn!("Ignoring invalid command for {}: {}", addr, e);
+                return;
+            }
+        };
+
+        // Together, so color and brightness fade at the same time.
+        let sent = queue::send_all(&self.state, entry, events, transition);
+        tokio::spawn(async move {
+            match sent.await {
+                Ok(()) => {}
+                // Sliders send a command for every step, each stopping the fade of the last.
+                Err(devices::Error::Superseded) => {
+                    debug!("Command from MQTT to {} was superseded", addr)
+                }
+                Err(e) => warn!("Failed to apply command from MQTT to {}: {}", addr, e),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use bytes::BytesMut;
+    use rumqttc::mqttbytes::v4::{self, ConnAck, ConnectReturnCode, PubAck, SubAck};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    const ADDR: &str = "A4:C1:38:EC:91:32";
+    const DEVICES: &str = r#"
+        [[devices]]
+        kind = "govee"
+        address = "A4:C1:38:EC:91:32"
+        name = "Desk"
+        room = "Office"
+    "#;
+
+    fn command(json: &str) -> Result<(Option<Duration>, Vec<Event>), String> {
+        let command = serde_json::from_str::<LightCommand>(json).map_err(|e| e.to_string())?;
+        Ok((command.transition()?, command.events()?))
+    }
+
+    #[test]
+    fn light_commands_become_events_in_order() {
+        let (transition, events) = command(
+            r#"{"state": "ON", "brightness": 40, "color": {"r": 255, "g": 136, "b": 0},
+                "transition": 1.5}"#,
+        )
+        .unwrap();
+        assert_eq!(transition, Some(Duration::from_millis(1500)));
+        assert!(matches!(
+            events[..],
+            [Event::On, Event::Color(color), Event::Brightness(40)]
+                if color == Color::new(255, 136, 0)
+        ));
+
+        let (_, events) = command(r#"{"state": "OFF", "brightness": 40}"#).unwrap();
+        assert!(matches!(events[..], [Event::Off]));
+
+        let (_, events) = command(r#"{"brightness": 40}"#).unwrap();
+        assert!(matches!(events[..], [Event::Brightness(40)]));
+
+        let (_, events) = command(r#"{"state": "ON", "effect": "movie"}"#).unwrap();
+        assert!(matches!(events[..], [Event::On, Event::Scene(_)]));
+    }
+
+    #[test]
+    fn rejects_invalid_light_commands() {
+        assert!(command(r#"{"state": "DIM"}"#).is_err());
+        assert!(command(r#"{"effect": "Disco"}"#).is_err());
+        assert!(command(r#"{"transition": -1}"#).is_err());
+        assert!(command(r#"{"brightness": 256}"#).is_err());
+    }
+
+    #[test]
+    fn publishes_state_in_the_light_schema() {
+        let state = DeviceState {
+            connected: true,
+            power: Some(true),
+            brightness: Some(40),
+            mode: Some(Mode::Color(Color::new(255, 136, 0))),
+        };
+        assert_eq!(
+            state_payload(&state),
+            json!({
+                "state": "ON",
+                "brightness": 40,
+                "color_mode": "rgb",
+                "color": { "r": 255, "g": 136, "b": 0 },
+            })
+        );
+
+        let state = DeviceState {
+            power: Some(false),
+            mode: Some(Mode::Scene(govee::protocol::Scene::Movie)),
+            ..Default::default()
+        };
+        assert_eq!(
+            state_payload(&state),
+            json!({ "state": "OFF", "effect": "Movie" })
+        );
+        assert_eq!(state_payload(&DeviceState::default()), json!({}));
+    }
+
+    #[test]
+    fn discovery_describes_the_light() {
+        let config = Config::from_toml(&format!("[mqtt]\nbase_topic = \"leds\"\n{}", DEVICES));
+        let discovery = discovery_config(config.mqtt.as_ref().unwrap(), &config.devices[0]);
+
+        assert_eq!(discovery["unique_id"], "gatt_a4c138ec9132");
+        assert_eq!(discovery["command_topic"], "leds/a4c138ec9132/set");
+        assert_eq!(discovery["state_topic"], "leds/a4c138ec9132/state");
+        assert_eq!(
+            discovery["availability"],
+            json!([{ "topic": "leds/status" }, { "topic": "leds/a4c138ec9132/availability" }])
+        );
+        assert_eq!(discovery["device"]["name"], "Desk");
+        assert_eq!(discovery["device"]["suggested_area"], "Office");
+        assert_eq!(
+            discovery["device"]["connections"],
+            json!([["bluetooth", ADDR]])
+        );
+        assert_eq!(discovery["effect"], true);
+        assert!(discovery["effect_list"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("Movie")));
+    }
+
+    /// Just enough of a broker for one client: answers its handshakes, hands what it publishes
+    /// to the test and sends it what the test publishes.
+    struct Broker {
+        port: u16,
+        published: mpsc::UnboundedReceiver<Publish>,
+        to_client: mpsc::UnboundedSender<Publish>,
+    }
+
+    impl Broker {
+        async fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let (published_tx, published) = mpsc::unbounded_channel();
+            let (to_client, mut to_client_rx) = mpsc::unbounded_channel::<Publish>();
+
+            tokio::spawn(async move {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buffer = BytesMut::new();
+                loop {
+                    let mut out = BytesMut::new();
+                    tokio::select! {
+                        read = socket.read_buf(&mut buffer) => {
+                            if read.unwrap_or(0) == 0 {
+                                return;
+                            }
+                            while let Ok(packet) = v4::read(&mut buffer, 1 << 20) {
+                                match packet {
+                                    v4::Packet::Connect(_) => {
+                                        ConnAck::new(ConnectReturnCode::Success, false)
+                                            .write(&mut out)
+                                            .unwrap();
+                                    }
+                                    v4::Packet::Subscribe(subscribe) => {
+                                        let codes = subscribe
+                                            .filters
+                                            .iter()
+                                            .map(|filter| v4::SubscribeReasonCode::Success(filter.qos))
+                                            .collect();
+                                        SubAck::new(subscribe.pkid, codes).write(&mut out).unwrap();
+                                    }
+                                    v4::Packet::Publish(publish) => {
+                                        if publish.qos == QoS::AtLeastOnce {
+                                            PubAck::new(publish.pkid).write(&mut out).unwrap();
+                                        }
+                                        let _ = published_tx.send(publish);
+                                    }
+                                    v4::Packet::PingReq => {
+                                        v4::PingResp.write(&mut out).unwrap();
+                                    }
+                                    _ => {}
+                                }
+                            }
+                        }
+                        Some(publish) = to_client_rx.recv() => {
+                            publish.write(&mut out).unwrap();
+                        }
+                    }
+                    socket.write_all(&out).await.unwrap();
+                }
+            });
+
+            Self {
+                port,
+                published,
+                to_client,
+            }
+        }
+
+        /// The next publish of the client to `topic`, skipping others.
+        async fn next_on(&mut self, topic: &str) -> Publish {
+            let next = async {
+                loop {
+                    let publish = self.published.recv().await.unwrap();
+                    if publish.topic == topic {
+                        return publish;
+                    }
+                }
+            };
+            time::timeout(Duration::from_secs(5), next)
+                .await
+                .unwrap_or_else(|_| panic!("Nothing published to {}", topic))
+        }
+
+        fn send(&self, topic: &str, payload: &str) {
+            let publish = Publish::new(topic, QoS::AtMostOnce, payload);
+            self.to_client.send(publish).unwrap();
+        }
+    }
+
+    /// The frames written to the strip except keep alive queries, once there are `count`.
+    async fn commands(mock: &devices::transport::MockTransport, count: usize) -> Vec<Vec<u8>> {
+        let written = async {
+            loop {
+                let commands = mock
+                    .frames()
+                    .into_iter()
+                    .filter(|frame| frame[0] != 0xAA)
+                    .collect::<Vec<_>>();
+                if commands.len() >= count {
+                    return commands;
+                }
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        time::timeout(Duration::from_secs(5), written)
+            .await
+            .expect("Commands were not written")
+    }
+
+    #[tokio::test]
+    async fn bridges_home_assistant_through_a_broker() {
+        let mut broker = Broker::start().await;
+        let config = format!("[mqtt]\nport = {}\n{}", broker.port, DEVICES);
+        let state = GlobalState::for_tests("mqtt", &config).await;
+        let addr = ADDR.parse().unwrap();
+        let mock = state.attach_mock(addr).await;
+        let mqtt = state.config.read().unwrap().mqtt.clone().unwrap();
+        spawn(mqtt, state.clone());
+
+        // Announced once connected, after subscribing to commands.
+        let online = broker.next_on("gatt/status").await;
+        assert_eq!(online.payload.as_ref(), b"online");
+        assert!(online.retain);
+        let discovery = broker
+            .next_on("homeassistant/light/gatt_a4c138ec9132/config")
+            .await;
+        let discovery: Value = serde_json::from_slice(&discovery.payload).unwrap();
+        assert_eq!(discovery["command_topic"], "gatt/a4c138ec9132/set");
+
+        // Carried out in the order they were published.
+        broker.send(
+            "gatt/a4c138ec9132/set",
+            r#"{"state": "ON", "color": {"r": 255, "g": 0, "b": 0}}"#,
+        );
+        broker.send("gatt/a4c138ec9132/set", r#"{"brightness": 40}"#);
+        let written = commands(&mock, 3).await;
+        let kinds = written
+            .iter()
+            .map(|frame| (frame[1], frame[2]))
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, [(0x01, 0x01), (0x05, 0x02), (0x04, 40)]);
+
+        let mut published = Value::Null;
+        while published.get("brightness") != Some(&json!(40)) {
+            let state = broker.next_on("gatt/a4c138ec9132/state").await;
+            published = serde_json::from_slice(&state.payload).unwrap();
+        }
+        assert_eq!(published["state"], "ON");
+        assert_eq!(published["color"], json!({ "r": 255, "g": 0, "b": 0 }));
+
+        // Color and brightness of one command fade together.
+        mock.clear();
+        broker.send(
+            "gatt/a4c138ec9132/set",
+            r#"{"brightness": 200, "color": {"r": 0, "g": 0, "b": 255}, "transition": 0.5}"#,
+        );
+        let faded = time::timeout(Duration::from_secs(5), async {
+            loop {
+                let written = commands(&mock, 1).await;
+                if written
+                    .last()
+                    .is_some_and(|frame| frame[1] == 0x04 && frame[2] == 200)
+                {
+                    return written;
+                }
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("The fade did not end");
+        let kinds = faded.iter().map(|frame| frame[1]).collect::<Vec<_>>();
+        let first_brightness = kinds.iter().position(|kind| *kind == 0x04).unwrap();
+        let last_color = kinds.iter().rposition(|kind| *kind == 0x05).unwrap();
+        assert!(first_brightness < last_color, "{:02x?}", kinds);
+        assert!(faded.len() > 4, "{:02x?}", kinds);
+    }
+}
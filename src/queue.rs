@@ -0,0 +1,476 @@
+use devices::{govee::protocol::Mode, DeviceState, Devices, Event, LedDevice};
+use log::{debug, info};
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    sync::oneshot,
+    time::{self, Instant},
+};
+use utoipa::ToSchema;
+
+use crate::{effects, GlobalState, RegisteredDevice};
+
+/// Longest transition a command may ask for.
+pub const MAX_TRANSITION: Duration = Duration::from_secs(60 * 60);
+
+/// Which part of the state an event sets, a newer event for the same part supersedes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    Power,
+    Brightness,
+    Mode,
+}
+
+fn slot(event: &Event) -> Option<Slot> {
+    match event {
+        Event::On | Event::Off => Some(Slot::Power),
+        Event::Brightness(_) => Some(Slot::Brightness),
+        Event::Color(_) | Event::Scene(_) | Event::Music(_) | Event::Diy(_) => Some(Slot::Mode),
+        Event::Other(_) => None,
+    }
+}
+
+type Waiter = oneshot::Sender<devices::Result<()>>;
+
+#[derive(Debug)]
+struct Pending {
+    /// Written together, in order, so their colors and brightness fade at the same time.
+    events: Vec<Event>,
+    /// How long to fade to the colors and brightness of `events`.
+    transition: Option<Duration>,
+    /// Requests waiting for these events, including those of the events they superseded.
+    waiters: Vec<Waiter>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    pending: VecDeque<Pending>,
+    /// Whether a task is writing the pending events.
+    running: bool,
+    last_write: Option<Instant>,
+    sent: u64,
+    coalesced: u64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct QueueStats {
+    /// Commands waiting to be written.
+    pub depth: usize,
+    pub sent: u64,
+    pub coalesced: u64,
+}
+
+/// Events waiting to be written to one device, paced and without superseded events.
+#[derive(Debug, Default)]
+pub struct CommandQueue {
+    inner: Mutex<Inner>,
+}
+
+impl CommandQueue {
+    /// Queues `events`, dropping queued events they supersede. Returns whether a task has to be
+    /// started to write them.
+    fn push(&self, events: Vec<Event>, transition: Option<Duration>, waiter: Waiter) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+
+        let slots = events.iter().filter_map(slot).collect::<Vec<_>>();
+        let mut waiters = vec![waiter];
+        inner.pending.retain_mut(|pending| {
+            pending.events.retain(|queued| match slot(queued) {
+                Some(slot) if slots.contains(&slot) => {
+                    debug!("Dropping {:?}, superseded by {:?}", queued, events);
+                    inner.coalesced += 1;
+                    false
+                }
+                _ => true,
+            });
+            // Waiters of partly superseded events wait for the rest of them.
+            if !pending.events.is_empty() {
+                return true;
+            }
+            waiters.append(&mut pending.waiters);
+            false
+        });
+        inner.pending.push_back(Pending {
+            events,
+            transition,
+            waiters,
+        });
+
+        !std::mem::replace(&mut inner.running, true)
+    }
+
+    /// The next event to write, or `None` after marking the queue as idle.
+    fn pop(&self) -> Option<Pending> {
+        let mut inner = self.inner.lock().unwrap();
+        let next = inner.pending.pop_front();
+        if next.is_none() {
+            inner.running = false;
+        }
+        next
+    }
+
+    fn has_pending(&self) -> bool {
+        !self.inner.lock().unwrap().pending.is_empty()
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        let inner = self.inner.lock().unwrap();
+        QueueStats {
+            depth: inner.pending.len(),
+            sent: inner.sent,
+            coalesced: inner.coalesced,
+        }
+    }
+}
+
+/// Queues `event` for the device of `entry` and waits until it, or an event superseding it,
+/// was written. Stops the effect running on the device, which would override it.
+///
+/// With a `transition`, colors and brightness fade to the new value. The fade stops where it is
+/// when another command is queued for the device, answering [`devices::Error::Superseded`].
+pub async fn send(
+    state: &GlobalState,
+    entry: Arc<RegisteredDevice<Devices>>,
+    event: Event,
+    transition: Option<Duration>,
+) -> devices::Result<()> {
+    send_all(state, entry, vec![event], transition).await
+}
+
+/// Like [`send`] for several events written in order, like power, color and brightness of one
+/// command. With a `transition` their colors and brightness fade at the same time.
+///
+/// The events are queued when this is called, the returned future only waits for them to be
+/// written, so commands keep the order they were sent in without waiting for each other.
+pub fn send_all(
+    state: &GlobalState,
+    entry: Arc<RegisteredDevice<Devices>>,
+    events: Vec<Event>,
+    transition: Option<Duration>,
+) -> impl Future<Output = devices::Result<()>> {
+    let queued = enqueue(state, entry, events, transition);
+    async move {
+        match queued? {
+            // `drain` answers every waiter it takes, unless writing to the device panicked.
+            Some(result) => result.await.unwrap_or_else(|_| {
+                Err(devices::Error::Internal(
+                    "The command queue stopped before writing the command".to_string(),
+                ))
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Queues `events`, returning where the outcome is answered, `None` without events.
+fn enqueue(
+    state: &GlobalState,
+    entry: Arc<RegisteredDevice<Devices>>,
+    events: Vec<Event>,
+    transition: Option<Duration>,
+) -> devices::Result<Option<oneshot::Receiver<devices::Result<()>>>> {
+    if transition.is_some_and(|transition| transition > MAX_TRANSITION) {
+        return Err(devices::Error::InvalidCommand(format!(
+            "Transitions may take at most {} seconds",
+            MAX_TRANSITION.as_secs()
+        )));
+    }
+    effects::stop(state, entry.config().address);
+
+    if events.is_empty() {
+        return Ok(None);
+    }
+    match transition {
+        Some(transition) => info!(
+            "Set led on {}, {:?} over {:?}",
+            entry.config().address,
+            events,
+            transition
+        ),
+        None => info!("Set led on {}, {:?}", entry.config().address, events),
+    }
+
+    let (waiter, result) = oneshot::channel();
+    if entry.queue.push(events, transition, waiter) {
+        tokio::spawn(drain(state.clone(), entry));
+    }
+    Ok(Some(result))
+}
+
+/// Marks the queue as idle when `drain` panics, so the next command starts a new task instead
+/// of waiting for the dead one. A `drain` that returns was marked idle by `pop` already.
+struct Running<'a>(&'a CommandQueue);
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            if let Ok(mut inner) = self.0.inner.lock() {
+                inner.running = false;
+            }
+        }
+    }
+}
+
+/// Writes queued events until the queue is empty.
+async fn drain(state: GlobalState, entry: Arc<RegisteredDevice<Devices>>) {
+    let _running = Running(&entry.queue);
+
+    loop {
+        // Waiting before taking the next event lets events arriving meanwhile supersede it.
+        let min_interval = state.config.read().unwrap().queue.min_write_interval;
+        let last_write = entry.queue.inner.lock().unwrap().last_write;
+        if let Some(last_write) = last_write {
+            time::sleep_until(last_write + min_interval).await;
+        }
+        let Some(pending) = entry.queue.pop() else {
+            return;
+        };
+
+        let result = match pending.transition {
+            Some(transition) if !transition.is_zero() => {
+                fade(&state, &entry, pending.events, transition).await
+            }
+            _ => apply_events(&state, &entry, pending.events).await,
+        };
+
+        {
+            let mut inner = entry.queue.inner.lock().unwrap();
+            inner.last_write = Some(Instant::now());
+            inner.sent += 1;
+        }
+
+        for waiter in pending.waiters {
+            let _ = waiter.send(result.clone());
+        }
+    }
+}
+
+/// Applies `events` in order, stopping at the first that fails.
+async fn apply_events(
+    state: &GlobalState,
+    entry: &RegisteredDevice<Devices>,
+    events: Vec<Event>,
+) -> devices::Result<()> {
+    let addr = entry.config().address;
+    let mut device = entry.device.lock().await;
+    for event in events {
+        state.apply_event(addr, &mut *device, event).await?;
+    }
+    Ok(())
+}
+
+type Frame = Box<dyn Fn(f32) -> Event + Send + Sync>;
+
+/// The frames fading from the `recorded` state to `event`, `None` for events other than colors
+/// and brightness, or without a recorded value to start from.
+fn frames(event: &Event, recorded: &DeviceState) -> Option<Frame> {
+    match (event, recorded) {
+        (
+            Event::Color(to),
+            DeviceState {
+                mode: Some(Mode::Color(from)),
+                ..
+            },
+        ) => {
+            let (from, to) = (*from, *to);
+            Some(Box::new(move |t| Event::Color(from.mix_oklab(to, t))))
+        }
+        (
+            Event::Brightness(to),
+            DeviceState {
+                brightness: Some(from),
+                ..
+            },
+        ) => {
+            let (from, to) = (*from as f32, *to as f32);
+            Some(Box::new(move |t| {
+                Event::Brightness((from + (to - from) * t).round() as u8)
+            }))
+        }
+        _ => None,
+    }
+}
+
+/// Writes frames fading from the recorded state to `events` together, then the events
+/// themselves. Events that do not fade are applied at once, before the fade.
+async fn fade(
+    state: &GlobalState,
+    entry: &RegisteredDevice<Devices>,
+    events: Vec<Event>,
+    transition: Duration,
+) -> devices::Result<()> {
+    let addr = entry.config().address;
+    let recorded = state.store.get(&addr).unwrap_or_default();
+    let (faded, at_once): (Vec<_>, Vec<_>) = events
+        .into_iter()
+        .map(|event| (frames(&event, &recorded), event))
+        .partition(|(frame, _)| frame.is_some());
+
+    apply_events(
+        state,
+        entry,
+        at_once.into_iter().map(|(_, event)| event).collect(),
+    )
+    .await?;
+    let (frames, targets): (Vec<_>, Vec<_>) = faded
+        .into_iter()
+        .map(|(frame, event)| (frame.unwrap(), event))
+        .unzip();
+    if frames.is_empty() {
+        return Ok(());
+    }
+
+    let step = {
+        let config = state.config.read().unwrap();
+        config
+            .effects
+            .frame_interval
+            .max(config.queue.min_write_interval)
+    };
+    let started = Instant::now();
+    let mut written = Vec::new();
+    loop {
+        time::sleep(step).await;
+        let t = started.elapsed().as_secs_f32() / transition.as_secs_f32();
+        if t >= 1.0 {
+            break;
+        }
+
+        if entry.queue.has_pending() {
+            debug!("Stopping transition of {}, superseded", addr);
+            // The strip stays where the fade stopped, which the next command starts from.
+            for written in &written {
+                state.record(addr, written);
+            }
+            return Err(devices::Error::Superseded);
+        }
+
+        let mut device = entry.device.lock().await;
+        written.clear();
+        for frame in &frames {
+            let next = frame(t);
+            device.on_event(next.clone()).await?;
+            written.push(next);
+        }
+    }
+
+    apply_events(state, entry, targets).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use devices::Color;
+
+    fn push(queue: &CommandQueue, event: Event) -> (bool, oneshot::Receiver<devices::Result<()>>) {
+        push_all(queue, vec![event])
+    }
+
+    fn push_all(
+        queue: &CommandQueue,
+        events: Vec<Event>,
+    ) -> (bool, oneshot::Receiver<devices::Result<()>>) {
+        let (waiter, result) = oneshot::channel();
+        (queue.push(events, None, waiter), result)
+    }
+
+    #[test]
+    fn coalesces_events_of_the_same_slot() {
+        let queue = CommandQueue::default();
+        let (start, _) = push(&queue, Event::Color(Color::new(255, 0, 0)));
+        assert!(start);
+        let (start, _) = push(&queue, Event::On);
+        assert!(!start);
+        push(&queue, Event::Color(Color::new(0, 0, 255)));
+
+        let stats = queue.stats();
+        assert_eq!((stats.depth, stats.coalesced), (2, 1));
+        assert!(matches!(queue.pop().unwrap().events[..], [Event::On]));
+        let pending = queue.pop().unwrap();
+        assert!(matches!(pending.events[..], [Event::Color(color)] if color.b == 255));
+        assert_eq!(pending.waiters.len(), 2);
+        assert!(queue.pop().is_none());
+        assert!(push(&queue, Event::Off).0);
+    }
+
+    #[test]
+    fn supersedes_events_of_a_combined_command_one_by_one() {
+        let queue = CommandQueue::default();
+        push_all(
+            &queue,
+            vec![Event::Color(Color::new(255, 0, 0)), Event::Brightness(10)],
+        );
+        push(&queue, Event::Brightness(200));
+        push_all(
+            &queue,
+            vec![Event::Off, Event::Color(Color::new(0, 0, 255))],
+        );
+
+        assert_eq!(queue.stats().coalesced, 2);
+        let pending = queue.pop().unwrap();
+        assert!(matches!(pending.events[..], [Event::Brightness(200)]));
+        assert_eq!(pending.waiters.len(), 1);
+        let pending = queue.pop().unwrap();
+        assert!(matches!(pending.events[..], [Event::Off, Event::Color(_)]));
+        assert_eq!(pending.waiters.len(), 2);
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn panicking_drain_marks_the_queue_idle() {
+        let queue = CommandQueue::default();
+        let (_, mut result) = push(&queue, Event::On);
+
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _running = Running(&queue);
+            let _pending = queue.pop();
+            panic!("write failed");
+        }));
+        assert!(panicked.is_err());
+
+        assert!(result.try_recv().is_err(), "waiter answered");
+        assert!(push(&queue, Event::Off).0, "no new drain started");
+    }
+
+    #[tokio::test]
+    async fn superseded_transitions_answer_an_error() {
+        let state = GlobalState::for_tests(
+            "superseded",
+            "[[devices]]\nkind = \"govee\"\naddress = \"A4:C1:38:EC:91:32\"\n",
+        )
+        .await;
+        let addr = "A4:C1:38:EC:91:32".parse().unwrap();
+        let mock = state.attach_mock(addr).await;
+        let entry = state.devices.get_entry(&addr).unwrap();
+        let red = Event::Color(Color::new(255, 0, 0));
+        send(&state, entry.clone(), red, None).await.unwrap();
+
+        let blue = Color::new(0, 0, 255);
+        let fading = send_all(
+            &state,
+            entry.clone(),
+            vec![Event::Color(blue)],
+            Some(Duration::from_secs(2)),
+        );
+        time::sleep(Duration::from_millis(300)).await;
+        send(&state, entry, Event::Off, None).await.unwrap();
+
+        assert!(matches!(fading.await, Err(devices::Error::Superseded)));
+        let colors = mock
+            .frames()
+            .into_iter()
+            .filter(|frame| frame[1..3] == [0x05, 0x02])
+            .map(|frame| (frame[3], frame[4], frame[5]))
+            .collect::<Vec<_>>();
+        assert!(colors.len() > 1, "{:?}", colors);
+        assert!(!colors.contains(&(0, 0, 255)), "{:?}", colors);
+        let mut commands = mock.frames().into_iter().filter(|frame| frame[0] != 0xAA);
+        assert_eq!(commands.next_back().unwrap()[..3], [0x33, 0x01, 0x00]);
+    }
+}
@@ -0,0 +1,317 @@
+use bluer::Address;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, io, path::PathBuf, sync::Mutex};
+
+use crate::{
+    config::{Config, DeviceConfig, DevicePatch, RawDeviceConfig},
+    store::write_json,
+};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Contents {
+    /// `None` removes a device listed in the config.
+    #[serde(default)]
+    devices: BTreeMap<Address, Option<RawDeviceConfig>>,
+    /// Members of every group, by name.
+    #[serde(default)]
+    groups: BTreeMap<String, Vec<Address>>,
+    /// Fields changed at runtime of devices listed in the config, applied on top of their
+    /// entries so later edits of the other fields still take effect.
+    #[serde(default)]
+    patches: BTreeMap<Address, DevicePatch>,
+}
+
+impl Contents {
+    fn parse(content: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(content).or_else(|e| {
+            // Files written before groups only held the devices.
+            let devices = serde_json::from_str(content).map_err(|_| e)?;
+            Ok(Self {
+                devices,
+                ..Self::default()
+            })
+        })
+    }
+}
+
+/// Devices added, changed or removed at runtime on top of the ones listed in the config, and
+/// groups of devices, persisted as JSON so they survive restarts.
+#[derive(Debug)]
+pub struct Registry {
+    path: PathBuf,
+    contents: Mutex<Contents>,
+}
+
+impl Registry {
+    pub fn load(path: PathBuf) -> Self {
+        let contents = match fs::read_to_string(&path) {
+            Ok(content) => Contents::parse(&content).unwrap_or_else(|e| {
+                warn!("Ignoring invalid registry file {}: {}", path.display(), e);
+                Contents::default()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Contents::default(),
+            Err(e) => {
+                warn!("Failed to read registry file {}: {}", path.display(), e);
+                Contents::default()
+            }
+        };
+
+        Self {
+            path,
+            contents: Mutex::new(contents),
+        }
+    }
+
+    /// Adds or replaces a device, keeping the change only once it is saved.
+    pub fn set(&self, addr: Address, device: RawDeviceConfig) -> io::Result<()> {
+        self.change(|contents| {
+            contents.devices.insert(addr, Some(device));
+            contents.patches.remove(&addr);
+        })
+    }
+
+    /// The entry of device `addr` with `patch` applied after the earlier changes, or `None` when
+    /// the device is not registered.
+    pub fn patched(
+        &self,
+        addr: Address,
+        patch: &DevicePatch,
+        config: &Config,
+    ) -> Option<RawDeviceConfig> {
+        let contents = self.contents.lock().unwrap();
+        let mut raw = match contents.devices.get(&addr) {
+            Some(device) => device.clone()?,
+            None => {
+                let device = config
+                    .devices
+                    .iter()
+                    .find(|device| device.address == addr)?;
+                let mut raw = device.to_raw();
+                if let Some(earlier) = contents.patches.get(&addr) {
+                    earlier.apply(&mut raw);
+                }
+                raw
+            }
+        };
+        patch.apply(&mut raw);
+        Some(raw)
+    }
+
+    /// Changes the fields of device `addr` set in `patch`, keeping the change only once it is
+    /// saved. Devices added at runtime have their entry changed, devices listed in the config
+    /// only keep the changed fields.
+    pub fn patch(&self, addr: Address, patch: DevicePatch) -> io::Result<()> {
+        self.change(|contents| match contents.devices.get_mut(&addr) {
+            Some(Some(raw)) => patch.apply(raw),
+            _ => contents.patches.entry(addr).or_default().merge(patch),
+        })
+    }
+
+    /// Removes a device, hiding it from `config` when it is listed there, and takes it out of
+    /// every group, dropping the groups left without members.
+    pub fn remove(&self, addr: Address, config: &Config) -> io::Result<()> {
+        let listed = config.devices.iter().any(|device| device.address == addr);
+        self.change(|contents| {
+            if listed {
+                contents.devices.insert(addr, None);
+            } else {
+                contents.devices.remove(&addr);
+            }
+            contents.patches.remove(&addr);
+            contents.groups.retain(|_, members| {
+                members.retain(|member| *member != addr);
+                !members.is_empty()
+            });
+        })
+    }
+
+    pub fn groups(&self) -> BTreeMap<String, Vec<Address>> {
+        self.contents.lock().unwrap().groups.clone()
+    }
+
+    pub fn group(&self, name: &str) -> Option<Vec<Address>> {
+        self.contents.lock().unwrap().groups.get(name).cloned()
+    }
+
+    /// Creates or replaces a group, returns whether it existed.
+    pub fn set_group(&self, name: String, members: Vec<Address>) -> io::Result<bool> {
+        self.change(|contents| contents.groups.insert(name, members).is_some())
+    }
+
+    /// Removes a group, returns whether it existed.
+    pub fn remove_group(&self, name: &str) -> io::Result<bool> {
+        self.change(|contents| contents.groups.remove(name).is_some())
+    }
+
+    /// Applies `f` to a copy of the contents, keeping the change only once it is saved.
+    fn change<R>(&self, f: impl FnOnce(&mut Contents) -> R) -> io::Result<R> {
+        let mut contents = self.contents.lock().unwrap();
+        let mut updated = contents.clone();
+        let result = f(&mut updated);
+
+        write_json(&self.path, &updated)?;
+        *contents = updated;
+        Ok(result)
+    }
+
+    /// The devices of `config` with the changes made at runtime applied.
+    pub fn devices(&self, config: &Config) -> Vec<DeviceConfig> {
+        let contents = self.contents.lock().unwrap();
+        let changes = &contents.devices;
+
+        let mut devices = config
+            .devices
+            .iter()
+            .filter(|device| !changes.contains_key(&device.address))
+            .map(|device| {
+                let Some(patch) = contents.patches.get(&device.address) else {
+                    return device.clone();
+                };
+                let mut raw = device.to_raw();
+                patch.apply(&mut raw);
+                config.device(raw).unwrap_or_else(|errors| {
+                    warn!(
+                        "Ignoring changes to device {} that no longer fit the config: {}",
+                        device.address,
+                        errors.join(", ")
+                    );
+                    device.clone()
+                })
+            })
+            .collect::<Vec<_>>();
+        for (addr, device) in changes.iter() {
+            let Some(device) = device else {
+                continue;
+            };
+            match config.device(device.clone()) {
+                Ok(device) => devices.push(device),
+                Err(errors) => warn!(
+                    "Ignoring device {} that no longer fits the config: {}",
+                    addr,
+                    errors.join(", ")
+                ),
+            }
+        }
+        devices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use devices::DeviceKind;
+
+    const DESK: &str = "A4:C1:38:EC:91:32";
+    const SHELF: &str = "A4:C1:38:EC:91:33";
+
+    fn registry(name: &str) -> Registry {
+        let path = std::env::temp_dir().join(format!(
+            "gatt-registry-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        Registry::load(path)
+    }
+
+    #[test]
+    fn parses_files_written_before_groups() {
+        let legacy = format!(r#"{{"{}": null}}"#, DESK);
+        let contents = Contents::parse(&legacy).unwrap();
+        assert_eq!(contents.devices.len(), 1);
+        assert!(contents.devices[&DESK.parse().unwrap()].is_none());
+        assert!(contents.groups.is_empty());
+
+        let current = format!(r#"{{"devices": {{}}, "groups": {{"desk": ["{}"]}}}}"#, DESK);
+        let contents = Contents::parse(&current).unwrap();
+        assert!(contents.devices.is_empty());
+        assert_eq!(contents.groups["desk"], [DESK.parse().unwrap()]);
+
+        assert!(Contents::parse(r#"{"groups": {}, "rooms": {}}"#).is_err());
+    }
+
+    #[test]
+    fn remove_drops_groups_left_empty() {
+        let registry = registry("remove");
+        let (desk, shelf) = (DESK.parse().unwrap(), SHELF.parse().unwrap());
+        registry.set_group("desk".to_string(), vec![desk]).unwrap();
+        registry
+            .set_group("office".to_string(), vec![desk, shelf])
+            .unwrap();
+
+        registry.remove(desk, &Config::from_toml("")).unwrap();
+        assert_eq!(registry.group("desk"), None);
+        assert_eq!(registry.group("office"), Some(vec![shelf]));
+
+        let reloaded = Registry::load(registry.path.clone());
+        assert_eq!(reloaded.groups(), registry.groups());
+        fs::remove_file(&registry.path).unwrap();
+    }
+
+    fn config(room: &str) -> Config {
+        Config::from_toml(&format!(
+            "[[devices]]\nkind = \"govee\"\naddress = \"{}\"\nroom = \"{}\"\n",
+            DESK, room
+        ))
+    }
+
+    #[test]
+    fn keeps_only_the_patched_fields_of_listed_devices() {
+        let registry = registry("patch");
+        let desk = DESK.parse().unwrap();
+        let patch = DevicePatch {
+            name: Some("Desk".to_string()),
+            ..Default::default()
+        };
+        let raw = registry.patched(desk, &patch, &config("Office")).unwrap();
+        assert_eq!(
+            (raw.name.as_deref(), raw.room.as_deref()),
+            (Some("Desk"), Some("Office"))
+        );
+        registry.patch(desk, patch).unwrap();
+
+        let saved: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&registry.path).unwrap()).unwrap();
+        assert_eq!(saved["patches"][DESK], serde_json::json!({"name": "Desk"}));
+        assert_eq!(saved["devices"], serde_json::json!({}));
+
+        // Edits of the config still apply to the fields that were not patched.
+        let devices = registry.devices(&config("Study"));
+        assert_eq!(devices[0].name, "Desk");
+        assert_eq!(devices[0].room.as_deref(), Some("Study"));
+
+        let clear_room = serde_json::from_str(r#"{"room": null}"#).unwrap();
+        registry.patch(desk, clear_room).unwrap();
+        let devices = Registry::load(registry.path.clone()).devices(&config("Study"));
+        assert_eq!(
+            (devices[0].name.as_str(), devices[0].room.as_deref()),
+            ("Desk", None)
+        );
+
+        registry.remove(desk, &config("Study")).unwrap();
+        assert!(registry.devices(&config("Study")).is_empty());
+        fs::remove_file(&registry.path).unwrap();
+    }
+
+    #[test]
+    fn patches_the_entry_of_devices_added_at_runtime() {
+        let registry = registry("patch-added");
+        let shelf = SHELF.parse().unwrap();
+        registry
+            .set(shelf, RawDeviceConfig::new(DeviceKind::Esp, shelf))
+            .unwrap();
+        let patch = DevicePatch {
+            room: Some(Some("Hall".to_string())),
+            ..Default::default()
+        };
+        registry.patch(shelf, patch).unwrap();
+
+        let devices = registry.devices(&config("Office"));
+        let shelf_config = devices.iter().find(|d| d.address == shelf).unwrap();
+        assert_eq!(shelf_config.room.as_deref(), Some("Hall"));
+        assert!(registry.contents.lock().unwrap().patches.is_empty());
+        fs::remove_file(&registry.path).unwrap();
+    }
+}
@@ -0,0 +1,855 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use bluer::Address;
+use chrono::{DateTime, Datelike, Days, Local, NaiveTime, TimeZone, Weekday};
+use futures::future::join_all;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::PathBuf,
+    str::FromStr,
+    sync::Mutex,
+    time::Duration,
+};
+use tokio::{sync::Notify, time};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    config::Location,
+    error::ApiError,
+    queue::{self, MAX_TRANSITION},
+    store::write_json,
+    sun::{self, SunEvent},
+    v2::{ApiJson, ApiQuery, CommandRequest},
+    GlobalState,
+};
+
+/// Longest the scheduler sleeps without looking at the clock, which may be set meanwhile.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+/// Most fire times a preview lists.
+const MAX_PREVIEW: usize = 100;
+/// Furthest a sun trigger may be shifted from sunrise or sunset, in minutes.
+const MAX_SUN_OFFSET: i32 = 12 * 60;
+
+/// When a schedule fires, in the local time zone of the server.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Trigger {
+    /// A cron expression, with five fields as in crontab, where Sunday is 0 or 7, or with
+    /// seconds as the first of six, where Sunday is 1 and Saturday 7.
+    Cron {
+        #[schema(example = "30 23 * * *")]
+        expression: String,
+    },
+    /// Every day at a time, or only on `days`.
+    Time {
+        #[schema(value_type = String, example = "23:30")]
+        at: NaiveTime,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        #[schema(value_type = Vec<String>, example = json!(["Mon", "Fri"]))]
+        days: Vec<Weekday>,
+    },
+    /// Sunrise or sunset at the configured `[location]`, shifted by `offset_minutes`.
+    Sun {
+        event: SunEvent,
+        #[serde(default)]
+        offset_minutes: i32,
+    },
+}
+
+fn cron_schedule(expression: &str) -> Result<cron::Schedule, String> {
+    // The crate wants seconds, cron as people write it starts with minutes.
+    let fields: Vec<&str> = expression.split_whitespace().collect();
+    let expression = match fields[..] {
+        [minute, hour, day, month, weekday] => format!(
+            "0 {} {} {} {} {}",
+            minute,
+            hour,
+            day,
+            month,
+            crontab_weekdays(weekday)?
+        ),
+        _ => expression.to_string(),
+    };
+    cron::Schedule::from_str(&expression)
+        .map_err(|e| format!("Invalid cron expression {:?}: {}", expression, e))
+}
+
+/// Turns a crontab day-of-week field, where Sunday is 0 or 7, into the numbering of the cron
+/// crate, where Sunday is 1 and Saturday 7. Numeric ranges and steps are expanded over 0 to 7
+/// first, names such as `MON` are passed through.
+fn crontab_weekdays(field: &str) -> Result<String, String> {
+    let number = |value: &str| match value.parse::<u32>() {
+        Ok(day @ 0..=7) => Ok(day),
+        _ => Err(format!("Day of week {:?} is not between 0 and 7", value)),
+    };
+    let mut items = Vec::new();
+    let mut days = BTreeSet::new();
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => match step.parse::<usize>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("Invalid day of week step in {:?}", item)),
+            },
+            None => (item, 1),
+        };
+        if range.contains(|c: char| c.is_ascii_alphabetic() || c == '?')
+            || (range == "*" && step == 1)
+        {
+            items.push(item.to_string());
+            continue;
+        }
+
+        let (start, end) = match (range, range.split_once('-')) {
+            ("*", _) => (0, 7),
+            (_, Some((start, end))) => (number(start)?, number(end)?),
+            // `3/2` steps from 3 to the end of the week.
+            (start, None) if item.contains('/') => (number(start)?, 7),
+            (day, None) => (number(day)?, number(day)?),
+        };
+        if start > end {
+            return Err(format!(
+                "Day of week range {:?} ends before it starts",
+                range
+            ));
+        }
+        days.extend((start..=end).step_by(step).map(|day| day % 7 + 1));
+    }
+    items.extend(days.into_iter().map(|day| day.to_string()));
+    Ok(items.join(","))
+}
+
+impl Trigger {
+    fn validate(&self, location: Option<Location>) -> Result<(), String> {
+        match self {
+            Trigger::Cron { expression } => cron_schedule(expression).map(|_| ()),
+            Trigger::Time { .. } => Ok(()),
+            Trigger::Sun { .. } if location.is_none() => {
+                Err("Sun triggers need a [location] in the config".to_string())
+            }
+            Trigger::Sun { offset_minutes, .. } if offset_minutes.abs() > MAX_SUN_OFFSET => {
+                Err(format!(
+                    "offset_minutes must be between -{0} and {0}",
+                    MAX_SUN_OFFSET
+                ))
+            }
+            Trigger::Sun { .. } => Ok(()),
+        }
+    }
+
+    /// The first time the trigger fires after `after`.
+    fn next_after<Tz: TimeZone>(
+        &self,
+        after: DateTime<Tz>,
+        location: Option<Location>,
+    ) -> Option<DateTime<Tz>> {
+        let zone = after.timezone();
+        match self {
+            Trigger::Cron { expression } => cron_schedule(expression).ok()?.after(&after).next(),
+            Trigger::Time { at, days } => (0..=7)
+                .filter_map(|day| after.date_naive().checked_add_days(Days::new(day)))
+                .filter(|date| days.is_empty() || days.contains(&date.weekday()))
+                // Skips times that do not exist on days the clocks are set forward.
+                .filter_map(|date| zone.from_local_datetime(&date.and_time(*at)).earliest())
+                .find(|time| *time > after),
+            Trigger::Sun {
+                event,
+                offset_minutes,
+            } => {
+                let location = location?;
+                let offset = chrono::Duration::minutes(*offset_minutes as i64);
+                // Polar days and nights may go without sunrise for months.
+                let yesterday = after.date_naive().checked_sub_days(Days::new(1))?;
+                yesterday
+                    .iter_days()
+                    .take(368)
+                    .filter_map(|date| sun::time(*event, date, location))
+                    .map(|time| (time + offset).with_timezone(&zone))
+                    .find(|time| *time > after)
+            }
+        }
+    }
+
+    /// The next `count` times the trigger fires after `after`.
+    fn preview<Tz: TimeZone>(
+        &self,
+        after: DateTime<Tz>,
+        location: Option<Location>,
+        count: usize,
+    ) -> Vec<DateTime<Tz>> {
+        let mut times = Vec::new();
+        let mut after = after;
+        while times.len() < count {
+            let Some(next) = self.next_after(after, location) else {
+                break;
+            };
+            times.push(next.clone());
+            after = next;
+        }
+        times
+    }
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// Commands to send to some devices whenever a trigger fires.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default = "enabled")]
+    enabled: bool,
+    trigger: Trigger,
+    /// Bluetooth addresses of the devices, which receive the actions at the same time.
+    #[schema(value_type = Vec<String>, example = json!(["A4:C1:38:EC:91:32"]))]
+    devices: Vec<Address>,
+    /// Sent to every device in order.
+    actions: Vec<CommandRequest>,
+}
+
+impl Schedule {
+    fn label(&self, id: u64) -> String {
+        match &self.name {
+            Some(name) => format!("{} ({})", id, name),
+            None => id.to_string(),
+        }
+    }
+
+    fn validate(&self, state: &GlobalState) -> Result<(), ApiError> {
+        let location = state.config.read().unwrap().location;
+        self.trigger
+            .validate(location)
+            .map_err(ApiError::BadRequest)?;
+
+        if self.devices.is_empty() {
+            return Err(ApiError::BadRequest(
+                "devices must not be empty".to_string(),
+            ));
+        }
+        if let Some(addr) = self
+            .devices
+            .iter()
+            .find(|addr| !state.devices.contains(addr))
+        {
+            return Err(ApiError::device_not_found(*addr));
+        }
+
+        if self.actions.is_empty() {
+            return Err(ApiError::BadRequest(
+                "actions must not be empty".to_string(),
+            ));
+        }
+        let mut too_long = false;
+        for action in &self.actions {
+            let (_, transition) = action.clone().into_events()?;
+            too_long |= transition.is_some_and(|transition| transition > MAX_TRANSITION);
+        }
+        if too_long {
+            return Err(ApiError::BadRequest(format!(
+                "Transitions may take at most {} seconds",
+                MAX_TRANSITION.as_secs()
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Contents {
+    /// The highest id handed out so far, ids of deleted schedules are not used again.
+    last_id: u64,
+    schedules: BTreeMap<u64, Schedule>,
+}
+
+impl Contents {
+    fn parse(content: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(content).or_else(|e| {
+            // Files written before ids were counted only held the schedules.
+            let schedules: BTreeMap<u64, Schedule> =
+                serde_json::from_str(content).map_err(|_| e)?;
+            Ok(Self {
+                last_id: schedules.keys().next_back().copied().unwrap_or_default(),
+                schedules,
+            })
+        })
+    }
+}
+
+/// The schedules, persisted as JSON so they survive restarts.
+#[derive(Debug)]
+pub struct Schedules {
+    path: PathBuf,
+    contents: Mutex<Contents>,
+    /// Wakes the scheduler to look at the changed schedules.
+    changed: Notify,
+}
+
+impl Schedules {
+    pub fn load(path: PathBuf) -> Self {
+        let contents = match fs::read_to_string(&path) {
+            Ok(content) => Contents::parse(&content).unwrap_or_else(|e| {
+                warn!("Ignoring invalid schedules file {}: {}", path.display(), e);
+                Contents::default()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Contents::default(),
+            Err(e) => {
+                warn!("Failed to read schedules file {}: {}", path.display(), e);
+                Contents::default()
+            }
+        };
+
+        Self {
+            path,
+            contents: Mutex::new(contents),
+            changed: Notify::new(),
+        }
+    }
+
+    fn list(&self) -> Vec<(u64, Schedule)> {
+        let contents = self.contents.lock().unwrap();
+        contents
+            .schedules
+            .iter()
+            .map(|(id, schedule)| (*id, schedule.clone()))
+            .collect()
+    }
+
+    fn get(&self, id: u64) -> Option<Schedule> {
+        self.contents.lock().unwrap().schedules.get(&id).cloned()
+    }
+
+    /// Adds a schedule under an id no schedule had before, returns the id.
+    fn create(&self, schedule: Schedule) -> io::Result<u64> {
+        self.update(|contents| {
+            contents.last_id += 1;
+            contents.schedules.insert(contents.last_id, schedule);
+            contents.last_id
+        })
+    }
+
+    /// Applies `f` to a copy of the schedules, keeping the change only once it is saved.
+    fn change<R>(&self, f: impl FnOnce(&mut BTreeMap<u64, Schedule>) -> R) -> io::Result<R> {
+        self.update(|contents| f(&mut contents.schedules))
+    }
+
+    fn update<R>(&self, f: impl FnOnce(&mut Contents) -> R) -> io::Result<R> {
+        let mut contents = self.contents.lock().unwrap();
+        let mut updated = contents.clone();
+        let result = f(&mut updated);
+
+        write_json(&self.path, &updated)?;
+        *contents = updated;
+        self.changed.notify_one();
+        Ok(result)
+    }
+}
+
+/// Fires the schedules for the lifetime of the server.
+pub async fn run(state: GlobalState) {
+    let mut checked = Local::now();
+    loop {
+        let location = state.config.read().unwrap().location;
+        let now = Local::now();
+        let schedules = state.schedules.list();
+
+        for (id, schedule) in &schedules {
+            let due = schedule
+                .trigger
+                .next_after(checked, location)
+                .is_some_and(|at| at <= now);
+            if schedule.enabled && due {
+                tokio::spawn(fire(state.clone(), *id, schedule.clone()));
+            }
+        }
+        checked = now;
+
+        let next = schedules
+            .iter()
+            .filter(|(_, schedule)| schedule.enabled)
+            .filter_map(|(_, schedule)| schedule.trigger.next_after(now, location))
+            .min();
+        let sleep = next
+            .and_then(|next| (next - now).to_std().ok())
+            .map_or(MAX_SLEEP, |sleep| sleep.min(MAX_SLEEP));
+        tokio::select! {
+            _ = time::sleep(sleep) => {}
+            _ = state.schedules.changed.notified() => {}
+        }
+    }
+}
+
+/// Sends the actions of `schedule` to all of its devices at once.
+async fn fire(state: GlobalState, id: u64, schedule: Schedule) {
+    info!("Running schedule {}", schedule.label(id));
+
+    let sends = schedule.devices.iter().map(|addr| {
+        let state = &state;
+        let actions = &schedule.actions;
+        async move {
+            let Some(entry) = state.devices.get_entry(addr) else {
+                warn!("Schedule {} skips unregistered device {}", id, addr);
+                return;
+            };
+            for action in actions {
+                let Ok((events, transition)) = action.clone().into_events() else {
+                    continue;
+                };
+                if let Err(e) = queue::send_all(state, entry.clone(), events, transition).await {
+                    warn!("Schedule {} failed on {}: {}", id, addr, e);
+                    return;
+                }
+            }
+        }
+    });
+    join_all(sends).await;
+}
+
+pub fn router() -> Router<GlobalState> {
+    Router::new()
+        .route("/schedules", get(list_schedules).post(create_schedule))
+        .route("/schedules/preview", post(preview_schedule))
+        .route(
+            "/schedules/:id",
+            get(get_schedule)
+                .put(replace_schedule)
+                .delete(delete_schedule),
+        )
+        .route("/schedules/:id/next", get(next_fires))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ScheduleInfo {
+    id: u64,
+    name: Option<String>,
+    enabled: bool,
+    trigger: Trigger,
+    #[schema(value_type = Vec<String>)]
+    devices: Vec<Address>,
+    actions: Vec<CommandRequest>,
+    /// `null` for disabled schedules and triggers that never fire.
+    #[schema(value_type = Option<String>, format = DateTime)]
+    next_fire: Option<DateTime<Local>>,
+}
+
+impl ScheduleInfo {
+    fn new(state: &GlobalState, id: u64, schedule: Schedule) -> Self {
+        let location = state.config.read().unwrap().location;
+        let next_fire = match schedule.enabled {
+            true => schedule.trigger.next_after(Local::now(), location),
+            false => None,
+        };
+
+        Self {
+            id,
+            name: schedule.name,
+            enabled: schedule.enabled,
+            trigger: schedule.trigger,
+            devices: schedule.devices,
+            actions: schedule.actions,
+            next_fire,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct PreviewQuery {
+    /// How many fire times to list, 5 by default.
+    count: Option<usize>,
+}
+
+impl PreviewQuery {
+    fn count(&self) -> Result<usize, ApiError> {
+        match self.count.unwrap_or(5) {
+            count @ 1..=MAX_PREVIEW => Ok(count),
+            _ => Err(ApiError::BadRequest(format!(
+                "count must be between 1 and {}",
+                MAX_PREVIEW
+            ))),
+        }
+    }
+}
+
+fn parse_id(id: &str) -> Result<u64, ApiError> {
+    id.parse()
+        .map_err(|_| ApiError::BadRequest(format!("Invalid schedule id {:?}", id)))
+}
+
+fn schedule_not_found(id: u64) -> ApiError {
+    ApiError::NotFound(format!("Schedule {} does not exist", id))
+}
+
+fn save_failed(e: io::Error) -> ApiError {
+    ApiError::Internal(format!("Failed to save schedules: {}", e))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/schedules",
+    responses((status = 200, body = [ScheduleInfo]))
+)]
+pub async fn list_schedules(State(state): State<GlobalState>) -> Json<Vec<ScheduleInfo>> {
+    let schedules = state
+        .schedules
+        .list()
+        .into_iter()
+        .map(|(id, schedule)| ScheduleInfo::new(&state, id, schedule))
+        .collect();
+    Json(schedules)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/schedules",
+    request_body = Schedule,
+    responses(
+        (status = 201, body = ScheduleInfo),
+        (status = 400, description = "Invalid schedule", body = ErrorBody),
+        (status = 404, description = "A device is not registered", body = ErrorBody)
+    )
+)]
+pub async fn create_schedule(
+    State(state): State<GlobalState>,
+    ApiJson(schedule): ApiJson<Schedule>,
+) -> Result<(StatusCode, Json<ScheduleInfo>), ApiError> {
+    schedule.validate(&state)?;
+
+    let created = schedule.clone();
+    let id = state.schedules.create(created).map_err(save_failed)?;
+
+    info!("Created schedule {}", schedule.label(id));
+    Ok((
+        StatusCode::CREATED,
+        Json(ScheduleInfo::new(&state, id, schedule)),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/schedules/{id}",
+    params(("id" = u64, Path, description = "Id of the schedule")),
+    responses(
+        (status = 200, body = ScheduleInfo),
+        (status = 404, description = "Schedule does not exist", body = ErrorBody)
+    )
+)]
+pub async fn get_schedule(
+    Path(id): Path<String>,
+    State(state): State<GlobalState>,
+) -> Result<Json<ScheduleInfo>, ApiError> {
+    let id = parse_id(&id)?;
+    let schedule = state.schedules.get(id).ok_or(schedule_not_found(id))?;
+    Ok(Json(ScheduleInfo::new(&state, id, schedule)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v2/schedules/{id}",
+    params(("id" = u64, Path, description = "Id of the schedule")),
+    request_body = Schedule,
+    responses(
+        (status = 200, body = ScheduleInfo),
+        (status = 400, description = "Invalid schedule", body = ErrorBody),
+        (status = 404, description = "Schedule does not exist, or a device is not registered", body = ErrorBody)
+    )
+)]
+pub async fn replace_schedule(
+    Path(id): Path<String>,
+    State(state): State<GlobalState>,
+    ApiJson(schedule): ApiJson<Schedule>,
+) -> Result<Json<ScheduleInfo>, ApiError> {
+    let id = parse_id(&id)?;
+    schedule.validate(&state)?;
+
+    let replaced = schedule.clone();
+    let found = state
+        .schedules
+        .change(|schedules| match schedules.get_mut(&id) {
+            Some(previous) => {
+                *previous = replaced;
+                true
+            }
+            None => false,
+        })
+        .map_err(save_failed)?;
+    if !found {
+        return Err(schedule_not_found(id));
+    }
+
+    Ok(Json(ScheduleInfo::new(&state, id, schedule)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v2/schedules/{id}",
+    params(("id" = u64, Path, description = "Id of the schedule")),
+    responses(
+        (status = 204, description = "Schedule deleted"),
+        (status = 404, description = "Schedule does not exist", body = ErrorBody)
+    )
+)]
+pub async fn delete_schedule(
+    Path(id): Path<String>,
+    State(state): State<GlobalState>,
+) -> Result<StatusCode, ApiError> {
+    let id = parse_id(&id)?;
+    let removed = state
+        .schedules
+        .change(|schedules| schedules.remove(&id))
+        .map_err(save_failed)?;
+    let schedule = removed.ok_or(schedule_not_found(id))?;
+
+    info!("Deleted schedule {}", schedule.label(id));
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/schedules/{id}/next",
+    params(("id" = u64, Path, description = "Id of the schedule"), PreviewQuery),
+    responses(
+        (status = 200, description = "The next times the schedule fires, even when it is disabled", body = [String]),
+        (status = 404, description = "Schedule does not exist", body = ErrorBody)
+    )
+)]
+pub async fn next_fires(
+    Path(id): Path<String>,
+    State(state): State<GlobalState>,
+    ApiQuery(query): ApiQuery<PreviewQuery>,
+) -> Result<Json<Vec<DateTime<Local>>>, ApiError> {
+    let id = parse_id(&id)?;
+    let count = query.count()?;
+    let schedule = state.schedules.get(id).ok_or(schedule_not_found(id))?;
+
+    let location = state.config.read().unwrap().location;
+    Ok(Json(schedule.trigger.preview(
+        Local::now(),
+        location,
+        count,
+    )))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/schedules/preview",
+    params(PreviewQuery),
+    request_body = Trigger,
+    responses(
+        (status = 200, description = "The next times the trigger would fire", body = [String]),
+        (status = 400, description = "Invalid trigger", body = ErrorBody)
+    )
+)]
+pub async fn preview_schedule(
+    State(state): State<GlobalState>,
+    ApiQuery(query): ApiQuery<PreviewQuery>,
+    ApiJson(trigger): ApiJson<Trigger>,
+) -> Result<Json<Vec<DateTime<Local>>>, ApiError> {
+    let count = query.count()?;
+    let location = state.config.read().unwrap().location;
+    trigger.validate(location).map_err(ApiError::BadRequest)?;
+
+    Ok(Json(trigger.preview(Local::now(), location, count)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::{Europe::Berlin, Tz};
+
+    fn berlin(time: &str) -> DateTime<Tz> {
+        let time = chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap();
+        Berlin.from_local_datetime(&time).earliest().unwrap()
+    }
+
+    fn cron(expression: &str) -> Trigger {
+        Trigger::Cron {
+            expression: expression.to_string(),
+        }
+    }
+
+    fn weekdays(trigger: &Trigger) -> Vec<Weekday> {
+        // 2024-06-03 is a Monday.
+        let times = trigger.preview(berlin("2024-06-02 12:00"), None, 7);
+        times.iter().map(|time| time.weekday()).collect()
+    }
+
+    #[test]
+    fn numbers_crontab_weekdays_from_sunday_as_0() {
+        use Weekday::*;
+        assert_eq!(weekdays(&cron("0 9 * * 1")), [Mon; 7]);
+        assert_eq!(weekdays(&cron("0 9 * * 0")), [Sun; 7]);
+        assert_eq!(weekdays(&cron("0 9 * * 7")), [Sun; 7]);
+        assert_eq!(weekdays(&cron("0 9 * * 6"))[0], Sat);
+        assert_eq!(
+            weekdays(&cron("0 9 * * 1-5")),
+            [Mon, Tue, Wed, Thu, Fri, Mon, Tue]
+        );
+        assert_eq!(
+            weekdays(&cron("0 9 * * 5-7")),
+            [Fri, Sat, Sun, Fri, Sat, Sun, Fri]
+        );
+        assert_eq!(
+            weekdays(&cron("0 9 * * 0,3")),
+            [Wed, Sun, Wed, Sun, Wed, Sun, Wed]
+        );
+        assert_eq!(
+            weekdays(&cron("0 9 * * 0-7/2")),
+            [Tue, Thu, Sat, Sun, Tue, Thu, Sat]
+        );
+        assert_eq!(
+            weekdays(&cron("0 9 * * 3-7/2")),
+            [Wed, Fri, Sun, Wed, Fri, Sun, Wed]
+        );
+        assert_eq!(
+            weekdays(&cron("0 9 * * */3")),
+            [Wed, Sat, Sun, Wed, Sat, Sun, Wed]
+        );
+        assert_eq!(
+            weekdays(&cron("0 9 * * 4/2,1")),
+            [Mon, Thu, Sat, Mon, Thu, Sat, Mon]
+        );
+        assert_eq!(weekdays(&cron("0 9 * * MON")), [Mon; 7]);
+        assert_eq!(
+            weekdays(&cron("0 9 * * MON-WED,0")),
+            [Mon, Tue, Wed, Sun, Mon, Tue, Wed]
+        );
+        // Six fields are passed to the crate as they are, where Sunday is 1.
+        assert_eq!(weekdays(&cron("0 0 9 * * 1")), [Sun; 7]);
+    }
+
+    #[test]
+    fn rejects_invalid_weekdays() {
+        for weekdays in ["8", "1-9", "5-3", "*/0", "1/x", "1,,2"] {
+            let expression = format!("0 9 * * {}", weekdays);
+            assert!(cron(&expression).validate(None).is_err(), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn does_not_reuse_ids_of_deleted_schedules() {
+        let path = std::env::temp_dir().join(format!("gatt-schedules-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let schedule: Schedule = serde_json::from_value(serde_json::json!({
+            "trigger": {"type": "cron", "expression": "0 9 * * 1"},
+            "devices": [],
+            "actions": [{"type": "power", "on": true}],
+        }))
+        .unwrap();
+
+        let schedules = Schedules::load(path.clone());
+        assert_eq!(schedules.create(schedule.clone()).unwrap(), 1);
+        assert_eq!(schedules.create(schedule.clone()).unwrap(), 2);
+        schedules.change(|schedules| schedules.remove(&2)).unwrap();
+        assert_eq!(schedules.create(schedule.clone()).unwrap(), 3);
+        schedules.change(|schedules| schedules.remove(&3)).unwrap();
+
+        let schedules = Schedules::load(path.clone());
+        assert_eq!(schedules.create(schedule.clone()).unwrap(), 4);
+        let ids = schedules
+            .list()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [1, 4]);
+
+        // Files written before ids were counted continue after the highest id.
+        let legacy = serde_json::json!({ "7": schedule });
+        fs::write(&path, legacy.to_string()).unwrap();
+        let schedules = Schedules::load(path.clone());
+        assert_eq!(schedules.create(schedule).unwrap(), 8);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn time_skips_and_keeps_once_across_clock_changes() {
+        let trigger = Trigger::Time {
+            at: NaiveTime::from_hms_opt(2, 30, 0).unwrap(),
+            days: Vec::new(),
+        };
+        // Clocks go forward from 02:00 to 03:00 on 2024-03-31, so that day has no 02:30.
+        let spring = trigger.preview(berlin("2024-03-30 12:00"), None, 2);
+        assert_eq!(
+            spring,
+            [berlin("2024-04-01 02:30"), berlin("2024-04-02 02:30")]
+        );
+        // Clocks go back from 03:00 to 02:00 on 2024-10-27, so that day has 02:30 twice.
+        let autumn = trigger.preview(berlin("2024-10-26 12:00"), None, 2);
+        assert_eq!(
+            autumn,
+            [berlin("2024-10-27 02:30"), berlin("2024-10-28 02:30")]
+        );
+        assert_eq!(autumn[0].to_rfc3339(), "2024-10-27T02:30:00+02:00");
+    }
+
+    #[test]
+    fn time_fires_on_its_days_only() {
+        let trigger = Trigger::Time {
+            at: NaiveTime::from_hms_opt(7, 0, 0).unwrap(),
+            days: vec![Weekday::Mon, Weekday::Fri],
+        };
+        let times = trigger.preview(berlin("2024-06-03 07:00"), None, 3);
+        assert_eq!(
+            times,
+            [
+                berlin("2024-06-07 07:00"),
+                berlin("2024-06-10 07:00"),
+                berlin("2024-06-14 07:00")
+            ]
+        );
+    }
+
+    #[test]
+    fn sun_fires_at_offset_sunset_in_order() {
+        let location = Location {
+            latitude: 52.52,
+            longitude: 13.405,
+        };
+        let trigger = Trigger::Sun {
+            event: SunEvent::Sunset,
+            offset_minutes: -30,
+        };
+        let times = trigger.preview(berlin("2024-06-21 12:00"), Some(location), 3);
+        assert_eq!(times.len(), 3);
+        // Sunset is at about 21:33 around midsummer.
+        for (day, time) in (21..).zip(&times) {
+            assert_eq!(time.date_naive().day(), day);
+            let minutes = (time.naive_local()
+                - berlin(&format!("2024-06-{} 21:03", day)).naive_local())
+            .num_minutes();
+            assert!(
+                minutes.abs() <= 2,
+                "{} is not half an hour before sunset",
+                time
+            );
+        }
+        assert!(trigger
+            .preview(berlin("2024-06-21 12:00"), None, 3)
+            .is_empty());
+    }
+
+    #[test]
+    fn sun_skips_the_polar_night() {
+        let tromso = Location {
+            latitude: 69.65,
+            longitude: 18.96,
+        };
+        let trigger = Trigger::Sun {
+            event: SunEvent::Sunrise,
+            offset_minutes: 0,
+        };
+        // Polar night ends in mid-January.
+        let times = trigger.preview(berlin("2024-12-01 12:00"), Some(tromso), 2);
+        assert_eq!(times.len(), 2);
+        assert_eq!(times[0].month(), 1);
+        assert!(times[0] < times[1]);
+    }
+}
@@ -0,0 +1,671 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use bluer::Address;
+use devices::{govee::protocol::Mode, DeviceState, LedDevice};
+use futures::future::join_all;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, io, path::PathBuf, sync::Mutex, time::Duration};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    error::ApiError,
+    groups::{self, MemberResult},
+    queue,
+    store::write_json,
+    v2::{ApiJson, ApiQuery},
+    GlobalState,
+};
+
+/// What a snapshot brings a device back to, `None` where it is left as it is.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SnapshotState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    power: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    brightness: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mode: Option<Mode>,
+}
+
+impl From<DeviceState> for SnapshotState {
+    fn from(state: DeviceState) -> Self {
+        Self {
+            power: state.power,
+            brightness: state.brightness,
+            mode: state.mode,
+        }
+    }
+}
+
+impl SnapshotState {
+    fn events(&self) -> Vec<devices::Event> {
+        DeviceState {
+            connected: false,
+            power: self.power,
+            brightness: self.brightness,
+            mode: self.mode.clone(),
+        }
+        .events()
+    }
+}
+
+/// A named state of several devices, the format snapshots are exported and imported in.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Snapshot {
+    #[schema(example = "movie night")]
+    name: String,
+    /// By Bluetooth address.
+    devices: BTreeMap<Address, SnapshotState>,
+}
+
+/// The snapshots by name, persisted as JSON so they survive restarts.
+#[derive(Debug)]
+pub struct Snapshots {
+    path: PathBuf,
+    snapshots: Mutex<BTreeMap<String, BTreeMap<Address, SnapshotState>>>,
+}
+
+impl Snapshots {
+    pub fn load(path: PathBuf) -> Self {
+        let snapshots = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring invalid snapshots file {}: {}", path.display(), e);
+                BTreeMap::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => {
+                warn!("Failed to read snapshots file {}: {}", path.display(), e);
+                BTreeMap::new()
+            }
+        };
+
+        Self {
+            path,
+            snapshots: Mutex::new(snapshots),
+        }
+    }
+
+    fn list(&self) -> Vec<Snapshot> {
+        let snapshots = self.snapshots.lock().unwrap();
+        snapshots
+            .iter()
+            .map(|(name, devices)| Snapshot {
+                name: name.clone(),
+                devices: devices.clone(),
+            })
+            .collect()
+    }
+
+    fn get(&self, name: &str) -> Option<Snapshot> {
+        let snapshots = self.snapshots.lock().unwrap();
+        snapshots.get(name).map(|devices| Snapshot {
+            name: name.to_string(),
+            devices: devices.clone(),
+        })
+    }
+
+    /// Applies `f` to a copy of the snapshots, keeping the change only once it is saved.
+    fn change<R>(
+        &self,
+        f: impl FnOnce(&mut BTreeMap<String, BTreeMap<Address, SnapshotState>>) -> R,
+    ) -> io::Result<R> {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let mut updated = snapshots.clone();
+        let result = f(&mut updated);
+
+        write_json(&self.path, &updated)?;
+        *snapshots = updated;
+        Ok(result)
+    }
+}
+
+/// Routes under `/api/v2`. Named snapshots rather than scenes, which are the effects built into
+/// Govee strips.
+pub fn router() -> Router<GlobalState> {
+    Router::new()
+        .route("/snapshots", get(list_snapshots))
+        .route("/snapshots/import", post(import_snapshots))
+        .route(
+            "/snapshots/:name",
+            get(get_snapshot)
+                .put(capture_snapshot)
+                .delete(delete_snapshot),
+        )
+        .route("/snapshots/:name/recall", post(recall_snapshot))
+}
+
+fn snapshot_not_found(name: &str) -> ApiError {
+    ApiError::NotFound(format!("Snapshot {:?} does not exist", name))
+}
+
+fn save_failed(e: io::Error) -> ApiError {
+    ApiError::Internal(format!("Failed to save snapshots: {}", e))
+}
+
+fn validate_name(name: &str) -> Result<(), ApiError> {
+    match name.trim().is_empty() {
+        true => Err(ApiError::BadRequest(
+            "Snapshot name must not be empty".to_string(),
+        )),
+        false => Ok(()),
+    }
+}
+
+/// Rejects snapshots without devices or with devices that are not registered.
+fn validate_devices<'a>(
+    state: &GlobalState,
+    mut devices: impl ExactSizeIterator<Item = &'a Address>,
+) -> Result<(), ApiError> {
+    if devices.len() == 0 {
+        return Err(ApiError::BadRequest(
+            "devices must not be empty".to_string(),
+        ));
+    }
+    match devices.find(|addr| !state.devices.contains(addr)) {
+        Some(addr) => Err(ApiError::device_not_found(*addr)),
+        None => Ok(()),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/snapshots",
+    responses((status = 200, description = "Every snapshot, in the format import takes", body = [Snapshot]))
+)]
+pub async fn list_snapshots(State(state): State<GlobalState>) -> Json<Vec<Snapshot>> {
+    Json(state.snapshots.list())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/snapshots/{name}",
+    params(("name" = String, Path, description = "Name of the snapshot")),
+    responses(
+        (status = 200, body = Snapshot),
+        (status = 404, description = "Snapshot does not exist", body = ErrorBody)
+    )
+)]
+pub async fn get_snapshot(
+    Path(name): Path<String>,
+    State(state): State<GlobalState>,
+) -> Result<Json<Snapshot>, ApiError> {
+    let snapshot = state
+        .snapshots
+        .get(&name)
+        .ok_or_else(|| snapshot_not_found(&name))?;
+    Ok(Json(snapshot))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CaptureRequest {
+    /// Bluetooth addresses of the devices to capture.
+    #[schema(value_type = Vec<String>, example = json!(["A4:C1:38:EC:91:32"]))]
+    devices: Vec<Address>,
+}
+
+/// The state of `addr` as the strip reports it while connected, falling back to the state recorded
+/// from the commands sent to it for whatever the strip can not answer.
+async fn current_state(state: &GlobalState, addr: Address) -> SnapshotState {
+    let recorded = state.store.get(&addr).unwrap_or_default();
+    let live = match state.devices.get_entry(&addr) {
+        Some(entry) => entry.device.lock().await.state().await.ok(),
+        None => None,
+    };
+    let live = live.filter(|live| live.connected).unwrap_or_default();
+
+    SnapshotState {
+        power: live.power.or(recorded.power),
+        brightness: live.brightness.or(recorded.brightness),
+        mode: live.mode.or(recorded.mode),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v2/snapshots/{name}",
+    params(("name" = String, Path, description = "Name of the snapshot")),
+    request_body = CaptureRequest,
+    responses(
+        (status = 200, description = "Snapshot replaced", body = Snapshot),
+        (status = 201, description = "Snapshot created", body = Snapshot),
+        (status = 400, description = "Invalid name or no devices", body = ErrorBody),
+        (status = 404, description = "A device is not registered", body = ErrorBody),
+        (status = 409, description = "The state of a device is not known yet", body = ErrorBody)
+    )
+)]
+pub async fn capture_snapshot(
+    Path(name): Path<String>,
+    State(state): State<GlobalState>,
+    ApiJson(input): ApiJson<CaptureRequest>,
+) -> Result<(StatusCode, Json<Snapshot>), ApiError> {
+    validate_name(&name)?;
+    validate_devices(&state, input.devices.iter())?;
+
+    let captures = input
+        .devices
+        .iter()
+        .map(|addr| current_state(&state, *addr));
+    let captured = join_all(captures).await;
+    let mut devices = BTreeMap::new();
+    for (addr, captured) in input.devices.into_iter().zip(captured) {
+        if captured == SnapshotState::default() {
+            return Err(ApiError::Conflict(format!(
+                "Nothing is known about the state of {} yet",
+                addr
+            )));
+        }
+        devices.insert(addr, captured);
+    }
+
+    let saved = devices.clone();
+    let replaced = state
+        .snapshots
+        .change(|snapshots| snapshots.insert(name.clone(), saved).is_some())
+        .map_err(save_failed)?;
+    info!("Captured snapshot {:?} of {} devices", name, devices.len());
+
+    let status = match replaced {
+        true => StatusCode::OK,
+        false => StatusCode::CREATED,
+    };
+    Ok((status, Json(Snapshot { name, devices })))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v2/snapshots/{name}",
+    params(("name" = String, Path, description = "Name of the snapshot")),
+    responses(
+        (status = 204, description = "Snapshot deleted"),
+        (status = 404, description = "Snapshot does not exist", body = ErrorBody)
+    )
+)]
+pub async fn delete_snapshot(
+    Path(name): Path<String>,
+    State(state): State<GlobalState>,
+) -> Result<StatusCode, ApiError> {
+    let removed = state
+        .snapshots
+        .change(|snapshots| snapshots.remove(&name).is_some())
+        .map_err(save_failed)?;
+    match removed {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(snapshot_not_found(&name)),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/snapshots/import",
+    request_body = [Snapshot],
+    responses(
+        (status = 200, description = "Names of the imported snapshots, which replace those of the same name", body = [String]),
+        (status = 400, description = "Invalid snapshot", body = ErrorBody),
+        (status = 404, description = "A device is not registered", body = ErrorBody)
+    )
+)]
+pub async fn import_snapshots(
+    State(state): State<GlobalState>,
+    ApiJson(imported): ApiJson<Vec<Snapshot>>,
+) -> Result<Json<Vec<String>>, ApiError> {
+    for snapshot in &imported {
+        validate_name(&snapshot.name)?;
+        validate_devices(&state, snapshot.devices.keys())?;
+    }
+
+    let names = imported
+        .iter()
+        .map(|snapshot| snapshot.name.clone())
+        .collect::<Vec<_>>();
+    state
+        .snapshots
+        .change(|snapshots| {
+            for snapshot in imported {
+                snapshots.insert(snapshot.name, snapshot.devices);
+            }
+        })
+        .map_err(save_failed)?;
+
+    info!("Imported {} snapshots", names.len());
+    Ok(Json(names))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct RecallQuery {
+    /// Fades colors and brightness over this many milliseconds.
+    transition_ms: Option<u64>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/snapshots/{name}/recall",
+    params(("name" = String, Path, description = "Name of the snapshot"), RecallQuery),
+    responses(
+        (status = 200, description = "Every device was brought back to the snapshot", body = [MemberResult]),
+        (status = 207, description = "Some devices failed", body = [MemberResult]),
+        (status = 404, description = "Snapshot does not exist", body = ErrorBody)
+    )
+)]
+pub async fn recall_snapshot(
+    Path(name): Path<String>,
+    State(state): State<GlobalState>,
+    ApiQuery(query): ApiQuery<RecallQuery>,
+) -> Result<(StatusCode, Json<Vec<MemberResult>>), ApiError> {
+    let snapshot = state
+        .snapshots
+        .get(&name)
+        .ok_or_else(|| snapshot_not_found(&name))?;
+    let transition = query.transition_ms.map(Duration::from_millis);
+    info!("Recalling snapshot {:?}", name);
+
+    // All devices at once, color and brightness of one device fading together.
+    let recalls = snapshot.devices.iter().map(|(addr, recalled)| {
+        let state = &state;
+        async move {
+            let Some(entry) = state.devices.get_entry(addr) else {
+                return MemberResult::new(*addr, Err(ApiError::device_not_found(*addr)));
+            };
+            let sent = queue::send_all(state, entry, recalled.events(), transition).await;
+            MemberResult::new(*addr, sent.map_err(ApiError::from))
+        }
+    });
+    let results = join_all(recalls).await;
+    Ok((groups::status(&results), Json(results)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use devices::{govee::protocol::checksum, Color, Event};
+
+    const DEVICES: &str = r#"
+        [[devices]]
+        kind = "govee"
+        address = "A4:C1:38:EC:91:32"
+        name = "Desk"
+
+        [[devices]]
+        kind = "govee"
+        address = "A4:C1:38:EC:91:33"
+        name = "Shelf"
+    "#;
+
+    fn addresses() -> (Address, Address) {
+        (
+            "A4:C1:38:EC:91:32".parse().unwrap(),
+            "A4:C1:38:EC:91:33".parse().unwrap(),
+        )
+    }
+
+    fn frame(prefix: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0; 20];
+        frame[..prefix.len()].copy_from_slice(prefix);
+        frame[19] = checksum(&frame[..19]);
+        frame
+    }
+
+    /// The command frames written to the strip, without keep-alives and state queries.
+    fn commands(mock: &devices::transport::MockTransport) -> Vec<Vec<u8>> {
+        mock.take_frames()
+            .into_iter()
+            .filter(|frame| frame[0] != 0xAA)
+            .collect()
+    }
+
+    async fn capture(
+        state: &GlobalState,
+        name: &str,
+        devices: Vec<Address>,
+    ) -> Result<(StatusCode, Snapshot), ApiError> {
+        let input = ApiJson(CaptureRequest { devices });
+        let (status, Json(snapshot)) =
+            capture_snapshot(Path(name.to_string()), State(state.clone()), input).await?;
+        Ok((status, snapshot))
+    }
+
+    async fn recall(
+        state: &GlobalState,
+        name: &str,
+        transition_ms: Option<u64>,
+    ) -> Result<(StatusCode, serde_json::Value), ApiError> {
+        let query = ApiQuery(RecallQuery { transition_ms });
+        let (status, Json(results)) =
+            recall_snapshot(Path(name.to_string()), State(state.clone()), query).await?;
+        Ok((status, serde_json::to_value(results).unwrap()))
+    }
+
+    fn snapshot(name: &str, addr: Address, captured: SnapshotState) -> Snapshot {
+        Snapshot {
+            name: name.to_string(),
+            devices: BTreeMap::from([(addr, captured)]),
+        }
+    }
+
+    #[tokio::test]
+    async fn captures_what_connected_strips_answer_and_recorded_state_otherwise() {
+        let state = GlobalState::for_tests("snapshots-capture", DEVICES).await;
+        let (desk, shelf) = addresses();
+        let mock = state.attach_mock(desk).await;
+        // Commands sent earlier no longer match what the strip shows.
+        state.store.record(desk, &Event::Off);
+        state.store.record(shelf, &Event::On);
+        state.store.record(shelf, &Event::Brightness(10));
+
+        let strip = mock.clone();
+        let mode_query = frame(&[0xAA, 0x05]);
+        tokio::spawn(async move {
+            while !strip.frames().contains(&mode_query) {
+                tokio::task::yield_now().await;
+            }
+            strip.inject_notification(frame(&[0xAA, 0x01, 0x01]));
+            strip.inject_notification(frame(&[0xAA, 0x04, 0x40]));
+            strip.inject_notification(frame(&[0xAA, 0x05, 0x02, 0x12, 0x34, 0x56]));
+        });
+
+        let (status, captured) = capture(&state, "evening", vec![desk, shelf]).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(
+            captured.devices[&desk],
+            SnapshotState {
+                power: Some(true),
+                brightness: Some(0x40),
+                mode: Some(Mode::Color(Color::new(0x12, 0x34, 0x56))),
+            }
+        );
+        assert_eq!(
+            captured.devices[&shelf],
+            SnapshotState {
+                power: Some(true),
+                brightness: Some(10),
+                mode: None,
+            }
+        );
+        assert_eq!(
+            state.snapshots.get("evening").unwrap().devices,
+            captured.devices
+        );
+
+        let (status, _) = capture(&state, "evening", vec![shelf]).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            state.snapshots.get("evening").unwrap().devices.len(),
+            1,
+            "replaced snapshot kept the devices it no longer captures"
+        );
+    }
+
+    #[tokio::test]
+    async fn capture_rejects_unknown_devices_and_unknown_state() {
+        let state = GlobalState::for_tests("snapshots-capture-errors", DEVICES).await;
+        let (_, shelf) = addresses();
+        let unknown = "A4:C1:38:EC:91:34".parse().unwrap();
+
+        let cases = [
+            ("evening", vec![unknown], StatusCode::NOT_FOUND),
+            ("evening", vec![shelf], StatusCode::CONFLICT),
+            ("evening", vec![], StatusCode::BAD_REQUEST),
+            ("", vec![shelf], StatusCode::BAD_REQUEST),
+        ];
+        for (name, devices, expected) in cases {
+            let e = capture(&state, name, devices.clone()).await.unwrap_err();
+            assert_eq!(e.status(), expected, "{:?} of {:?}", name, devices);
+        }
+        assert!(state.snapshots.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn imports_exports_and_deletes_snapshots() {
+        let state = GlobalState::for_tests("snapshots-import", DEVICES).await;
+        let (desk, shelf) = addresses();
+        let off = SnapshotState {
+            power: Some(false),
+            ..Default::default()
+        };
+        let imported = vec![
+            snapshot("night", desk, off.clone()),
+            snapshot("away", shelf, off.clone()),
+        ];
+
+        let Json(names) = import_snapshots(State(state.clone()), ApiJson(imported))
+            .await
+            .unwrap();
+        assert_eq!(names, ["night", "away"]);
+        let Json(exported) = list_snapshots(State(state.clone())).await;
+        let exported = exported
+            .into_iter()
+            .map(|snapshot| snapshot.name)
+            .collect::<Vec<_>>();
+        assert_eq!(exported, ["away", "night"]);
+        let Json(night) = get_snapshot(Path("night".to_string()), State(state.clone()))
+            .await
+            .unwrap();
+        assert_eq!(night.devices, BTreeMap::from([(desk, off.clone())]));
+
+        let unknown = "A4:C1:38:EC:91:34".parse().unwrap();
+        let e = import_snapshots(
+            State(state.clone()),
+            ApiJson(vec![snapshot("hall", unknown, off)]),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(e.status(), StatusCode::NOT_FOUND);
+        assert!(state.snapshots.get("hall").is_none());
+
+        let deleted = delete_snapshot(Path("night".to_string()), State(state.clone())).await;
+        assert_eq!(deleted.unwrap(), StatusCode::NO_CONTENT);
+        for name in ["night", "hall"] {
+            let e = get_snapshot(Path(name.to_string()), State(state.clone()))
+                .await
+                .unwrap_err();
+            assert_eq!(e.status(), StatusCode::NOT_FOUND);
+            let e = delete_snapshot(Path(name.to_string()), State(state.clone()))
+                .await
+                .unwrap_err();
+            assert_eq!(e.status(), StatusCode::NOT_FOUND);
+        }
+
+        // Survives a restart.
+        let reloaded = Snapshots::load(state.snapshots.path.clone());
+        assert_eq!(reloaded.list().len(), 1);
+        assert!(reloaded.get("away").is_some());
+    }
+
+    #[tokio::test]
+    async fn recall_sends_the_captured_state() {
+        let state = GlobalState::for_tests("snapshots-recall", DEVICES).await;
+        let (desk, _) = addresses();
+        let mock = state.attach_mock(desk).await;
+        let evening = SnapshotState {
+            power: Some(true),
+            brightness: Some(0x40),
+            mode: Some(Mode::Color(Color::new(0x12, 0x34, 0x56))),
+        };
+        state
+            .snapshots
+            .change(|snapshots| {
+                snapshots.insert("evening".to_string(), BTreeMap::from([(desk, evening)]))
+            })
+            .unwrap();
+
+        let (status, results) = recall(&state, "evening", None).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            results,
+            serde_json::json!([{"address": "A4:C1:38:EC:91:32"}])
+        );
+        assert_eq!(
+            commands(&mock),
+            [
+                frame(&[0x33, 0x01, 0x01]),
+                frame(&[0x33, 0x04, 0x40]),
+                frame(&[0x33, 0x05, 0x02, 0x12, 0x34, 0x56]),
+            ]
+        );
+
+        let e = recall(&state, "morning", None).await.unwrap_err();
+        assert_eq!(e.status(), StatusCode::NOT_FOUND);
+        assert!(commands(&mock).is_empty());
+    }
+
+    #[tokio::test]
+    async fn recall_fades_over_the_transition() {
+        let state = GlobalState::for_tests("snapshots-recall-fade", DEVICES).await;
+        let (desk, shelf) = addresses();
+        let mock = state.attach_mock(desk).await;
+        let entry = state.devices.get_entry(&desk).unwrap();
+        queue::send(&state, entry.clone(), Event::Brightness(0x10), None)
+            .await
+            .unwrap();
+        queue::send(&state, entry, Event::Color(Color::new(0, 0, 0)), None)
+            .await
+            .unwrap();
+        mock.clear();
+
+        let evening = SnapshotState {
+            power: Some(true),
+            brightness: Some(0x40),
+            mode: Some(Mode::Color(Color::new(0x12, 0x34, 0x56))),
+        };
+        state
+            .snapshots
+            .change(|snapshots| {
+                let devices = BTreeMap::from([(desk, evening.clone()), (shelf, evening)]);
+                snapshots.insert("evening".to_string(), devices)
+            })
+            .unwrap();
+
+        // Shelf has no connection, desk still fades.
+        let (status, results) = recall(&state, "evening", Some(500)).await.unwrap();
+        assert_eq!(status, StatusCode::MULTI_STATUS);
+        assert!(results[0].get("error").is_none());
+        assert_eq!(results[1]["address"], "A4:C1:38:EC:91:33");
+        assert_eq!(results[1]["error"]["error"], "not_connected");
+
+        let commands = commands(&mock);
+        assert_eq!(commands[0], frame(&[0x33, 0x01, 0x01]));
+        let brightness = commands
+            .iter()
+            .filter(|frame| frame[1] == 0x04)
+            .map(|frame| frame[2])
+            .collect::<Vec<_>>();
+        let colors = commands
+            .iter()
+            .filter(|frame| frame[1..3] == [0x05, 0x02])
+            .map(|frame| (frame[3], frame[4], frame[5]))
+            .collect::<Vec<_>>();
+        assert!(brightness.len() > 2, "brightness jumped: {:?}", brightness);
+        assert!(colors.len() > 2, "color jumped: {:?}", colors);
+        assert!(brightness.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(brightness.iter().all(|b| (0x10..=0x40).contains(b)));
+        assert_eq!(brightness.last(), Some(&0x40));
+        assert_eq!(colors.last(), Some(&(0x12, 0x34, 0x56)));
+    }
+}
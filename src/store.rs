@@ -0,0 +1,113 @@
+use bluer::Address;
+use devices::{DeviceState, Event};
+use log::{error, warn};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{sync::Notify, time};
+
+/// How long `run_writer` gathers changes before it writes them out, so a fade or effect saves
+/// a few times a second instead of on every frame.
+const SAVE_DELAY: Duration = Duration::from_millis(500);
+
+/// Last state applied to every device, persisted as JSON so it survives restarts.
+#[derive(Debug)]
+pub struct StateStore {
+    path: PathBuf,
+    states: Mutex<HashMap<Address, DeviceState>>,
+    changed: Notify,
+}
+
+impl StateStore {
+    pub fn load(path: PathBuf) -> Self {
+        let states = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring invalid state file {}: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                warn!("Failed to read state file {}: {}", path.display(), e);
+                HashMap::new()
+            }
+        };
+
+        Self {
+            path,
+            states: Mutex::new(states),
+            changed: Notify::new(),
+        }
+    }
+
+    pub fn get(&self, addr: &Address) -> Option<DeviceState> {
+        self.states.lock().unwrap().get(addr).cloned()
+    }
+
+    /// Records that `event` was applied to `addr`, `run_writer` saves it shortly after.
+    pub fn record(&self, addr: Address, event: &Event) {
+        let mut states = self.states.lock().unwrap();
+        let state = states.entry(addr).or_default();
+
+        let before = state.clone();
+        state.apply_event(event);
+        if *state != before {
+            self.changed.notify_one();
+        }
+    }
+
+    /// Saves the recorded states whenever they changed, off the async threads.
+    pub async fn run_writer(self: Arc<Self>) {
+        loop {
+            self.changed.notified().await;
+            time::sleep(SAVE_DELAY).await;
+
+            let states = self.states.lock().unwrap().clone();
+            let path = self.path.clone();
+            let written = tokio::task::spawn_blocking(move || write_json(&path, &states))
+                .await
+                .unwrap_or_else(|e| Err(io::Error::other(e)));
+            if let Err(e) = written {
+                error!("Failed to save state to {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+/// Writes `value` to a temporary file first, so a crash never leaves a truncated file behind.
+pub fn write_json<T: serde::Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(value)?)?;
+    fs::rename(tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn writer_saves_recorded_states() {
+        let path = std::env::temp_dir().join(format!("gatt-state-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let addr = Address::new([0xA4, 0xC1, 0x38, 0xEC, 0x91, 0x32]);
+
+        let store = Arc::new(StateStore::load(path.clone()));
+        tokio::spawn(store.clone().run_writer());
+        store.record(addr, &Event::On);
+        store.record(addr, &Event::Brightness(40));
+        assert!(!path.exists(), "saved before SAVE_DELAY");
+
+        time::sleep(SAVE_DELAY * 2).await;
+        let loaded = StateStore::load(path.clone());
+        let state = loaded.get(&addr).unwrap();
+        assert_eq!((state.power, state.brightness), (Some(true), Some(40)));
+        fs::remove_file(path).unwrap();
+    }
+}
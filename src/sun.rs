@@ -0,0 +1,121 @@
+//! Sunrise and sunset times, computed without any network service.
+// https://en.wikipedia.org/wiki/Sunrise_equation
+
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::config::Location;
+
+/// Julian date of 2000-01-01 12:00 UTC.
+const J2000: f64 = 2451545.0;
+/// Julian date of the Unix epoch.
+const UNIX_EPOCH: f64 = 2440587.5;
+/// Added to `num_days_from_ce`, gives the Julian date at midnight starting that day.
+const CE_TO_JULIAN_MIDNIGHT: f64 = 1721424.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SunEvent {
+    Sunrise,
+    Sunset,
+}
+
+/// When the upper edge of the sun crosses the horizon on `date` at `location`, or `None` when
+/// it does not, as in polar days and nights.
+pub fn time(event: SunEvent, date: NaiveDate, location: Location) -> Option<DateTime<Utc>> {
+    let latitude = location.latitude.to_radians();
+
+    let day = (date.num_days_from_ce() as f64 + CE_TO_JULIAN_MIDNIGHT - J2000 + 0.0008).ceil();
+    let mean_solar_noon = day - location.longitude / 360.0;
+
+    let anomaly = (357.5291 + 0.98560028 * mean_solar_noon)
+        .rem_euclid(360.0)
+        .to_radians();
+    let center =
+        1.9148 * anomaly.sin() + 0.02 * (2.0 * anomaly).sin() + 0.0003 * (3.0 * anomaly).sin();
+    let ecliptic_longitude = (anomaly.to_degrees() + center + 180.0 + 102.9372)
+        .rem_euclid(360.0)
+        .to_radians();
+    let transit = J2000 + mean_solar_noon + 0.0053 * anomaly.sin()
+        - 0.0069 * (2.0 * ecliptic_longitude).sin();
+
+    let declination = (ecliptic_longitude.sin() * 23.4397_f64.to_radians().sin()).asin();
+    // -0.833° accounts for refraction and the radius of the sun.
+    let cos_hour_angle = ((-0.833_f64).to_radians().sin() - latitude.sin() * declination.sin())
+        / (latitude.cos() * declination.cos());
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+
+    let julian = match event {
+        SunEvent::Sunrise => transit - hour_angle / 360.0,
+        SunEvent::Sunset => transit + hour_angle / 360.0,
+    };
+    let millis = ((julian - UNIX_EPOCH) * 86_400_000.0).round() as i64;
+    Utc.timestamp_millis_opt(millis).single()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BERLIN: Location = Location {
+        latitude: 52.52,
+        longitude: 13.405,
+    };
+    const NEW_YORK: Location = Location {
+        latitude: 40.7128,
+        longitude: -74.006,
+    };
+    const TROMSO: Location = Location {
+        latitude: 69.65,
+        longitude: 18.96,
+    };
+
+    fn assert_near(time: Option<DateTime<Utc>>, expected: &str) {
+        let expected = DateTime::parse_from_rfc3339(expected).unwrap();
+        let time = time.expect("no sunrise or sunset");
+        let off = (time - expected.with_timezone(&Utc)).num_seconds().abs();
+        assert!(
+            off <= 120,
+            "{} is not within 2 minutes of {}",
+            time,
+            expected
+        );
+    }
+
+    #[test]
+    fn matches_published_times() {
+        let midsummer = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        let midwinter = NaiveDate::from_ymd_opt(2024, 12, 21).unwrap();
+        // Published as 04:43 and 21:33 CEST.
+        assert_near(
+            time(SunEvent::Sunrise, midsummer, BERLIN),
+            "2024-06-21T04:43:00+02:00",
+        );
+        assert_near(
+            time(SunEvent::Sunset, midsummer, BERLIN),
+            "2024-06-21T21:33:00+02:00",
+        );
+        // Published as 07:17 and 16:32 EST, west of Greenwich.
+        assert_near(
+            time(SunEvent::Sunrise, midwinter, NEW_YORK),
+            "2024-12-21T07:17:00-05:00",
+        );
+        assert_near(
+            time(SunEvent::Sunset, midwinter, NEW_YORK),
+            "2024-12-21T16:32:00-05:00",
+        );
+    }
+
+    #[test]
+    fn polar_night_and_day_have_none() {
+        for date in [(2024, 12, 21), (2024, 6, 21)] {
+            let date = NaiveDate::from_ymd_opt(date.0, date.1, date.2).unwrap();
+            assert_eq!(time(SunEvent::Sunrise, date, TROMSO), None);
+            assert_eq!(time(SunEvent::Sunset, date, TROMSO), None);
+        }
+    }
+}
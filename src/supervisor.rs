@@ -0,0 +1,97 @@
+use bluer::Address;
+use devices::{backoff::Backoff, wait_for_disconnect, LedDevice};
+use log::{info, warn};
+use std::{collections::HashMap, sync::Mutex};
+use tokio::{task::JoinHandle, time};
+
+use crate::{events, v2::ConnectionStatus, GlobalState};
+
+/// Reconnects devices that drop their connection, until they are disconnected manually.
+#[derive(Debug, Default)]
+pub struct Supervisor {
+    tasks: Mutex<HashMap<Address, JoinHandle<()>>>,
+}
+
+impl Supervisor {
+    /// Starts watching a device that was just connected.
+    pub fn watch(&self, state: GlobalState, addr: Address) {
+        let task = tokio::spawn(supervise(state, addr));
+        if let Some(previous) = self.tasks.lock().unwrap().insert(addr, task) {
+            previous.abort();
+        }
+    }
+
+    pub fn stop(&self, addr: &Address) {
+        if let Some(task) = self.tasks.lock().unwrap().remove(addr) {
+            task.abort();
+        }
+    }
+}
+
+/// Reconnects the device and publishes the state it reports, until it is dropped.
+async fn supervise(state: GlobalState, addr: Address) {
+    let updates = match state.devices.get_entry(&addr) {
+        Some(entry) => entry.device.lock().await.watch_state(),
+        None => return,
+    };
+    match updates {
+        Some(updates) => {
+            tokio::select! {
+                _ = reconnect(state.clone(), addr) => {}
+                _ = events::forward_state(state, addr, updates) => {}
+            }
+        }
+        None => reconnect(state, addr).await,
+    }
+}
+
+async fn reconnect(state: GlobalState, addr: Address) {
+    loop {
+        let Some(entry) = state.devices.get_entry(&addr) else {
+            return;
+        };
+        let Some(device) = entry.device.lock().await.bluetooth_device() else {
+            return;
+        };
+
+        // A failed watch says nothing about the connection, watch again until it is lost.
+        let mut backoff = Backoff::default();
+        while let Err(e) = wait_for_disconnect(&device).await {
+            let delay = backoff.next_delay();
+            warn!(
+                "Failed to watch connection of {}, retrying in {:?}: {}",
+                addr, delay, e
+            );
+            time::sleep(delay).await;
+        }
+        info!("Lost connection to {}", addr);
+
+        entry.device.lock().await.mark_disconnected();
+        state
+            .events
+            .connection(addr, ConnectionStatus::Disconnected);
+
+        let mut backoff = Backoff::default();
+        loop {
+            let delay = backoff.next_delay();
+            info!(
+                "Reconnecting to {} in {:?} (attempt {})",
+                addr,
+                delay,
+                backoff.attempt()
+            );
+            time::sleep(delay).await;
+
+            let mut device = entry.device.lock().await;
+            match device.connect().await {
+                Ok(()) => {
+                    info!("Reconnected to {}", addr);
+                    state.power_on(&entry.config(), &mut device).await;
+                    state.events.connection(addr, ConnectionStatus::Connected);
+                    break;
+                }
+                Err(e) => warn!("Failed to reconnect to {}: {}", addr, e),
+            }
+        }
+    }
+}
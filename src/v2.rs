@@ -0,0 +1,715 @@
+use async_trait::async_trait;
+use axum::{
+    extract::{rejection::JsonRejection, FromRequest, FromRequestParts, Path, Query, State},
+    http::{request::Parts, Request, StatusCode},
+    routing::{get, post},
+    Json, Router,
+};
+use devices::{
+    effects::Effect,
+    govee::protocol::{Diy, DiyStyle, MusicMode, Scene},
+    Color, DeviceKind, DeviceState, Event, LedDevice,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{
+    effects,
+    error::{parse_address, ApiError, ErrorBody},
+    events::LiveEvent,
+    groups::MemberResult,
+    queue::{self, QueueStats},
+    scheduler, snapshots,
+    sun::SunEvent,
+    Devices, GlobalState, RegisteredDevice,
+};
+
+/// `/api/v2`, where every request and response body is JSON with a fixed schema.
+pub fn router() -> Router<GlobalState> {
+    Router::new()
+        .route("/devices", get(list_devices))
+        .route("/devices/:addr", get(get_device))
+        .route("/devices/:addr/state", get(device_state))
+        .route("/devices/:addr/connect", post(connect))
+        .route("/devices/:addr/disconnect", post(disconnect))
+        .route("/devices/:addr/commands", post(send_command))
+        .route(
+            "/devices/:addr/effect",
+            get(get_effect)
+                .put(start_effect)
+                .patch(update_effect)
+                .delete(stop_effect),
+        )
+        .route("/effects", get(list_effects))
+        .merge(scheduler::router())
+        .merge(snapshots::router())
+        .route("/openapi.json", get(openapi))
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "gatt", description = "Control Bluetooth LE led strips."),
+    paths(
+        list_devices,
+        get_device,
+        device_state,
+        connect,
+        disconnect,
+        send_command,
+        get_effect,
+        start_effect,
+        update_effect,
+        stop_effect,
+        list_effects,
+        scheduler::list_schedules,
+        scheduler::create_schedule,
+        scheduler::get_schedule,
+        scheduler::replace_schedule,
+        scheduler::delete_schedule,
+        scheduler::next_fires,
+        scheduler::preview_schedule,
+        snapshots::list_snapshots,
+        snapshots::get_snapshot,
+        snapshots::capture_snapshot,
+        snapshots::delete_snapshot,
+        snapshots::import_snapshots,
+        snapshots::recall_snapshot,
+        openapi
+    ),
+    components(schemas(
+        DeviceStatus,
+        ConnectionStatus,
+        CommandRequest,
+        CommandResult,
+        ErrorBody,
+        QueueStats,
+        DeviceKind,
+        DeviceState,
+        devices::govee::protocol::Mode,
+        Color,
+        Scene,
+        MusicMode,
+        DiyStyle,
+        Diy,
+        Effect,
+        scheduler::Schedule,
+        scheduler::ScheduleInfo,
+        scheduler::Trigger,
+        SunEvent,
+        snapshots::Snapshot,
+        snapshots::SnapshotState,
+        snapshots::CaptureRequest,
+        MemberResult
+    ))
+)]
+pub struct ApiDoc;
+
+/// Like `Query`, but answers query strings that do not match the schema with an [`ApiError`].
+pub struct ApiQuery<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ApiQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match Query::<T>::from_request_parts(parts, state).await {
+            Ok(Query(value)) => Ok(ApiQuery(value)),
+            Err(rejection) => Err(ApiError::BadRequest(rejection.body_text())),
+        }
+    }
+}
+
+/// Like `Json`, but answers bodies that do not match the schema with an [`ApiError`].
+pub struct ApiJson<T>(pub T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    Json<T>: FromRequest<S, B, Rejection = JsonRejection>,
+    S: Send + Sync,
+    B: Send + 'static,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        match Json::<T>::from_request(req, state).await {
+            Ok(Json(value)) => Ok(ApiJson(value)),
+            Err(rejection) => Err(ApiError::BadRequest(rejection.body_text())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionStatus {
+    Connected,
+    Disconnected,
+    /// An operation is in progress, like connecting.
+    Busy,
+}
+
+impl ConnectionStatus {
+    pub fn of(entry: &RegisteredDevice<Devices>) -> Self {
+        // Waiting for the device would stall listings behind a slow connect.
+        match entry.device.try_lock() {
+            Ok(device) if device.bluetooth_device().is_some() => ConnectionStatus::Connected,
+            Ok(_) => ConnectionStatus::Disconnected,
+            Err(_) => ConnectionStatus::Busy,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeviceStatus {
+    #[schema(example = "A4:C1:38:EC:91:32")]
+    address: String,
+    name: String,
+    room: Option<String>,
+    kind: DeviceKind,
+    status: ConnectionStatus,
+    queue: QueueStats,
+}
+
+impl DeviceStatus {
+    fn new(entry: &RegisteredDevice<Devices>) -> Self {
+        let config = entry.config();
+        Self {
+            address: config.address.to_string(),
+            name: config.name,
+            room: config.room,
+            kind: config.kind,
+            status: ConnectionStatus::of(entry),
+            queue: entry.queue.stats(),
+        }
+    }
+}
+
+/// Something to show on a strip.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum CommandRequest {
+    Power {
+        on: bool,
+    },
+    Brightness {
+        brightness: u8,
+        /// Fades to the brightness over this many milliseconds.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        transition_ms: Option<u64>,
+    },
+    Color {
+        color: Color,
+        /// Fades to the color over this many milliseconds.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        transition_ms: Option<u64>,
+    },
+    Scene {
+        scene: Scene,
+    },
+    Music {
+        mode: MusicMode,
+    },
+    Diy {
+        style: DiyStyle,
+        #[serde(default)]
+        speed: u8,
+        colors: Vec<Color>,
+    },
+    /// Power, color and brightness at once, fading color and brightness together.
+    Set {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        on: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        color: Option<Color>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        brightness: Option<u8>,
+        /// Fades to the color and brightness over this many milliseconds.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        transition_ms: Option<u64>,
+    },
+}
+
+impl CommandRequest {
+    /// The events to send and how long to fade to them, an error when there are none.
+    pub fn into_events(self) -> Result<(Vec<Event>, Option<Duration>), ApiError> {
+        let event = match self {
+            CommandRequest::Power { on: true } => Event::On,
+            CommandRequest::Power { on: false } => Event::Off,
+            CommandRequest::Brightness {
+                brightness,
+                transition_ms,
+            } => {
+                let transition = transition_ms.map(Duration::from_millis);
+                return Ok((vec![Event::Brightness(brightness)], transition));
+            }
+            CommandRequest::Color {
+                color,
+                transition_ms,
+            } => {
+                return Ok((
+                    vec![Event::Color(color)],
+                    transition_ms.map(Duration::from_millis),
+                ))
+            }
+            CommandRequest::Scene { scene } => Event::Scene(scene),
+            CommandRequest::Music { mode } => Event::Music(mode),
+            CommandRequest::Diy {
+                style,
+                speed,
+                colors,
+            } => Event::Diy(Diy {
+                style,
+                speed,
+                colors,
+            }),
+            CommandRequest::Set {
+                on,
+                color,
+                brightness,
+                transition_ms,
+            } => {
+                let events = [
+                    on.map(|on| if on { Event::On } else { Event::Off }),
+                    color.map(Event::Color),
+                    brightness.map(Event::Brightness),
+                ];
+                let events = events.into_iter().flatten().collect::<Vec<_>>();
+                if events.is_empty() {
+                    return Err(ApiError::BadRequest(
+                        "Nothing to set, pass on, color or brightness".to_string(),
+                    ));
+                }
+                return Ok((events, transition_ms.map(Duration::from_millis)));
+            }
+        };
+        Ok((vec![event], None))
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CommandResult {
+    #[schema(example = "A4:C1:38:EC:91:32")]
+    address: String,
+    /// The state set through the server so far, including this command.
+    state: DeviceState,
+}
+
+fn entry(state: &GlobalState, addr: &str) -> Result<Arc<RegisteredDevice<Devices>>, ApiError> {
+    let addr = parse_address(addr)?;
+    state
+        .devices
+        .get_entry(&addr)
+        .ok_or(ApiError::device_not_found(addr))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/devices",
+    responses((status = 200, description = "Registered devices, by name", body = [DeviceStatus]))
+)]
+async fn list_devices(State(state): State<GlobalState>) -> Json<Vec<DeviceStatus>> {
+    let mut devices = state
+        .devices
+        .entries()
+        .iter()
+        .map(|entry| DeviceStatus::new(entry))
+        .collect::<Vec<_>>();
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+    Json(devices)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/devices/{addr}",
+    params(("addr" = String, Path, description = "Bluetooth address of the device")),
+    responses(
+        (status = 200, body = DeviceStatus),
+        (status = 400, description = "Invalid address", body = ErrorBody),
+        (status = 404, description = "Device not registered", body = ErrorBody)
+    )
+)]
+async fn get_device(
+    Path(addr): Path<String>,
+    State(state): State<GlobalState>,
+) -> Result<Json<DeviceStatus>, ApiError> {
+    let entry = entry(&state, &addr)?;
+    Ok(Json(DeviceStatus::new(&entry)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/devices/{addr}/state",
+    params(("addr" = String, Path, description = "Bluetooth address of the device")),
+    responses(
+        (status = 200, description = "State read back from the strip where it supports it", body = DeviceState),
+        (status = 400, description = "Invalid address", body = ErrorBody),
+        (status = 404, description = "Device not registered", body = ErrorBody),
+        (status = 502, description = "Bluetooth error", body = ErrorBody)
+    )
+)]
+async fn device_state(
+    Path(addr): Path<String>,
+    State(state): State<GlobalState>,
+) -> Result<Json<DeviceState>, ApiError> {
+    let entry = entry(&state, &addr)?;
+    let device_state = entry.device.lock().await.state().await?;
+    state.events.publish(LiveEvent::State {
+        address: entry.config().address,
+        state: device_state.clone(),
+    });
+    Ok(Json(device_state))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/devices/{addr}/connect",
+    params(("addr" = String, Path, description = "Bluetooth address of the device")),
+    responses(
+        (status = 200, body = DeviceStatus),
+        (status = 400, description = "Invalid address", body = ErrorBody),
+        (status = 404, description = "Device not registered or not discovered", body = ErrorBody),
+        (status = 502, description = "Bluetooth error, or the strip lacks its characteristic", body = ErrorBody),
+        (status = 504, description = "Connecting timed out", body = ErrorBody)
+    )
+)]
+async fn connect(
+    Path(addr): Path<String>,
+    State(state): State<GlobalState>,
+) -> Result<Json<DeviceStatus>, ApiError> {
+    let entry = entry(&state, &addr)?;
+    {
+        let mut device = entry.device.lock().await;
+        device.connect().await?;
+        state.power_on(&entry.config(), &mut device).await;
+    }
+    let addr = entry.config().address;
+    state.events.connection(addr, ConnectionStatus::Connected);
+    state.supervisor.watch(state.clone(), addr);
+    Ok(Json(DeviceStatus::new(&entry)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/devices/{addr}/disconnect",
+    params(("addr" = String, Path, description = "Bluetooth address of the device")),
+    responses(
+        (status = 200, body = DeviceStatus),
+        (status = 400, description = "Invalid address", body = ErrorBody),
+        (status = 404, description = "Device not registered", body = ErrorBody),
+        (status = 502, description = "Bluetooth error", body = ErrorBody)
+    )
+)]
+async fn disconnect(
+    Path(addr): Path<String>,
+    State(state): State<GlobalState>,
+) -> Result<Json<DeviceStatus>, ApiError> {
+    let entry = entry(&state, &addr)?;
+    state.supervisor.stop(&entry.config().address);
+    effects::stop(&state, entry.config().address);
+    entry.device.lock().await.disconnect().await?;
+    state
+        .events
+        .connection(entry.config().address, ConnectionStatus::Disconnected);
+    Ok(Json(DeviceStatus::new(&entry)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/devices/{addr}/commands",
+    params(("addr" = String, Path, description = "Bluetooth address of the device")),
+    request_body = CommandRequest,
+    responses(
+        (status = 200, description = "The command, or a newer one superseding it, was written", body = CommandResult),
+        (status = 400, description = "Invalid address or command", body = ErrorBody),
+        (status = 404, description = "Device not registered", body = ErrorBody),
+        (status = 409, description = "Device not connected, or a newer command stopped the transition (`superseded`)", body = ErrorBody),
+        (status = 422, description = "The strip does not support the command", body = ErrorBody),
+        (status = 502, description = "Bluetooth error", body = ErrorBody)
+    )
+)]
+async fn send_command(
+    Path(addr): Path<String>,
+    State(state): State<GlobalState>,
+    ApiJson(command): ApiJson<CommandRequest>,
+) -> Result<Json<CommandResult>, ApiError> {
+    let entry = entry(&state, &addr)?;
+    let addr = entry.config().address;
+
+    let (events, transition) = command.into_events()?;
+    queue::send_all(&state, entry, events, transition).await?;
+    let recorded = DeviceState {
+        connected: true,
+        ..state.store.get(&addr).unwrap_or_default()
+    };
+    Ok(Json(CommandResult {
+        address: addr.to_string(),
+        state: recorded,
+    }))
+}
+
+fn no_effect(addr: bluer::Address) -> ApiError {
+    ApiError::NotFound(format!("No effect is running on {}", addr))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/devices/{addr}/effect",
+    params(("addr" = String, Path, description = "Bluetooth address of the device")),
+    responses(
+        (status = 200, description = "The effect running on the device", body = Effect),
+        (status = 400, description = "Invalid address", body = ErrorBody),
+        (status = 404, description = "Device not registered or no effect running", body = ErrorBody)
+    )
+)]
+async fn get_effect(
+    Path(addr): Path<String>,
+    State(state): State<GlobalState>,
+) -> Result<Json<Effect>, ApiError> {
+    let addr = entry(&state, &addr)?.config().address;
+    let effect = state.effects.get(&addr).ok_or(no_effect(addr))?;
+    Ok(Json(effect))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v2/devices/{addr}/effect",
+    params(("addr" = String, Path, description = "Bluetooth address of the device")),
+    request_body = Effect,
+    responses(
+        (status = 200, description = "The effect replaced the one running before", body = Effect),
+        (status = 400, description = "Invalid address or effect", body = ErrorBody),
+        (status = 404, description = "Device not registered", body = ErrorBody),
+        (status = 422, description = "Parameters the effect can not run with", body = ErrorBody)
+    )
+)]
+async fn start_effect(
+    Path(addr): Path<String>,
+    State(state): State<GlobalState>,
+    ApiJson(effect): ApiJson<Effect>,
+) -> Result<Json<Effect>, ApiError> {
+    let entry = entry(&state, &addr)?;
+    effects::start(&state, entry, effect.clone())?;
+    Ok(Json(effect))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v2/devices/{addr}/effect",
+    params(("addr" = String, Path, description = "Bluetooth address of the device")),
+    request_body(
+        content = Object,
+        description = "Parameters of the running effect to change, like `{\"period_ms\": 2000}`"
+    ),
+    responses(
+        (status = 200, description = "The effect carries on with the new parameters", body = Effect),
+        (status = 400, description = "Invalid address or parameters", body = ErrorBody),
+        (status = 404, description = "Device not registered or no effect running", body = ErrorBody),
+        (status = 422, description = "Parameters the effect can not run with", body = ErrorBody)
+    )
+)]
+async fn update_effect(
+    Path(addr): Path<String>,
+    State(state): State<GlobalState>,
+    ApiJson(changes): ApiJson<serde_json::Map<String, serde_json::Value>>,
+) -> Result<Json<Effect>, ApiError> {
+    let addr = entry(&state, &addr)?.config().address;
+    let current = state.effects.get(&addr).ok_or(no_effect(addr))?;
+
+    let serde_json::Value::Object(mut params) = serde_json::to_value(&current).unwrap() else {
+        unreachable!("effects serialize to objects");
+    };
+    if changes
+        .get("effect")
+        .is_some_and(|name| params["effect"] != *name)
+    {
+        return Err(ApiError::BadRequest(
+            "Effects can not be switched by changing parameters, start the other effect instead"
+                .to_string(),
+        ));
+    }
+    params.extend(changes);
+    let effect = serde_json::from_value::<Effect>(params.into())
+        .map_err(|e| ApiError::BadRequest(format!("Invalid parameters: {}", e)))?;
+
+    if !effects::update(&state, addr, effect.clone())? {
+        return Err(no_effect(addr));
+    }
+    Ok(Json(effect))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v2/devices/{addr}/effect",
+    params(("addr" = String, Path, description = "Bluetooth address of the device")),
+    responses(
+        (status = 204, description = "The effect stopped, the strip shows its last frame"),
+        (status = 400, description = "Invalid address", body = ErrorBody),
+        (status = 404, description = "Device not registered or no effect running", body = ErrorBody)
+    )
+)]
+async fn stop_effect(
+    Path(addr): Path<String>,
+    State(state): State<GlobalState>,
+) -> Result<StatusCode, ApiError> {
+    let addr = entry(&state, &addr)?.config().address;
+    if !effects::stop(&state, addr) {
+        return Err(no_effect(addr));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/effects",
+    responses((status = 200, description = "Names of the effects the server can animate", body = [String]))
+)]
+async fn list_effects() -> Json<[&'static str; 6]> {
+    Json(Effect::NAMES)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/openapi.json",
+    responses((status = 200, description = "This document"))
+)]
+async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::{Body, HttpBody},
+        http::Method,
+    };
+    use tower::ServiceExt;
+    use utoipa::openapi::PathItemType;
+
+    /// Posts `body` to a route that takes a `CommandRequest`.
+    async fn post_command(body: &str) -> (StatusCode, serde_json::Value) {
+        async fn accept(ApiJson(_): ApiJson<CommandRequest>) -> StatusCode {
+            StatusCode::NO_CONTENT
+        }
+
+        let request = Request::post("/")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = Router::new()
+            .route("/", post(accept))
+            .oneshot(request)
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let mut body = response.into_body();
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.data().await {
+            bytes.extend_from_slice(&chunk.unwrap());
+        }
+        (status, serde_json::from_slice(&bytes).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn accepts_valid_commands() {
+        let (status, _) = post_command(r#"{"type": "power", "on": true}"#).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) =
+            post_command(r##"{"type": "color", "color": "#ff8800", "transition_ms": 500}"##).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+
+    #[test]
+    fn set_combines_power_color_and_brightness() {
+        let command = serde_json::from_str::<CommandRequest>(
+            r##"{"type": "set", "on": true, "color": "#ff0000", "brightness": 40, "transition_ms": 500}"##,
+        )
+        .unwrap();
+        let (events, transition) = command.into_events().unwrap();
+        assert!(matches!(
+            events[..],
+            [Event::On, Event::Color(_), Event::Brightness(40)]
+        ));
+        assert_eq!(transition, Some(Duration::from_millis(500)));
+
+        let empty = serde_json::from_str::<CommandRequest>(r#"{"type": "set"}"#).unwrap();
+        assert!(matches!(empty.into_events(), Err(ApiError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_types_and_fields() {
+        for body in [
+            r#"{"type": "blink", "on": true}"#,
+            r#"{"on": true}"#,
+            r#"{"type": "power", "on": true, "brightness": 10}"#,
+            r#"{"type": "brightness", "brightness": 300}"#,
+            r#"{"type": "power""#,
+        ] {
+            let (status, error) = post_command(body).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+            assert_eq!(error["error"], "bad_request", "{}", body);
+        }
+    }
+
+    /// The methods of the API, as they are in the OpenAPI document.
+    const METHODS: [(PathItemType, Method); 5] = [
+        (PathItemType::Get, Method::GET),
+        (PathItemType::Post, Method::POST),
+        (PathItemType::Put, Method::PUT),
+        (PathItemType::Patch, Method::PATCH),
+        (PathItemType::Delete, Method::DELETE),
+    ];
+
+    #[tokio::test]
+    async fn routes_every_documented_operation() {
+        let state = GlobalState::for_tests("openapi", "").await;
+        // Tells requests that match no route apart from the ones answered by a handler.
+        let app = Router::new()
+            .nest("/api/v2", router())
+            .fallback(|| async { StatusCode::IM_A_TEAPOT })
+            .with_state(state);
+
+        let documented = ApiDoc::openapi().paths.paths;
+        assert!(documented.len() > 10, "{:?}", documented.keys());
+        for (path, item) in documented {
+            let uri = path
+                .replace("{addr}", "A4:C1:38:EC:91:32")
+                .replace("{id}", "1")
+                .replace("{name}", "evening");
+            for (item_type, method) in METHODS {
+                let request = Request::builder()
+                    .method(method.clone())
+                    .uri(&uri)
+                    .header("content-type", "application/json")
+                    .body(Body::from("{}"))
+                    .unwrap();
+                let status = app.clone().oneshot(request).await.unwrap().status();
+
+                assert_ne!(status, StatusCode::IM_A_TEAPOT, "{} is not routed", path);
+                match item.operations.contains_key(&item_type) {
+                    true => assert_ne!(
+                        status,
+                        StatusCode::METHOD_NOT_ALLOWED,
+                        "{} {} is documented but not routed",
+                        method,
+                        path
+                    ),
+                    false => assert_eq!(
+                        status,
+                        StatusCode::METHOD_NOT_ALLOWED,
+                        "{} {} is routed but not documented",
+                        method,
+                        path
+                    ),
+                }
+            }
+        }
+    }
+}